@@ -0,0 +1,270 @@
+mod pak;
+mod st;
+
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use excavator_formats as formats;
+use formats::st::sheet::SheetFormat;
+use formats::vfs::VfsPath;
+
+/// Work with Shovel Knight's data files without the Godot UI.
+///
+/// Files inside archives can be read with paths like `data.pak::strings.stl`, nesting as deep as needed.
+/// Results are printed to stdout as JSON. Errors are printed to stderr, with a non-zero exit code.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+	#[command(subcommand)]
+	command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// `.pak` archives
+	#[command(subcommand)]
+	Pak(PakCommand),
+	/// `.stl`, `.stm` and `.stb` string tables
+	#[command(subcommand)]
+	St(StCommand),
+}
+
+#[derive(Subcommand)]
+enum PakCommand {
+	/// List every file in an archive
+	List {
+		archive: VfsPath,
+	},
+	/// Copy files out of an archive into a directory
+	Extract {
+		archive: VfsPath,
+		destination: PathBuf,
+		/// Only extract these files, instead of all of them
+		#[arg(long = "only", value_name = "NAME")]
+		only: Vec<String>,
+	},
+	/// Build an archive out of every file in a directory
+	Pack {
+		directory: PathBuf,
+		output: PathBuf,
+		/// Start from this archive, keeping its file order and header fields, and replace or add files from the directory
+		#[arg(long, value_name = "ARCHIVE")]
+		base: Option<VfsPath>,
+	},
+}
+
+#[derive(Subcommand)]
+enum StCommand {
+	/// Print a string table as JSON
+	Dump {
+		table: VfsPath,
+		#[command(flatten)]
+		options: TableOptions,
+	},
+	/// Write a string table from JSON made by `st dump`
+	Import {
+		json: PathBuf,
+		output: PathBuf,
+		/// Replace the checksums with ones computed from the strings.
+		/// The checksum algorithm is a guess that hasn't been checked against the game's tables, so the game might reject them
+		#[arg(long)]
+		recompute_checksums: bool,
+	},
+	/// Write a string table as a CSV or TSV spreadsheet, one row per entry
+	ExportSheet {
+		table: VfsPath,
+		sheet: PathBuf,
+		#[command(flatten)]
+		options: TableOptions,
+		/// Separate fields with tabs instead of commas
+		#[arg(long)]
+		tsv: bool,
+	},
+	/// Apply an edited spreadsheet made by `st export-sheet` to a table, and write the result
+	ImportSheet {
+		table: VfsPath,
+		sheet: PathBuf,
+		output: PathBuf,
+		#[command(flatten)]
+		options: TableOptions,
+		/// Fields are separated with tabs instead of commas
+		#[arg(long)]
+		tsv: bool,
+	},
+	/// Write a gettext POT template, or a PO file for one language, from a table whose first row names its fields
+	ExportPo {
+		table: VfsPath,
+		output: PathBuf,
+		#[command(flatten)]
+		options: TableOptions,
+		/// The field to translate from. The second field if not given
+		#[arg(long)]
+		source: Option<String>,
+		/// The field to translate into. A POT template is written if not given
+		#[arg(long)]
+		language: Option<String>,
+	},
+	/// Apply a translated PO file to a table, and write the result. Fuzzy and untranslated entries are reported, not applied
+	ImportPo {
+		table: VfsPath,
+		po: PathBuf,
+		output: PathBuf,
+		#[command(flatten)]
+		options: TableOptions,
+		/// The field that was translated from. The second field if not given
+		#[arg(long)]
+		source: Option<String>,
+		/// The field to write the translations into
+		#[arg(long)]
+		language: String,
+	},
+	/// Search the text of every string table under a directory or archive, including inside archives
+	Search {
+		root: VfsPath,
+		/// Looked for anywhere in each string, ignoring case
+		query: String,
+		/// Treat the query as a regular expression instead. Case matters unless it starts with `(?i)`
+		#[arg(long)]
+		regex: bool,
+		/// How to decode the strings: UTF-8, Shift_JIS or windows-1252
+		#[arg(long, default_value = "UTF-8")]
+		encoding: String,
+	},
+}
+
+#[derive(Args)]
+struct TableOptions {
+	/// Which kind of table this is. Guessed from the contents and extension if not given
+	#[arg(long)]
+	format: Option<StFormat>,
+	/// How to decode the strings: UTF-8, Shift_JIS or windows-1252
+	#[arg(long, default_value = "UTF-8")]
+	encoding: String,
+}
+
+impl TableOptions {
+	fn stl(&self) -> Option<bool> {
+		self.format.map(|f| matches!(f, StFormat::Stl))
+	}
+}
+
+fn sheet_format(tsv: bool) -> SheetFormat {
+	if tsv { SheetFormat::Tsv } else { SheetFormat::Csv }
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum StFormat {
+	Stl,
+	Stm,
+	Stb,
+}
+
+/// Something that went wrong, with a description of what was being done at the time.
+#[derive(Debug)]
+pub struct Failure(String);
+
+impl Failure {
+	pub fn new(context: impl Display, error: impl Display) -> Self {
+		Self(format!("{context}: {error}"))
+	}
+}
+
+pub type CliResult<T = ()> = Result<T, Failure>;
+
+pub fn print_json(value: &serde_json::Value) {
+	println!("{}", serde_json::to_string_pretty(value).expect("JSON values can always be serialized"));
+}
+
+fn main() -> ExitCode {
+	run(Cli::parse().command)
+}
+
+fn run(command: Command) -> ExitCode {
+	let result = match command {
+		Command::Pak(PakCommand::List { archive }) => pak::list(&archive),
+		Command::Pak(PakCommand::Extract { archive, destination, only }) => pak::extract(&archive, &destination, &only),
+		Command::Pak(PakCommand::Pack { directory, output, base }) => pak::pack(&directory, &output, base.as_ref()),
+		Command::St(StCommand::Dump { table, options }) => st::dump(&table, options.stl(), &options.encoding),
+		Command::St(StCommand::Import { json, output, recompute_checksums }) => st::import(&json, &output, recompute_checksums),
+		Command::St(StCommand::ExportSheet { table, sheet, options, tsv }) => {
+			st::export_sheet(&table, options.stl(), &options.encoding, &sheet, sheet_format(tsv))
+		},
+		Command::St(StCommand::ImportSheet { table, sheet, output, options, tsv }) => {
+			st::import_sheet(&table, options.stl(), &options.encoding, &sheet, sheet_format(tsv), &output)
+		},
+		Command::St(StCommand::ExportPo { table, output, options, source, language }) => {
+			st::export_po(&table, options.stl(), &options.encoding, &output, source.as_deref(), language.as_deref())
+		},
+		Command::St(StCommand::ImportPo { table, po, output, options, source, language }) => {
+			st::import_po(&table, options.stl(), &options.encoding, &po, &output, source.as_deref(), &language)
+		},
+		Command::St(StCommand::Search { root, query, regex, encoding }) => st::search(&root, &query, regex, &encoding),
+	};
+	
+	match result {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(Failure(message)) => {
+			eprintln!("error: {message}");
+			ExitCode::FAILURE
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::io::Cursor;
+	
+	use formats::pak::PakWriter;
+	use formats::st::{StEncoding, StString, StTable, write_st};
+	
+	use super::*;
+	
+	fn run_args(args: &[&str]) -> ExitCode {
+		run(Cli::parse_from([&["excavator_cli"], args].concat()).command)
+	}
+	
+	#[test]
+	fn parse_failures_exit_with_failure() {
+		let root = std::env::temp_dir().join(format!("excavator_cli_exit_{}", std::process::id()));
+		let _ = fs::remove_dir_all(&root);
+		fs::create_dir_all(&root).unwrap();
+		
+		let mut pak = PakWriter::new();
+		pak.add_file(c"hello.txt".into(), b"Hello!".to_vec());
+		let mut pak_bytes = Cursor::new(Vec::new());
+		pak.write(&mut pak_bytes).unwrap();
+		let pak_bytes = pak_bytes.into_inner();
+		
+		let text = |t: &str| StString::from_text(t, StEncoding::Utf8).unwrap();
+		let table = StTable {
+			field_count: 2,
+			strings: vec![text("key"), text("english"), text("hello"), text("Hello!")],
+			encoding: StEncoding::Utf8,
+			extended: None,
+			layout: None,
+		};
+		let mut stl_bytes = Cursor::new(Vec::new());
+		write_st(&mut stl_bytes, &table).unwrap();
+		let stl_bytes = stl_bytes.into_inner();
+		
+		let pak_path = root.join("test.pak");
+		let stl_path = root.join("test.stl");
+		let (pak_arg, stl_arg) = (pak_path.to_str().unwrap(), stl_path.to_str().unwrap());
+		fs::write(&pak_path, &pak_bytes).unwrap();
+		fs::write(&stl_path, &stl_bytes).unwrap();
+		assert_eq!(run_args(&["pak", "list", pak_arg]), ExitCode::SUCCESS);
+		assert_eq!(run_args(&["st", "dump", stl_arg]), ExitCode::SUCCESS);
+		
+		fs::write(&pak_path, &pak_bytes[..pak_bytes.len() - 4]).unwrap();
+		fs::write(&stl_path, &stl_bytes[..stl_bytes.len() - 4]).unwrap();
+		assert_eq!(run_args(&["pak", "list", pak_arg]), ExitCode::FAILURE);
+		assert_eq!(run_args(&["st", "dump", stl_arg]), ExitCode::FAILURE);
+		assert_eq!(run_args(&["st", "dump", stl_arg, "--format", "stl"]), ExitCode::FAILURE);
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+}
@@ -0,0 +1,148 @@
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::json;
+
+use crate::formats::pak::{self, PakIndex, PakWriter};
+use crate::formats::vfs::{MountedVfs, Vfs, VfsPath, VfsReader};
+use crate::{CliResult, Failure, print_json};
+
+fn open_index(archive: &VfsPath) -> CliResult<(Arc<PakIndex>, Box<dyn VfsReader>)> {
+	let vfs = MountedVfs::new();
+	let index = vfs.archive_index(archive).map_err(|e| Failure::new(archive, e))?;
+	let reader = vfs.open(archive).map_err(|e| Failure::new(archive, e))?;
+	Ok((index, reader))
+}
+
+pub fn list(archive: &VfsPath) -> CliResult {
+	let (index, _) = open_index(archive)?;
+	let files: Vec<_> = index.files.iter().map(|(name, entry)| json!({
+		"name": name.to_string_lossy(),
+		"size": entry.data_length,
+		"data_start": entry.data_start,
+		"header_offset": entry.header_offset,
+		"name_pointer": entry.name_pointer,
+		"idk1": entry.idk1,
+		"idk2": entry.idk2,
+		"idk3": entry.idk3,
+	})).collect();
+	print_json(&json!({ "files": files }));
+	Ok(())
+}
+
+pub fn extract(archive: &VfsPath, destination: &Path, only: &[String]) -> CliResult {
+	let (index, mut reader) = open_index(archive)?;
+	
+	let report = if only.is_empty() {
+		pak::extract_all(&index, &mut reader, destination)
+	} else {
+		if let Some(missing) = only.iter().find(|wanted| !index.files.iter().any(|f| f.0.to_string_lossy() == **wanted)) {
+			return Err(Failure::new(missing, "no such file in the archive"));
+		}
+		pak::extract_selected(&index, &mut reader, destination, |name| only.iter().any(|wanted| name.to_string_lossy() == *wanted))
+	}.map_err(|e| Failure::new(archive, e))?;
+	
+	let collisions: Vec<_> = report.collisions.iter().map(|collision| json!({
+		"name": collision.name.to_string_lossy(),
+		"path": collision.path,
+	})).collect();
+	print_json(&json!({ "extracted": report.extracted, "collisions": collisions }));
+	Ok(())
+}
+
+/// Lists every file under `directory`, as archive names using `/` as the separator.
+fn collect_files(directory: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> CliResult {
+	let mut entries = fs::read_dir(directory)
+		.and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+		.map_err(|e| Failure::new(directory.display(), e))?;
+	entries.sort_by_key(|entry| entry.file_name());
+	
+	for entry in entries {
+		let file_name = entry.file_name();
+		let file_name = file_name.to_str()
+			.ok_or_else(|| Failure::new(entry.path().display(), "file name isn't valid UTF-8"))?;
+		let name = format!("{prefix}{file_name}");
+		let file_type = entry.file_type().map_err(|e| Failure::new(entry.path().display(), e))?;
+		if file_type.is_dir() {
+			collect_files(&entry.path(), &format!("{name}/"), files)?;
+		} else {
+			files.push((name, entry.path()));
+		}
+	}
+	Ok(())
+}
+
+pub fn pack(directory: &Path, output: &Path, base: Option<&VfsPath>) -> CliResult {
+	let mut writer = match base {
+		Some(base) => {
+			let (index, mut reader) = open_index(base)?;
+			PakWriter::from_index(&index, &mut reader).map_err(|e| Failure::new(base, e))?
+		},
+		None => PakWriter::new(),
+	};
+	
+	let mut files = Vec::new();
+	collect_files(directory, "", &mut files)?;
+	
+	let mut replaced = Vec::new();
+	let mut added = Vec::new();
+	for (name, path) in files {
+		let data = fs::read(&path).map_err(|e| Failure::new(path.display(), e))?;
+		let c_name = CString::new(name.clone()).map_err(|e| Failure::new(path.display(), e))?;
+		if writer.files.iter().any(|f| f.0 == c_name) {
+			writer.replace_file(&c_name, data);
+			replaced.push(name);
+		} else {
+			writer.add_file(c_name, data);
+			added.push(name);
+		}
+	}
+	
+	let file = File::create(output).map_err(|e| Failure::new(output.display(), e))?;
+	let mut file = BufWriter::new(file);
+	writer.write(&mut file).map_err(|e| Failure::new(output.display(), e))?;
+	file.flush().map_err(|e| Failure::new(output.display(), e))?;
+	
+	print_json(&json!({ "replaced": replaced, "added": added, "file_count": writer.files.len() }));
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	/// A fresh, empty directory for a test to write into.
+	fn test_directory(name: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("excavator_cli_{name}_{}", std::process::id()));
+		let _ = fs::remove_dir_all(&path);
+		fs::create_dir_all(&path).unwrap();
+		path
+	}
+	
+	#[test]
+	fn extracting_only_some_files_leaves_existing_ones_alone() {
+		let root = test_directory("extract_only");
+		let mut pak = PakWriter::new();
+		pak.add_file(c"a.txt".into(), b"from the archive".to_vec());
+		pak.add_file(c"b.txt".into(), b"also from the archive".to_vec());
+		pak.add_file(c"c.txt".into(), b"not wanted".to_vec());
+		let mut file = File::create(root.join("test.pak")).unwrap();
+		pak.write(&mut file).unwrap();
+		drop(file);
+		let destination = root.join("out");
+		fs::create_dir_all(&destination).unwrap();
+		fs::write(destination.join("a.txt"), b"already here").unwrap();
+		
+		let archive = VfsPath::disk(root.join("test.pak"));
+		extract(&archive, &destination, &["a.txt".to_string(), "b.txt".to_string()]).unwrap();
+		assert_eq!(fs::read(destination.join("a.txt")).unwrap(), b"already here");
+		assert_eq!(fs::read(destination.join("b.txt")).unwrap(), b"also from the archive");
+		assert!(!destination.join("c.txt").exists());
+		
+		assert!(extract(&archive, &destination, &["missing.txt".to_string()]).is_err());
+		fs::remove_dir_all(root).unwrap();
+	}
+}
@@ -0,0 +1,315 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::formats::FileType;
+use crate::formats::vfs::{MountedVfs, Vfs, VfsPath};
+use crate::formats::st::po::{self, field_by_name};
+use crate::formats::st::search::{StIndex, StQuery};
+use crate::formats::st::sheet::{SheetFormat, export_sheet as export_sheet_to, import_sheet as import_sheet_from};
+use crate::formats::st::{
+	StEncoding, StExtendedData, StExtraEntry, StString, StTable, read_st_with_encoding, recompute_checksums, write_st,
+};
+use crate::{CliResult, Failure, print_json};
+
+fn parse_encoding(name: &str) -> CliResult<StEncoding> {
+	StEncoding::ALL.into_iter()
+		.find(|encoding| encoding.name().eq_ignore_ascii_case(name))
+		.ok_or_else(|| Failure::new(name, "unknown encoding"))
+}
+
+/// Strings are plain JSON strings, unless they couldn't be decoded cleanly.
+/// Then they're objects with the exact bytes in hex, so importing them again doesn't lose anything.
+fn string_to_json(string: &StString) -> Value {
+	if string.is_lossy() {
+		let hex: String = string.raw().iter().map(|byte| format!("{byte:02x}")).collect();
+		json!({ "text": string.text(), "raw": hex })
+	} else {
+		json!(string.text())
+	}
+}
+
+fn string_from_json(value: &Value, encoding: StEncoding) -> Result<StString, String> {
+	match value {
+		Value::String(text) => StString::from_text(text.as_str(), encoding).map_err(|e| e.to_string()),
+		Value::Object(object) => {
+			let hex = object.get("raw").and_then(Value::as_str).ok_or("string object has no \"raw\" field")?;
+			let raw = (0..hex.len()).step_by(2)
+				.map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+				.collect::<Option<Vec<u8>>>()
+				.ok_or_else(|| format!("{hex:?} isn't valid hex"))?;
+			Ok(StString::from_raw(raw, encoding))
+		},
+		other => Err(format!("expected a string, found {other}")),
+	}
+}
+
+fn extra_to_json(section: &[StExtraEntry]) -> Value {
+	section.iter().map(|entry| json!(entry.pieces)).collect()
+}
+
+fn extra_from_json(value: Option<&Value>) -> Result<Vec<StExtraEntry>, String> {
+	let entries = value.and_then(Value::as_array).ok_or("missing extra section")?;
+	entries.iter().map(|entry| {
+		let pieces = entry.as_array().ok_or("extra entry isn't an array")?;
+		let pieces = pieces.iter().map(|piece| {
+			let pair = piece.as_array().filter(|pair| pair.len() == 2).ok_or("extra piece isn't a pair")?;
+			let number = |value: &Value| value.as_u64().and_then(|n| u32::try_from(n).ok()).ok_or("extra piece isn't a u32");
+			Ok((number(&pair[0])?, number(&pair[1])?))
+		}).collect::<Result<_, &str>>()?;
+		Ok(StExtraEntry { pieces })
+	}).collect()
+}
+
+/// `.stm` and `.stb` tables can't be told apart by their contents, so the extension of `path` says which one a table is.
+/// Tables with some other extension are `"stm_or_stb"`.
+pub fn table_to_json(table: &StTable, path: &VfsPath) -> Value {
+	let format = match &table.extended {
+		None => "stl",
+		Some(_) => match Path::new(path.name().as_ref()).extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase()).as_deref() {
+			Some("stm") => "stm",
+			Some("stb") => "stb",
+			_ => "stm_or_stb",
+		},
+	};
+	let rows: Vec<Value> = table.rows().map(|row| row.iter().map(string_to_json).collect()).collect();
+	let mut value = json!({
+		"format": format,
+		"encoding": table.encoding.name(),
+		"field_count": table.field_count,
+		"rows": rows,
+	});
+	if let Some(extended) = &table.extended {
+		value["checksums"] = json!(extended.checksums);
+		value["extra1"] = extra_to_json(&extended.extra1);
+		value["extra2"] = extra_to_json(&extended.extra2);
+	}
+	value
+}
+
+pub fn table_from_json(value: &Value) -> Result<StTable, String> {
+	let encoding = value["encoding"].as_str().ok_or("missing \"encoding\"")?;
+	let encoding = parse_encoding(encoding).map_err(|Failure(message)| message)?;
+	let field_count = value["field_count"].as_u64().ok_or("missing \"field_count\"")?;
+	let field_count = usize::try_from(field_count).map_err(|_| "\"field_count\" is too big")?;
+	let rows = value["rows"].as_array().ok_or("missing \"rows\"")?;
+	
+	// Check the shape of every row first, so the strings can be allocated for without trusting "field_count"
+	let rows = rows.iter().enumerate().map(|(i, row)| {
+		let row = row.as_array().ok_or_else(|| format!("row {i} isn't an array"))?;
+		if row.len() != field_count {
+			return Err(format!("row {i} has {} fields instead of {field_count}", row.len()));
+		}
+		Ok(row)
+	}).collect::<Result<Vec<_>, String>>()?;
+	let string_count = rows.len().checked_mul(field_count).ok_or("there are too many strings")?;
+	let mut strings = Vec::with_capacity(string_count);
+	for (i, row) in rows.into_iter().enumerate() {
+		for string in row {
+			strings.push(string_from_json(string, encoding).map_err(|e| format!("row {i}: {e}"))?);
+		}
+	}
+	
+	let extended = match value["format"].as_str() {
+		Some("stl") => None,
+		Some("stm" | "stb" | "stm_or_stb") => {
+			let checksums = value["checksums"].as_array().ok_or("missing \"checksums\"")?
+				.iter()
+				.map(|checksum| checksum.as_u64().ok_or("checksum isn't a number"))
+				.collect::<Result<_, _>>()?;
+			Some(StExtendedData {
+				checksums,
+				extra1: extra_from_json(value.get("extra1"))?,
+				extra2: extra_from_json(value.get("extra2"))?,
+			})
+		},
+		_ => return Err("\"format\" must be \"stl\", \"stm\", \"stb\" or \"stm_or_stb\"".to_string()),
+	};
+	
+	Ok(StTable { field_count, strings, encoding, extended, layout: None })
+}
+
+fn open_table(path: &VfsPath, stl: Option<bool>, encoding: &str) -> CliResult<StTable> {
+	let encoding = parse_encoding(encoding)?;
+	let mut reader = MountedVfs::new().open(path).map_err(|e| Failure::new(path, e))?;
+	let stl = match stl {
+		Some(stl) => stl,
+		None => match FileType::detect_reader(path.name().as_ref(), &mut reader).map_err(|e| Failure::new(path, e))?.file_type {
+			FileType::Stl => true,
+			FileType::StmOrStb => false,
+			_ => return Err(Failure::new(path, "doesn't look like a string table, use --format")),
+		},
+	};
+	read_st_with_encoding(&mut reader, stl, encoding).map_err(|e| Failure::new(path, e))
+}
+
+fn save_table(output: &Path, table: &StTable) -> CliResult {
+	let file = File::create(output).map_err(|e| Failure::new(output.display(), e))?;
+	let mut writer = BufWriter::new(file);
+	write_st(&mut writer, table).map_err(|e| Failure::new(output.display(), e))?;
+	writer.flush().map_err(|e| Failure::new(output.display(), e))
+}
+
+pub fn dump(path: &VfsPath, stl: Option<bool>, encoding: &str) -> CliResult {
+	let table = open_table(path, stl, encoding)?;
+	print_json(&table_to_json(&table, path));
+	Ok(())
+}
+
+pub fn import(json_path: &Path, output: &Path, recompute: bool) -> CliResult {
+	let file = File::open(json_path).map_err(|e| Failure::new(json_path.display(), e))?;
+	let value: Value = serde_json::from_reader(BufReader::new(file)).map_err(|e| Failure::new(json_path.display(), e))?;
+	let mut table = table_from_json(&value).map_err(|e| Failure::new(json_path.display(), e))?;
+	if recompute {
+		recompute_checksums(&mut table);
+	}
+	
+	save_table(output, &table)?;
+	print_json(&json!({ "strings": table.strings.len(), "rows": table.entry_count() }));
+	Ok(())
+}
+
+pub fn export_sheet(path: &VfsPath, stl: Option<bool>, encoding: &str, sheet_path: &Path, format: SheetFormat) -> CliResult {
+	let table = open_table(path, stl, encoding)?;
+	let file = File::create(sheet_path).map_err(|e| Failure::new(sheet_path.display(), e))?;
+	export_sheet_to(&table, BufWriter::new(file), format).map_err(|e| Failure::new(sheet_path.display(), e))?;
+	print_json(&json!({ "rows": table.entry_count(), "fields": table.field_count }));
+	Ok(())
+}
+
+pub fn import_sheet(
+	path: &VfsPath, stl: Option<bool>, encoding: &str, sheet_path: &Path, format: SheetFormat, output: &Path,
+) -> CliResult {
+	let original = open_table(path, stl, encoding)?;
+	let file = File::open(sheet_path).map_err(|e| Failure::new(sheet_path.display(), e))?;
+	let table = import_sheet_from(&original, BufReader::new(file), format).map_err(|e| Failure::new(sheet_path.display(), e))?;
+	save_table(output, &table)?;
+	
+	let changed = original.strings.iter().zip(&table.strings).filter(|(old, new)| old != new).count();
+	print_json(&json!({ "rows": table.entry_count(), "changed_strings": changed }));
+	Ok(())
+}
+
+/// Finds a field by name, or the second field if no name is given, since the first one holds the keys.
+fn find_field(table: &StTable, name: Option<&str>) -> CliResult<usize> {
+	match name {
+		Some(name) => field_by_name(table, name).ok_or_else(|| {
+			let names: Vec<&str> = table.rows().next().unwrap_or_default().iter().map(StString::text).collect();
+			Failure::new(name, format!("no such field, the table has {names:?}"))
+		}),
+		None if table.field_count >= 2 => Ok(1),
+		None => Err(Failure::new("--source", "the table only has one field, so there's nothing to translate")),
+	}
+}
+
+pub fn export_po(
+	path: &VfsPath, stl: Option<bool>, encoding: &str, output: &Path, source: Option<&str>, language: Option<&str>,
+) -> CliResult {
+	let table = open_table(path, stl, encoding)?;
+	let source_field = find_field(&table, source)?;
+	let target_field = language.map(|name| find_field(&table, Some(name))).transpose()?;
+	
+	let file = File::create(output).map_err(|e| Failure::new(output.display(), e))?;
+	let writer = BufWriter::new(file);
+	match target_field {
+		Some(target_field) => po::export_po(&table, source_field, target_field, writer),
+		None => po::export_pot(&table, source_field, writer),
+	}.map_err(|e| Failure::new(output.display(), e))?;
+	
+	print_json(&json!({ "rows": table.entry_count().saturating_sub(1) }));
+	Ok(())
+}
+
+pub fn import_po(
+	path: &VfsPath, stl: Option<bool>, encoding: &str, po_path: &Path, output: &Path, source: Option<&str>, language: &str,
+) -> CliResult {
+	let original = open_table(path, stl, encoding)?;
+	let source_field = find_field(&original, source)?;
+	let target_field = find_field(&original, Some(language))?;
+	
+	let file = File::open(po_path).map_err(|e| Failure::new(po_path.display(), e))?;
+	let import = po::import_po(&original, source_field, target_field, BufReader::new(file))
+		.map_err(|e| Failure::new(po_path.display(), e))?;
+	save_table(output, &import.table)?;
+	
+	print_json(&json!({
+		"changed": import.changed,
+		"fuzzy": import.fuzzy,
+		"untranslated": import.untranslated,
+		"outdated": import.outdated,
+		"unknown": import.unknown,
+	}));
+	Ok(())
+}
+
+pub fn search(root: &VfsPath, query: &str, regex: bool, encoding: &str) -> CliResult {
+	let encoding = parse_encoding(encoding)?;
+	let query = if regex {
+		StQuery::regex(query).map_err(|e| Failure::new(query, e))?
+	} else {
+		StQuery::substring(query)
+	};
+	let index = StIndex::build(&MountedVfs::new(), std::slice::from_ref(root), encoding, || true);
+	
+	let matches: Vec<Value> = index.search(&query).into_iter().map(|found| {
+		let table = &index.tables()[found.table];
+		json!({
+			"archive": table.path.parent_archive().map(|archive| archive.to_string()),
+			"table": table.path.to_string(),
+			"row": found.row,
+			"field": found.field,
+			"field_name": table.field_name(found.field),
+			"text": table.text(found.row, found.field),
+		})
+	}).collect();
+	let errors: Vec<Value> = index.errors().iter().map(|(path, e)| json!({ "path": path.to_string(), "error": e.to_string() })).collect();
+	print_json(&json!({ "tables": index.tables().len(), "matches": matches, "errors": errors }));
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn sample_table() -> StTable {
+		let text = |t: &str| StString::from_text(t, StEncoding::Utf8).unwrap();
+		StTable {
+			field_count: 2,
+			strings: vec![text("key"), text("english"), text("hello"), StString::from_raw(vec![0x83, 0x65], StEncoding::Utf8)],
+			encoding: StEncoding::Utf8,
+			extended: Some(StExtendedData {
+				checksums: vec![1, 2, 3, 4],
+				extra1: vec![StExtraEntry { pieces: vec![(1, 2)] }],
+				extra2: vec![],
+			}),
+			layout: None,
+		}
+	}
+	
+	#[test]
+	fn json_round_trip() {
+		let table = sample_table();
+		let value = table_to_json(&table, &VfsPath::disk("strings.stb"));
+		assert_eq!(value["format"], "stb");
+		assert_eq!(value["rows"][1][1]["raw"], "8365");
+		assert_eq!(table_from_json(&value).unwrap(), table);
+		
+		assert_eq!(table_to_json(&table, &VfsPath::disk("strings.STM"))["format"], "stm");
+		let value = table_to_json(&table, &VfsPath::disk("renamed.bin"));
+		assert_eq!(value["format"], "stm_or_stb");
+		assert_eq!(table_from_json(&value).unwrap(), table);
+	}
+	
+	#[test]
+	fn json_rejects_ragged_rows() {
+		let mut value = table_to_json(&sample_table(), &VfsPath::disk("strings.stm"));
+		value["rows"][1].as_array_mut().unwrap().pop();
+		assert_eq!(table_from_json(&value).unwrap_err(), "row 1 has 1 fields instead of 2");
+		
+		let mut value = table_to_json(&sample_table(), &VfsPath::disk("strings.stm"));
+		value["field_count"] = json!(u64::MAX);
+		assert_eq!(table_from_json(&value).unwrap_err(), format!("row 0 has 2 fields instead of {}", u64::MAX));
+	}
+}
@@ -0,0 +1,267 @@
+//! Labelled byte ranges that parsers record while reading a file, so its structure can be shown over a hex dump.
+//!
+//! Each parser has an `_annotated` variant that takes an [`Annotations`] list. The plain variants pass one
+//! that ignores everything, so parsing costs the same as before when nobody is looking.
+//! Ranges are recorded as soon as their bytes are read, so a file that fails to parse is still annotated up to the failure.
+
+use std::collections::BTreeSet;
+use std::cmp::Reverse;
+use std::fmt::Display;
+use std::io::{BufRead, Seek};
+
+use crate::error::Result;
+use crate::{level, pak, st, FileType};
+
+/// A named range of bytes in a file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Annotation {
+	pub name: String,
+	pub offset: u64,
+	pub length: u64,
+	/// The index of the annotation this one is part of, such as the structure a field belongs to.
+	pub parent: Option<usize>,
+}
+
+impl Annotation {
+	pub fn end(&self) -> u64 {
+		self.offset.saturating_add(self.length)
+	}
+	
+	pub fn contains(&self, offset: u64) -> bool {
+		(self.offset..self.end()).contains(&offset)
+	}
+}
+
+/// The layout of a binrw structure, so its fields can be annotated without reading them one by one.
+pub(crate) trait Layout {
+	const NAME: &'static str;
+	/// Each field's name and size in bytes, in the order they're stored. Magic values count as fields.
+	/// Each format's tests check these against the structure with [`assert_layout`].
+	const FIELDS: &'static [(&'static str, u64)];
+	
+	fn layout_size() -> u64 {
+		Self::FIELDS.iter().map(|(_, size)| size).sum()
+	}
+}
+
+/// Checks that a structure's [`Layout`] matches how binrw writes it, by writing `value`
+/// and comparing each field's bytes with `expected`, which has to list every field in order.
+#[cfg(test)]
+pub(crate) fn assert_layout<T>(value: &T, expected: &[(&str, &[u8])])
+where
+	T: Layout + binrw::BinWrite,
+	for<'a> T::Args<'a>: Default,
+{
+	let bytes = crate::util_binary::struct_to_bytes(value, 0).unwrap();
+	let names: Vec<_> = T::FIELDS.iter().map(|(name, _)| *name).collect();
+	let expected_names: Vec<_> = expected.iter().map(|(name, _)| *name).collect();
+	assert_eq!(names, expected_names, "fields of {}", T::NAME);
+	
+	let mut offset = 0;
+	for (&(name, size), (_, expected_bytes)) in T::FIELDS.iter().zip(expected) {
+		let end = offset + size as usize;
+		assert_eq!(bytes.get(offset..end), Some(*expected_bytes), "{}.{name} at offset {offset}", T::NAME);
+		offset = end;
+	}
+	assert_eq!(offset, bytes.len(), "size of {}", T::NAME);
+}
+
+/// A part of the file where a single annotation is the innermost one. See [`Annotations::segments`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Segment {
+	pub start: u64,
+	pub end: u64,
+	pub annotation: usize,
+}
+
+/// The annotations recorded while parsing a file. Annotations are identified by their index in the list.
+#[derive(Clone, Debug)]
+pub struct Annotations {
+	ranges: Vec<Annotation>,
+	enabled: bool,
+}
+
+impl Default for Annotations {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Annotations {
+	pub fn new() -> Self {
+		Self { ranges: Vec::new(), enabled: true }
+	}
+	
+	/// A list that ignores everything, for parsing without annotating.
+	pub(crate) fn disabled() -> Self {
+		Self { ranges: Vec::new(), enabled: false }
+	}
+	
+	/// Records a range, and returns its index for use as the parent of other ranges.
+	///
+	/// The name is only formatted if the list is recording, so `format_args!` is cheap to pass here.
+	pub(crate) fn add(&mut self, parent: Option<usize>, offset: u64, length: u64, name: impl Display) -> Option<usize> {
+		if !self.enabled { return None; }
+		self.ranges.push(Annotation { name: name.to_string(), offset, length, parent });
+		Some(self.ranges.len() - 1)
+	}
+	
+	/// Records a structure named after its type, with its fields inside it.
+	pub(crate) fn add_struct<T: Layout>(&mut self, parent: Option<usize>, offset: u64) -> Option<usize> {
+		let id = self.add(parent, offset, T::layout_size(), T::NAME);
+		self.add_fields::<T>(id, offset);
+		id
+	}
+	
+	/// Records the fields of a structure at `offset` inside an existing annotation.
+	pub(crate) fn add_fields<T: Layout>(&mut self, parent: Option<usize>, offset: u64) {
+		if !self.enabled { return; }
+		let mut field_offset = offset;
+		for &(name, size) in T::FIELDS {
+			self.add(parent, field_offset, size, name);
+			field_offset += size;
+		}
+	}
+	
+	/// Finds the first annotation named `name` directly inside `parent`.
+	pub(crate) fn child(&self, parent: Option<usize>, name: &str) -> Option<usize> {
+		parent?;
+		self.ranges.iter().position(|range| range.parent == parent && range.name == name)
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.ranges.is_empty()
+	}
+	
+	pub fn len(&self) -> usize {
+		self.ranges.len()
+	}
+	
+	pub fn get(&self, index: usize) -> Option<&Annotation> {
+		self.ranges.get(index)
+	}
+	
+	pub fn iter(&self) -> impl Iterator<Item = &Annotation> {
+		self.ranges.iter()
+	}
+	
+	/// The annotation at `index`, then its parent, and so on up to one without a parent.
+	pub fn ancestors(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+		std::iter::successors(Some(index).filter(|&i| i < self.ranges.len()), |&i| self.ranges[i].parent)
+	}
+	
+	/// Splits the annotated parts of the file into pieces that each belong to the smallest annotation covering them.
+	/// When equally small annotations overlap, the one recorded last wins.
+	///
+	/// The segments are sorted and don't overlap, so they can be binary searched.
+	pub fn segments(&self) -> Vec<Segment> {
+		// Ends sort before starts at the same offset, so touching ranges don't overlap
+		let mut events: Vec<(u64, bool, usize)> = self.ranges.iter().enumerate()
+			.filter(|(_, range)| range.length > 0)
+			.flat_map(|(i, range)| [(range.offset, true, i), (range.end(), false, i)])
+			.collect();
+		events.sort_unstable_by_key(|&(at, is_start, _)| (at, is_start));
+		
+		let mut active = BTreeSet::new();
+		let mut segments = Vec::<Segment>::new();
+		let mut events = events.into_iter().peekable();
+		while let Some((at, is_start, index)) = events.next() {
+			let key = (self.ranges[index].length, Reverse(index));
+			if is_start {
+				active.insert(key);
+			} else {
+				active.remove(&key);
+			}
+			let Some(&(next_at, _, _)) = events.peek() else { break; };
+			if next_at == at { continue; }
+			let Some(&(_, Reverse(annotation))) = active.first() else { continue; };
+			match segments.last_mut() {
+				Some(last) if last.end == at && last.annotation == annotation => last.end = next_at,
+				_ => segments.push(Segment { start: at, end: next_at, annotation }),
+			}
+		}
+		segments
+	}
+	
+	/// The innermost annotation covering `offset`, looked up in segments from [`Self::segments`].
+	pub fn innermost_in(segments: &[Segment], offset: u64) -> Option<usize> {
+		let index = segments.partition_point(|segment| segment.end <= offset);
+		segments.get(index).filter(|segment| segment.start <= offset).map(|segment| segment.annotation)
+	}
+}
+
+/// Parses a file of the given type only to annotate it.
+///
+/// The annotations are returned even if parsing fails, since the part before the failure is often what needs looking at.
+/// Types without a parser in this crate get no annotations.
+pub fn annotate_file<R: BufRead + Seek>(file_type: FileType, reader: &mut R) -> (Annotations, Result<()>) {
+	let mut annotations = Annotations::new();
+	let result = match file_type {
+		FileType::Pak => pak::PakIndex::create_index_annotated(reader, &mut annotations).map(drop),
+		FileType::Stl => st::read_st_annotated(reader, true, st::StEncoding::Utf8, &mut annotations).map(drop),
+		FileType::StmOrStb => st::read_st_annotated(reader, false, st::StEncoding::Utf8, &mut annotations).map(drop),
+		FileType::Ltb => level::read_ltb_annotated(reader, &mut annotations).map(drop),
+		FileType::Lvb => level::read_lvb_annotated(reader, &mut annotations).map(drop),
+		FileType::Png | FileType::Unknown => Ok(()),
+	};
+	(annotations, result)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	struct Pair;
+	
+	impl Layout for Pair {
+		const NAME: &'static str = "Pair";
+		const FIELDS: &'static [(&'static str, u64)] = &[("first", 4), ("second", 8)];
+	}
+	
+	#[test]
+	fn disabled_records_nothing() {
+		let mut annotations = Annotations::disabled();
+		assert_eq!(annotations.add(None, 0, 4, "Header"), None);
+		assert_eq!(annotations.add_struct::<Pair>(None, 4), None);
+		assert!(annotations.is_empty());
+	}
+	
+	#[test]
+	fn struct_fields() {
+		let mut annotations = Annotations::new();
+		let file = annotations.add(None, 0, 100, "File");
+		let pair = annotations.add_struct::<Pair>(file, 16);
+		assert_eq!(pair, Some(1));
+		let names: Vec<_> = annotations.iter().map(|a| (a.name.as_str(), a.offset, a.length, a.parent)).collect();
+		assert_eq!(names, vec![
+			("File", 0, 100, None),
+			("Pair", 16, 12, Some(0)),
+			("first", 16, 4, Some(1)),
+			("second", 20, 8, Some(1)),
+		]);
+		assert_eq!(annotations.child(pair, "second"), Some(3));
+		assert_eq!(annotations.ancestors(3).collect::<Vec<_>>(), vec![3, 1, 0]);
+	}
+	
+	#[test]
+	fn innermost_segments() {
+		let mut annotations = Annotations::new();
+		let outer = annotations.add(None, 0, 20, "Outer");
+		annotations.add(outer, 4, 4, "Inner");
+		annotations.add(None, 30, 2, "Separate");
+		annotations.add(None, 30, 2, "Same size, later");
+		
+		let segments = annotations.segments();
+		assert_eq!(segments, vec![
+			Segment { start: 0, end: 4, annotation: 0 },
+			Segment { start: 4, end: 8, annotation: 1 },
+			Segment { start: 8, end: 20, annotation: 0 },
+			Segment { start: 30, end: 32, annotation: 3 },
+		]);
+		assert_eq!(Annotations::innermost_in(&segments, 5), Some(1));
+		assert_eq!(Annotations::innermost_in(&segments, 19), Some(0));
+		assert_eq!(Annotations::innermost_in(&segments, 20), None);
+		assert_eq!(Annotations::innermost_in(&segments, 31), Some(3));
+		assert_eq!(Annotations::innermost_in(&segments, 32), None);
+	}
+}
@@ -0,0 +1,115 @@
+//! The error type shared by every format in this crate.
+
+use std::fmt;
+use std::io;
+
+/// Something that went wrong while reading or writing a file.
+///
+/// Wherever possible, the variant says where in the file the problem was found, so damaged files can be diagnosed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+	/// An I/O error that isn't better described by another variant.
+	Io(io::Error),
+	/// The structure at `offset` didn't start with the expected magic bytes.
+	BadMagic { offset: u64 },
+	/// The structure at `offset` runs past the end of the file.
+	Truncated { offset: u64 },
+	/// The table of `count` entries at `offset` runs past the end of the file.
+	TruncatedTable { offset: u64, count: u64 },
+	/// The pointer stored at `offset` points to `pointer`, which is outside of the file.
+	PointerOutOfBounds { offset: u64, pointer: u64 },
+	/// The structure at `offset` overlaps another structure at `other`.
+	OverlappingRegions { offset: u64, other: u64 },
+	/// The string at `offset` isn't terminated before the end of the file.
+	MissingNulTerminator { offset: u64 },
+	/// A count stored at `offset` is too big to be used, or a count is too big to be stored there.
+	CountOverflow { offset: u64 },
+	/// The structure at `offset` couldn't be understood for some other reason.
+	Malformed { offset: u64, message: String },
+	/// The filename stored at `offset` would escape the directory it's being extracted to.
+	UnsafeName { offset: u64, name: String },
+	/// A spreadsheet being imported has a problem on the given line.
+	BadSheet { line: u64, message: String },
+	/// A spreadsheet being imported has a different number of rows than the table it's imported into.
+	SheetRowCount { expected: u64, found: u64 },
+	/// A gettext PO file being imported has a problem on the given line.
+	BadPo { line: u64, message: String },
+	/// The data being written can't be stored in the format.
+	Unwritable { message: String },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+	/// Where in the file the error was found, if known.
+	pub fn offset(&self) -> Option<u64> {
+		match self {
+			Self::Io(_) | Self::BadSheet { .. } | Self::SheetRowCount { .. } | Self::BadPo { .. } | Self::Unwritable { .. } => None,
+			Self::BadMagic { offset }
+			| Self::Truncated { offset }
+			| Self::TruncatedTable { offset, .. }
+			| Self::PointerOutOfBounds { offset, .. }
+			| Self::OverlappingRegions { offset, .. }
+			| Self::MissingNulTerminator { offset }
+			| Self::CountOverflow { offset }
+			| Self::Malformed { offset, .. }
+			| Self::UnsafeName { offset, .. } => Some(*offset),
+		}
+	}
+	
+	/// Converts an error from parsing the structure that starts at `offset`.
+	pub(crate) fn from_binrw(error: binrw::Error, offset: u64) -> Self {
+		match error {
+			binrw::Error::BadMagic { pos, .. } => Self::BadMagic { offset: pos },
+			binrw::Error::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => Self::Truncated { offset },
+			binrw::Error::Io(e) => Self::Io(e),
+			binrw::Error::Backtrace(backtrace) => Self::from_binrw(*backtrace.error, offset),
+			other => Self::Malformed { offset, message: other.to_string() },
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "I/O error: {e}"),
+			Self::BadMagic { offset } => write!(f, "bad magic bytes at offset {offset:#X}"),
+			Self::Truncated { offset } => write!(f, "data at offset {offset:#X} is cut off by the end of the file"),
+			Self::TruncatedTable { offset, count } => {
+				write!(f, "table of {count} entries at offset {offset:#X} is cut off by the end of the file")
+			},
+			Self::PointerOutOfBounds { offset, pointer } => {
+				write!(f, "pointer at offset {offset:#X} points outside of the file ({pointer:#X})")
+			},
+			Self::OverlappingRegions { offset, other } => {
+				write!(f, "data at offset {offset:#X} overlaps other data at offset {other:#X}")
+			},
+			Self::MissingNulTerminator { offset } => write!(f, "string at offset {offset:#X} has no NUL terminator"),
+			Self::CountOverflow { offset } => write!(f, "count at offset {offset:#X} is too large"),
+			Self::Malformed { offset, message } => write!(f, "malformed data at offset {offset:#X}: {message}"),
+			Self::UnsafeName { offset, name } => write!(f, "filename at offset {offset:#X} is unsafe to extract: {name:?}"),
+			Self::BadSheet { line, message } => write!(f, "problem on line {line} of the spreadsheet: {message}"),
+			Self::SheetRowCount { expected, found } => {
+				write!(f, "the spreadsheet has {found} rows, but the table has {expected}")
+			},
+			Self::BadPo { line, message } => write!(f, "problem on line {line} of the PO file: {message}"),
+			Self::Unwritable { message } => write!(f, "can't write file: {message}"),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Io(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(value: io::Error) -> Self {
+		Self::Io(value)
+	}
+}
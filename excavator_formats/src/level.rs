@@ -1,54 +1,30 @@
-use std::ffi::CStr;
+//! Level files: `.ltb` (layers) and `.lvb` (placed objects). Most of both formats is still unexplored.
+
 use std::io::{BufRead, Seek, SeekFrom};
 
 use binrw::{BinRead, BinWrite};
 
-/*
-#[derive(BinRead, BinWrite, Copy, Clone, Eq, PartialEq, Debug)]
-#[brw(little, magic = b"\0\0\0\0")]
-struct LvbHeader {
-	something1: u32,
-	pointer1: u64,
-	maybe_quantity: u32,
-	something2: u32,
-	pointer2: u64,
-}
+use crate::annotate::{Annotations, Layout};
+use crate::error::{Error, Result};
+use crate::util_binary::{count_to_usize, read_struct};
+use crate::validate::Regions;
 
+/// One of the sections listed in a level file's header. Only some of them are understood so far.
 #[derive(BinRead, BinWrite, Copy, Clone, Eq, PartialEq, Debug)]
-#[brw(little, magic = b"\0\0\0\0")]
-struct LvbThingy {
-	field0: u32,
-	bleh_a: u16,
-	maybe_x: u16,
-	bleh_b: u16,
-	maybe_y: u16,
-	field3: u32,
-	field4: u32,
-	field5: u32,
-	field6: u32,
-	field7: u32,
-	field8: u32,
-	increasing: u64,
-}
-
-pub fn lvb_wip<R: BufRead + Seek>(reader: &mut R) -> BinResult<()> {
-	let header = LvbHeader::read(reader)?;
-	
-	reader.seek(SeekFrom::Start(header.pointer2))?;
-	for _i in 0..header.maybe_quantity {
-		let thingy = LvbThingy::read(reader)?;
-		println!("({}, {})", thingy.maybe_x, thingy.maybe_y);
-	}
-	
-	Ok(())
+#[brw(little)]
+pub struct LevelHeaderElement {
+	pub value_a: u32,
+	pub value_b: u32,
+	pub pointer: u64,
 }
-*/
 
-#[derive(BinRead, BinWrite, Copy, Clone, Eq, PartialEq, Debug)]
-struct HeaderElement {
-	value_a: u32,
-	value_b: u32,
-	pointer: u64,
+impl Layout for LevelHeaderElement {
+	const NAME: &'static str = "LevelHeaderElement";
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("value_a", 4),
+		("value_b", 4),
+		("pointer", 8),
+	];
 }
 
 #[derive(BinRead, BinWrite, Copy, Clone, Eq, PartialEq, Debug)]
@@ -56,14 +32,43 @@ struct HeaderElement {
 struct LtbHeader {
 	first_value_a: u32,
 	first_value_b: u32,
-	elements: [HeaderElement; 8],
+	elements: [LevelHeaderElement; 8],
 }
 
+impl LtbHeader {
+	const SIZE: u64 = 16 + 8 * 16;
+}
+
+impl Layout for LtbHeader {
+	const NAME: &'static str = "LtbHeader";
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("magic", 8),
+		("first_value_a", 4),
+		("first_value_b", 4),
+		("elements", 8 * 16),
+	];
+}
+
+/// Starts with four zero bytes, which are the first element's `value_a`.
 #[derive(BinRead, BinWrite, Copy, Clone, Eq, PartialEq, Debug)]
 #[brw(little)]
-#[expect(dead_code)] // we'll get back to this
 struct LvbHeader {
-	elements: [HeaderElement; 7],
+	elements: [LevelHeaderElement; 7],
+}
+
+impl LvbHeader {
+	const SIZE: u64 = 7 * 16;
+	
+	fn has_magic(&self) -> bool {
+		self.elements[0].value_a == 0
+	}
+}
+
+impl Layout for LvbHeader {
+	const NAME: &'static str = "LvbHeader";
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("elements", 7 * 16),
+	];
 }
 
 #[derive(BinRead, BinWrite, Copy, Clone, Eq, PartialEq, Debug)]
@@ -73,23 +78,367 @@ struct LtbLayerEntry {
 	numbers: [u32; 24],
 }
 
-#[expect(dead_code)] // we'll get back to this
-pub fn ltb_wip<R: BufRead + Seek>(reader: &mut R) -> Result<(), Box<dyn std::error::Error>> {
-	let header = LtbHeader::read(reader)?;
+impl LtbLayerEntry {
+	const SIZE: u64 = 32 + 24 * 4;
+}
+
+impl Layout for LtbLayerEntry {
+	const NAME: &'static str = "LtbLayerEntry";
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("name", 32),
+		("numbers", 24 * 4),
+	];
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LtbFile {
+	pub first_value_a: u32,
+	pub first_value_b: u32,
+	/// The first element points to the layers. The others are unexplored.
+	pub elements: [LevelHeaderElement; 8],
+	pub layers: Vec<LtbLayer>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LtbLayer {
+	/// Stored in 32 bytes, ending at the first NUL if it's shorter.
+	pub name: String,
+	/// What these mean is unknown.
+	pub numbers: [u32; 24],
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LvbFile {
+	/// The second element points to the objects. The others are unexplored.
+	pub elements: [LevelHeaderElement; 7],
+	pub objects: Vec<LvbObject>,
+}
+
+/// Something placed in a level. The position is a guess, based on what looks right when plotted.
+#[derive(BinRead, BinWrite, Copy, Clone, Eq, PartialEq, Debug)]
+#[brw(little, magic = b"\0\0\0\0")]
+pub struct LvbObject {
+	pub field0: u32,
+	pub bleh_a: u16,
+	pub maybe_x: u16,
+	pub bleh_b: u16,
+	pub maybe_y: u16,
+	pub field3: u32,
+	pub field4: u32,
+	pub field5: u32,
+	pub field6: u32,
+	pub field7: u32,
+	pub field8: u32,
+	/// Goes up with each object, so it might be an ID.
+	pub increasing: u64,
+}
+
+impl LvbObject {
+	const SIZE: u64 = 48;
+}
+
+/// Keep this in step with the fields above as more of them are figured out.
+impl Layout for LvbObject {
+	const NAME: &'static str = "LvbObject";
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("magic", 4),
+		("field0", 4),
+		("bleh_a", 2),
+		("maybe_x", 2),
+		("bleh_b", 2),
+		("maybe_y", 2),
+		("field3", 4),
+		("field4", 4),
+		("field5", 4),
+		("field6", 4),
+		("field7", 4),
+		("field8", 4),
+		("increasing", 8),
+	];
+}
+
+/// Records each of a header's elements inside its `elements` field, which starts at `offset`.
+fn annotate_elements(annotations: &mut Annotations, header: Option<usize>, offset: u64, count: u64) {
+	let elements = annotations.child(header, "elements");
+	for i in 0..count {
+		let element_offset = offset + i * 16;
+		let element = annotations.add(elements, element_offset, 16, format_args!("elements[{i}]"));
+		annotations.add_fields::<LevelHeaderElement>(element, element_offset);
+	}
+}
+
+pub fn read_ltb<R: BufRead + Seek>(reader: &mut R) -> Result<LtbFile> {
+	read_ltb_annotated(reader, &mut Annotations::disabled())
+}
+
+/// Like [`read_ltb`], also recording the header and layer entries.
+pub fn read_ltb_annotated<R: BufRead + Seek>(reader: &mut R, annotations: &mut Annotations) -> Result<LtbFile> {
+	reader.rewind()?;
+	let mut regions = Regions::new(reader)?;
+	let header: LtbHeader = read_struct(reader)?;
+	let header_id = annotations.add_struct::<LtbHeader>(None, 0);
+	annotate_elements(annotations, header_id, 16, 8);
+	regions.claim(0, LtbHeader::SIZE, 0)?;
 	
 	let element = header.elements[0];
+	// The element's `value_b` field, then its pointer
+	let count = count_to_usize(element.value_b, 20)?;
+	let table = annotations.add(None, element.pointer, count as u64 * LtbLayerEntry::SIZE, "Layer table");
+	regions.claim_table(element.pointer, count as u64, LtbLayerEntry::SIZE, 24)?;
+	
+	reader.seek(SeekFrom::Start(element.pointer))?;
+	let layers = (0..count).map(|i| {
+		let offset = reader.stream_position()?;
+		let entry: LtbLayerEntry = read_struct(reader)?;
+		let length = entry.name.iter().position(|&b| b == 0).unwrap_or(entry.name.len());
+		let name = String::from_utf8_lossy(&entry.name[..length]).into_owned();
+		let layer = annotations.add(table, offset, LtbLayerEntry::SIZE, format_args!("Layer {i}: {name}"));
+		annotations.add_fields::<LtbLayerEntry>(layer, offset);
+		Ok(LtbLayer { name, numbers: entry.numbers })
+	}).collect::<Result<_>>()?;
+	
+	Ok(LtbFile {
+		first_value_a: header.first_value_a,
+		first_value_b: header.first_value_b,
+		elements: header.elements,
+		layers,
+	})
+}
+
+pub fn read_lvb<R: BufRead + Seek>(reader: &mut R) -> Result<LvbFile> {
+	read_lvb_annotated(reader, &mut Annotations::disabled())
+}
+
+/// Like [`read_lvb`], also recording the header and objects.
+pub fn read_lvb_annotated<R: BufRead + Seek>(reader: &mut R, annotations: &mut Annotations) -> Result<LvbFile> {
+	reader.rewind()?;
+	let mut regions = Regions::new(reader)?;
+	let header: LvbHeader = read_struct(reader)?;
+	if !header.has_magic() {
+		return Err(Error::BadMagic { offset: 0 });
+	}
+	let header_id = annotations.add_struct::<LvbHeader>(None, 0);
+	annotate_elements(annotations, header_id, 0, 7);
+	regions.claim(0, LvbHeader::SIZE, 0)?;
+	
+	let element = header.elements[1];
+	// The element's `value_a` field, then its pointer
+	let count = count_to_usize(element.value_a, 16)?;
+	let table = annotations.add(None, element.pointer, count as u64 * LvbObject::SIZE, "Object table");
+	regions.claim_table(element.pointer, count as u64, LvbObject::SIZE, 24)?;
+	
 	reader.seek(SeekFrom::Start(element.pointer))?;
-	let mut writer = csv::Writer::from_writer(std::io::stdout());
-	for _i in 0..element.value_b {
-		let layer = LtbLayerEntry::read(reader)?;
-		let mut row = Vec::<String>::new();
-		let lossy_name = CStr::from_bytes_until_nul(&layer.name)?.to_string_lossy().into_owned();
-		row.push(lossy_name);
-		for number in layer.numbers {
-			row.push(number.to_string());
+	let objects = (0..count as u64).map(|i| {
+		let offset = element.pointer + i * LvbObject::SIZE;
+		let object = read_struct(reader)?;
+		let id = annotations.add(table, offset, LvbObject::SIZE, format_args!("Object {i}"));
+		annotations.add_fields::<LvbObject>(id, offset);
+		Ok(object)
+	}).collect::<Result<_>>()?;
+	
+	Ok(LvbFile {
+		elements: header.elements,
+		objects,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+	
+	use crate::annotate::assert_layout;
+	use crate::util_binary::struct_to_bytes;
+	
+	fn element(value_a: u32, value_b: u32, pointer: u64) -> LevelHeaderElement {
+		LevelHeaderElement { value_a, value_b, pointer }
+	}
+	
+	/// Two layers, right after the header.
+	fn small_ltb() -> Vec<u8> {
+		let mut elements = [element(0, 0, 0); 8];
+		elements[0] = element(7, 2, LtbHeader::SIZE);
+		let header = LtbHeader { first_value_a: 1, first_value_b: 2, elements };
+		let mut writer = Cursor::new(Vec::<u8>::new());
+		header.write(&mut writer).unwrap();
+		
+		for (name, base) in [(&b"background"[..], 100u32), (b"front", 200)] {
+			let mut name_buf = [0u8; 32];
+			name_buf[..name.len()].copy_from_slice(name);
+			let numbers = std::array::from_fn(|i| base + i as u32);
+			LtbLayerEntry { name: name_buf, numbers }.write(&mut writer).unwrap();
 		}
-		writer.write_record(row)?;
+		writer.into_inner()
+	}
+	
+	fn sample_object(n: u16) -> LvbObject {
+		LvbObject {
+			field0: 9,
+			bleh_a: 0,
+			maybe_x: 16 * n,
+			bleh_b: 0,
+			maybe_y: 100 - n,
+			field3: 3,
+			field4: 4,
+			field5: 5,
+			field6: 6,
+			field7: 7,
+			field8: 8,
+			increasing: u64::from(n),
+		}
+	}
+	
+	/// Three objects, right after the header.
+	fn small_lvb() -> Vec<u8> {
+		let mut elements = [element(0, 0, 0); 7];
+		elements[1] = element(3, 0, LvbHeader::SIZE);
+		let mut writer = Cursor::new(Vec::<u8>::new());
+		LvbHeader { elements }.write(&mut writer).unwrap();
+		for n in 0..3 {
+			sample_object(n).write(&mut writer).unwrap();
+		}
+		writer.into_inner()
+	}
+	
+	#[test]
+	fn ltb_read() {
+		let ltb = read_ltb(&mut Cursor::new(small_ltb())).unwrap();
+		assert_eq!((ltb.first_value_a, ltb.first_value_b), (1, 2));
+		assert_eq!(ltb.elements[0], element(7, 2, LtbHeader::SIZE));
+		assert_eq!(ltb.layers.len(), 2);
+		assert_eq!(ltb.layers[0].name, "background");
+		assert_eq!(ltb.layers[0].numbers[0], 100);
+		assert_eq!(ltb.layers[1].name, "front");
+		assert_eq!(ltb.layers[1].numbers[23], 223);
+		
+		// Names can take up all 32 bytes
+		let mut long_name = small_ltb();
+		let name_start = LtbHeader::SIZE as usize;
+		long_name[name_start..name_start + 32].fill(b'a');
+		assert_eq!(read_ltb(&mut Cursor::new(long_name)).unwrap().layers[0].name, "a".repeat(32));
+	}
+	
+	#[test]
+	fn ltb_read_damaged() {
+		let mut too_many_layers = small_ltb();
+		too_many_layers[20] = 3;
+		assert!(matches!(
+			read_ltb(&mut Cursor::new(too_many_layers)),
+			Err(Error::TruncatedTable { offset: LtbHeader::SIZE, count: 3 }),
+		));
+	}
+	
+	#[test]
+	fn lvb_read() {
+		let lvb = read_lvb(&mut Cursor::new(small_lvb())).unwrap();
+		assert_eq!(lvb.elements[1], element(3, 0, LvbHeader::SIZE));
+		assert_eq!(lvb.objects, [sample_object(0), sample_object(1), sample_object(2)]);
+		assert_eq!((lvb.objects[2].maybe_x, lvb.objects[2].maybe_y), (32, 98));
+	}
+	
+	#[test]
+	fn lvb_read_damaged() {
+		let mut objects_in_header = small_lvb();
+		objects_in_header[24] = 0x10;
+		assert!(matches!(
+			read_lvb(&mut Cursor::new(objects_in_header)),
+			Err(Error::OverlappingRegions { offset: 0x10, other: 0 }),
+		));
+		
+		let mut bad_header_magic = small_lvb();
+		bad_header_magic[2] = 1;
+		assert!(matches!(read_lvb(&mut Cursor::new(bad_header_magic)), Err(Error::BadMagic { offset: 0 })));
+		
+		let mut bad_object_magic = small_lvb();
+		bad_object_magic[LvbHeader::SIZE as usize + LvbObject::SIZE as usize] = 1;
+		assert!(matches!(
+			read_lvb(&mut Cursor::new(bad_object_magic)),
+			Err(Error::BadMagic { offset }) if offset == LvbHeader::SIZE + LvbObject::SIZE,
+		));
+	}
+	
+	#[test]
+	fn layouts_match_fields() {
+		assert_eq!(LtbHeader::layout_size(), LtbHeader::SIZE);
+		assert_eq!(LvbHeader::layout_size(), LvbHeader::SIZE);
+		assert_eq!(LtbLayerEntry::layout_size(), LtbLayerEntry::SIZE);
+		assert_eq!(LvbObject::layout_size(), LvbObject::SIZE);
+		
+		assert_layout(&element(1, 2, 3), &[
+			("value_a", &1u32.to_le_bytes()),
+			("value_b", &2u32.to_le_bytes()),
+			("pointer", &3u64.to_le_bytes()),
+		]);
+		
+		let elements: [_; 8] = std::array::from_fn(|i| element(i as u32, 0, 0x100 * i as u64));
+		let elements_bytes = struct_to_bytes(&elements, 0).unwrap();
+		let ltb_header = LtbHeader { first_value_a: 1, first_value_b: 2, elements };
+		assert_layout(&ltb_header, &[
+			("magic", &[0; 8]),
+			("first_value_a", &1u32.to_le_bytes()),
+			("first_value_b", &2u32.to_le_bytes()),
+			("elements", &elements_bytes),
+		]);
+		let lvb_header = LvbHeader { elements: elements[..7].try_into().unwrap() };
+		assert_layout(&lvb_header, &[("elements", &elements_bytes[..7 * 16])]);
+		
+		let numbers: [u32; 24] = std::array::from_fn(|i| i as u32 + 1);
+		let numbers_bytes: Vec<u8> = numbers.iter().flat_map(|number| number.to_le_bytes()).collect();
+		assert_layout(&LtbLayerEntry { name: [b'n'; 32], numbers }, &[
+			("name", &[b'n'; 32]),
+			("numbers", &numbers_bytes),
+		]);
+		
+		let object = LvbObject {
+			field0: 1,
+			bleh_a: 2,
+			maybe_x: 3,
+			bleh_b: 4,
+			maybe_y: 5,
+			field3: 6,
+			field4: 7,
+			field5: 8,
+			field6: 9,
+			field7: 10,
+			field8: 11,
+			increasing: 12,
+		};
+		assert_layout(&object, &[
+			("magic", &[0; 4]),
+			("field0", &1u32.to_le_bytes()),
+			("bleh_a", &2u16.to_le_bytes()),
+			("maybe_x", &3u16.to_le_bytes()),
+			("bleh_b", &4u16.to_le_bytes()),
+			("maybe_y", &5u16.to_le_bytes()),
+			("field3", &6u32.to_le_bytes()),
+			("field4", &7u32.to_le_bytes()),
+			("field5", &8u32.to_le_bytes()),
+			("field6", &9u32.to_le_bytes()),
+			("field7", &10u32.to_le_bytes()),
+			("field8", &11u32.to_le_bytes()),
+			("increasing", &12u64.to_le_bytes()),
+		]);
+	}
+	
+	#[test]
+	fn lvb_annotated() {
+		let mut annotations = Annotations::new();
+		read_lvb_annotated(&mut Cursor::new(small_lvb()), &mut annotations).unwrap();
+		let find = |name: &str| annotations.iter().position(|a| a.name == name);
+		
+		let element = annotations.get(find("elements[1]").unwrap()).unwrap();
+		assert_eq!((element.offset, element.length), (16, 16));
+		let object = find("Object 2");
+		assert_eq!(annotations.get(object.unwrap()).unwrap().offset, LvbHeader::SIZE + 2 * LvbObject::SIZE);
+		let maybe_y = annotations.get(annotations.child(object, "maybe_y").unwrap()).unwrap();
+		assert_eq!((maybe_y.offset, maybe_y.length), (LvbHeader::SIZE + 2 * LvbObject::SIZE + 14, 2));
+	}
+	
+	#[test]
+	fn ltb_annotated() {
+		let mut annotations = Annotations::new();
+		read_ltb_annotated(&mut Cursor::new(small_ltb()), &mut annotations).unwrap();
+		let layer = annotations.iter().find(|a| a.name == "Layer 1: front").unwrap();
+		assert_eq!((layer.offset, layer.length), (LtbHeader::SIZE + LtbLayerEntry::SIZE, LtbLayerEntry::SIZE));
 	}
-	writer.flush()?;
-	Ok(())
 }
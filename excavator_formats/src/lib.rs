@@ -1,25 +1,39 @@
-// pub mod level;
+pub mod annotate;
+mod error;
+pub mod level;
 pub mod pak;
+mod sniff;
 pub mod st;
 mod util_binary;
+mod validate;
+pub mod vfs;
 
 use std::ffi::OsStr;
 use std::path::Path;
 
+pub use error::{Error, Result};
+pub use sniff::{Confidence, Detection, SNIFF_LENGTH};
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum FileType {
 	Unknown,
+	Png,
 	Pak,
 	StmOrStb,
 	Stl,
+	Ltb,
+	Lvb,
 }
 
 impl FileType {
 	fn from_extension(ext: Option<&OsStr>) -> Self {
 		match ext.map(|e| e.as_encoded_bytes()) {
+			Some(b"png") => Self::Png,
 			Some(b"pak") => Self::Pak,
 			Some(b"stm" | b"stb") => Self::StmOrStb,
 			Some(b"stl") => Self::Stl,
+			Some(b"ltb") => Self::Ltb,
+			Some(b"lvb") => Self::Lvb,
 			_ => Self::Unknown,
 		}
 	}
@@ -37,10 +51,13 @@ mod tests {
 	#[test]
 	fn known_extensions() {
 		let examples = vec![
+			("cool/file.png", FileType::Png),
 			("cool/file.pak", FileType::Pak),
 			("cool/file.stb", FileType::StmOrStb),
 			("cool/file.stm", FileType::StmOrStb),
 			("cool/file.stl", FileType::Stl),
+			("cool/file.ltb", FileType::Ltb),
+			("cool/file.lvb", FileType::Lvb),
 		];
 		
 		for example in examples {
@@ -1,9 +1,18 @@
-use std::ffi::CString;
-use std::io::{BufRead, Seek, SeekFrom};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::{CStr, CString};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-use binrw::{BinRead, BinResult, BinWrite};
+use binrw::{BinRead, BinWrite};
 
-use super::util_binary::{read_pointers, seek_absolute};
+use crate::annotate::{Annotations, Layout};
+use crate::error::{Error, Result};
+use crate::util_binary::{
+	count_to_usize, read_nul_terminated, read_pointers, read_struct, seek_absolute, struct_to_bytes, write_padding, write_pieces,
+};
+use crate::validate::Regions;
 
 /// The header at the beginning of a `.bin` archive.
 #[derive(BinRead, BinWrite, Copy, Clone, Debug)]
@@ -17,6 +26,20 @@ struct PakHeader {
 	name_table_offset: u64,
 }
 
+impl PakHeader {
+	const SIZE: u64 = 24;
+}
+
+impl Layout for PakHeader {
+	const NAME: &'static str = "PakHeader";
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("magic", 4),
+		("file_count", 4),
+		("data_table_offset", 8),
+		("name_table_offset", 8),
+	];
+}
+
 /// The header for a single file. Immediately precedes the contents of the file.
 #[derive(BinRead, BinWrite, Copy, Clone, Debug)]
 #[brw(little)]
@@ -27,48 +50,114 @@ struct PakFileHeader {
 	idk3: u64,
 }
 
+impl PakFileHeader {
+	const SIZE: u64 = 32;
+}
+
+impl Layout for PakFileHeader {
+	const NAME: &'static str = "PakFileHeader";
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("file_size", 8),
+		("idk1", 8),
+		("idk2", 8),
+		("idk3", 8),
+	];
+}
+
 #[derive(Clone, Debug)]
 pub struct PakIndex {
 	pub files: Vec<(CString, PakIndexFileEntry)>,
+	/// The raw pointers from the header, kept so [`PakWriter::from_index`] can reproduce the original layout.
+	pub data_table_offset: u64,
+	pub name_table_offset: u64,
+	/// The length of the whole archive, including any padding after the last file.
+	pub archive_length: u64,
 }
 
 #[derive(Clone, Debug)]
 pub struct PakIndexFileEntry {
+	/// Where the file's header starts. This is the file's raw pointer from the data pointer table.
+	pub header_offset: u64,
+	/// The file's raw pointer from the name pointer table.
+	pub name_pointer: u64,
 	pub data_start: u64,
 	pub data_length: u64,
+	/// The unidentified fields of the file's header, kept so they can be studied and written back.
+	pub idk1: u64,
+	pub idk2: u64,
+	pub idk3: u64,
 }
 
 impl PakIndex {
-	pub fn create_index<R: BufRead + Seek>(reader: &mut R) -> BinResult<Self> {
+	/// Reads the archive's tables and file headers.
+	///
+	/// Every pointer is checked against the length of the archive, and the tables and files must not overlap,
+	/// so a damaged archive fails here instead of causing huge allocations or reads later on.
+	pub fn create_index<R: BufRead + Seek>(reader: &mut R) -> Result<Self> {
+		Self::create_index_annotated(reader, &mut Annotations::disabled())
+	}
+	
+	/// Like [`Self::create_index`], also recording the headers, tables, names and file contents it comes across.
+	pub fn create_index_annotated<R: BufRead + Seek>(reader: &mut R, annotations: &mut Annotations) -> Result<Self> {
 		reader.rewind()?;
-		let header = PakHeader::read(reader)?;
+		let mut regions = Regions::new(reader)?;
+		let header: PakHeader = read_struct(reader)?;
+		annotations.add_struct::<PakHeader>(None, 0);
+		regions.claim(0, PakHeader::SIZE, 0)?;
+		
+		let file_count_usize = count_to_usize(header.file_count, 4)?;
+		let file_count = u64::from(header.file_count);
+		annotations.add(None, header.data_table_offset, file_count * 8, "Data pointer table");
+		annotations.add(None, header.name_table_offset, file_count * 8, "Name pointer table");
+		regions.claim_table(header.data_table_offset, file_count, 8, 8)?;
+		regions.claim_table(header.name_table_offset, file_count, 8, 16)?;
 		
-		let file_count_usize = header.file_count as usize;
 		reader.seek(SeekFrom::Start(header.data_table_offset))?;
 		let data_pointers = read_pointers(reader, file_count_usize)?;
 		reader.seek(SeekFrom::Start(header.name_table_offset))?;
 		let name_pointers = read_pointers(reader, file_count_usize)?;
 		
 		let mut file_names = Vec::<CString>::with_capacity(file_count_usize);
-		for i in 0..file_count_usize {
-			seek_absolute(reader, name_pointers[i])?;
-			let mut name_buf = Vec::<u8>::new();
-			reader.read_until(0, &mut name_buf)?;
-			file_names.push(CString::from_vec_with_nul(name_buf).unwrap());
+		for (i, &name_pointer) in name_pointers.iter().enumerate() {
+			// Names aren't claimed, in case some archive shares them between files
+			regions.check_pointer(name_pointer, header.name_table_offset + i as u64 * 8)?;
+			seek_absolute(reader, name_pointer)?;
+			let name_buf = read_nul_terminated(reader)?;
+			annotations.add(None, name_pointer, name_buf.len() as u64 + 1, format_args!("Name of file {i}"));
+			// `read_nul_terminated` stops at the first NUL, so there can't be any others
+			file_names.push(CString::new(name_buf).expect("name has no interior NUL"));
 		}
 		
 		let mut entries = Vec::<PakIndexFileEntry>::with_capacity(file_count_usize);
-		for i in 0..file_count_usize {
-			seek_absolute(reader, data_pointers[i])?;
-			let file_header = PakFileHeader::read(reader)?;
+		for (i, (data_pointer, name_pointer)) in data_pointers.into_iter().zip(name_pointers).enumerate() {
+			let stored_at = header.data_table_offset + i as u64 * 8;
+			regions.check_pointer(data_pointer, stored_at)?;
+			seek_absolute(reader, data_pointer)?;
+			let file_header: PakFileHeader = read_struct(reader)?;
+			let data_start = data_pointer + PakFileHeader::SIZE;
+			let name = file_names[i].to_string_lossy();
+			let file = annotations.add(None, data_pointer, PakFileHeader::SIZE.saturating_add(file_header.file_size), format_args!("File {i}: {name}"));
+			annotations.add_struct::<PakFileHeader>(file, data_pointer);
+			annotations.add(file, data_start, file_header.file_size, "Contents");
+			regions.claim(data_pointer, PakFileHeader::SIZE, stored_at)?;
+			regions.claim(data_start, file_header.file_size, data_pointer)?;
 			
-			let data_start = reader.stream_position()?;
-			let data_length = file_header.file_size;
-			entries.push(PakIndexFileEntry { data_start, data_length });
+			entries.push(PakIndexFileEntry {
+				header_offset: data_pointer,
+				name_pointer,
+				data_start,
+				data_length: file_header.file_size,
+				idk1: file_header.idk1,
+				idk2: file_header.idk2,
+				idk3: file_header.idk3,
+			});
 		}
 		
 		Ok(Self {
 			files: file_names.into_iter().zip(entries).collect(),
+			data_table_offset: header.data_table_offset,
+			name_table_offset: header.name_table_offset,
+			archive_length: regions.stream_length(),
 		})
 	}
 }
@@ -79,3 +168,771 @@ pub fn read_whole_file<R: BufRead + Seek>(file_entry: &PakIndexFileEntry, reader
 	reader.read_exact(&mut data_buf)?;
 	Ok(data_buf)
 }
+
+/// Reads a single file inside an archive without copying it out first.
+///
+/// Positions are relative to the start of the file's contents, and reads stop at the end of the file,
+/// so format parsers can treat it like a standalone file.
+///
+/// `R` can be a borrowed reader, or an owned one when the entry reader needs to outlive the code that opened the archive.
+pub struct PakEntryReader<R> {
+	inner: R,
+	data_start: u64,
+	data_length: u64,
+	position: u64,
+}
+
+impl<R: BufRead + Seek> PakEntryReader<R> {
+	pub fn new(file_entry: &PakIndexFileEntry, mut inner: R) -> io::Result<Self> {
+		seek_absolute(&mut inner, file_entry.data_start)?;
+		Ok(Self {
+			inner,
+			data_start: file_entry.data_start,
+			data_length: file_entry.data_length,
+			position: 0,
+		})
+	}
+	
+	pub fn len(&self) -> u64 {
+		self.data_length
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.data_length == 0
+	}
+	
+	fn remaining(&self) -> u64 {
+		self.data_length.saturating_sub(self.position)
+	}
+}
+
+impl<R: BufRead + Seek> Read for PakEntryReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let max = buf.len().min(self.remaining().try_into().unwrap_or(usize::MAX));
+		let amount = self.inner.read(&mut buf[..max])?;
+		self.position += amount as u64;
+		Ok(amount)
+	}
+}
+
+impl<R: BufRead + Seek> BufRead for PakEntryReader<R> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		let remaining = self.remaining().try_into().unwrap_or(usize::MAX);
+		if remaining == 0 {
+			return Ok(&[]);
+		}
+		let buf = self.inner.fill_buf()?;
+		let max = buf.len().min(remaining);
+		Ok(&buf[..max])
+	}
+	
+	fn consume(&mut self, amount: usize) {
+		self.inner.consume(amount);
+		self.position += amount as u64;
+	}
+}
+
+impl<R: BufRead + Seek> Seek for PakEntryReader<R> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		let new_position = match pos {
+			SeekFrom::Start(offset) => Some(offset),
+			SeekFrom::End(offset) => self.data_length.checked_add_signed(offset),
+			SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+		};
+		let absolute = new_position.and_then(|position| Some((position, self.data_start.checked_add(position)?)));
+		let (new_position, absolute) = absolute.ok_or_else(|| {
+			io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")
+		})?;
+		seek_absolute(&mut self.inner, absolute)?;
+		self.position = new_position;
+		Ok(new_position)
+	}
+	
+	fn stream_position(&mut self) -> io::Result<u64> {
+		Ok(self.position)
+	}
+}
+
+/// Turns an archived filename into a relative path, treating both `/` and `\` as separators.
+///
+/// Returns `None` if the name is empty, absolute, has a drive letter, or contains `.`, `..` or empty components,
+/// since extracting it could write outside of the destination.
+pub fn safe_relative_path(name: &CStr) -> Option<PathBuf> {
+	let name = name.to_string_lossy();
+	let components: Vec<&str> = name.split(['/', '\\']).collect();
+	let safe = components.iter().all(|component| {
+		!component.is_empty() && *component != "." && *component != ".." && !component.contains(':')
+	});
+	safe.then(|| components.iter().collect())
+}
+
+/// What happened while extracting a whole archive.
+#[derive(Clone, Debug, Default)]
+pub struct ExtractReport {
+	/// The paths of the files that were written.
+	pub extracted: Vec<PathBuf>,
+	/// Files that weren't written, because an earlier file in the archive already took their path,
+	/// or a file was already there in the destination.
+	pub collisions: Vec<ExtractCollision>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ExtractCollision {
+	pub name: CString,
+	pub path: PathBuf,
+}
+
+/// Writes every file in the archive under `destination`, creating directories for the path components in their names.
+///
+/// All names are checked with [`safe_relative_path`] before anything is written.
+/// Paths are compared case-insensitively, since that's how the game's home platform treats them,
+/// so when two files would land on the same path, or one file's path is used as another's directory, only the first is kept.
+/// Files that are already in the destination are never overwritten, and are reported as collisions too.
+pub fn extract_all<R: BufRead + Seek>(index: &PakIndex, reader: &mut R, destination: &Path) -> Result<ExtractReport> {
+	extract_selected(index, reader, destination, |_| true)
+}
+
+/// Like [`extract_all`], but only for the files whose names `selected` accepts.
+///
+/// Only the selected files' names are checked, and only they can collide with each other.
+pub fn extract_selected<R: BufRead + Seek>(
+	index: &PakIndex, reader: &mut R, destination: &Path, mut selected: impl FnMut(&CStr) -> bool,
+) -> Result<ExtractReport> {
+	let files: Vec<_> = index.files.iter().filter(|(name, _)| selected(name)).collect();
+	let paths = files.iter().map(|(name, entry)| {
+		safe_relative_path(name).ok_or_else(|| Error::UnsafeName {
+			offset: entry.name_pointer,
+			name: name.to_string_lossy().into_owned(),
+		})
+	}).collect::<Result<Vec<_>>>()?;
+	
+	let folded = |path: &Path| path.to_string_lossy().to_lowercase();
+	let mut taken_files = HashSet::<String>::new();
+	let mut taken_dirs = HashSet::<String>::new();
+	let mut report = ExtractReport::default();
+	
+	for ((name, entry), relative) in files.into_iter().zip(paths) {
+		let ancestors: Vec<String> = relative.ancestors().skip(1).map(folded).filter(|a| !a.is_empty()).collect();
+		let key = folded(&relative);
+		let path = destination.join(&relative);
+		if taken_files.contains(&key) || taken_dirs.contains(&key) || ancestors.iter().any(|a| taken_files.contains(a)) {
+			report.collisions.push(ExtractCollision { name: name.clone(), path });
+			continue;
+		}
+		taken_files.insert(key);
+		taken_dirs.extend(ancestors);
+		
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		let file = match File::create_new(&path) {
+			Ok(file) => file,
+			Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+				report.collisions.push(ExtractCollision { name: name.clone(), path });
+				continue;
+			},
+			Err(e) => return Err(e.into()),
+		};
+		let mut output = BufWriter::new(file);
+		io::copy(&mut PakEntryReader::new(entry, &mut *reader)?, &mut output)?;
+		output.flush()?;
+		report.extracted.push(path);
+	}
+	
+	Ok(report)
+}
+
+/// Builds a complete archive out of in-memory files.
+///
+/// The layout is: header, data pointer table, name pointer table, the packed filenames,
+/// then each file's header and contents in order, with every file header aligned to [`Self::FILE_ALIGNMENT`].
+///
+/// Archives loaded with [`Self::from_index`] keep the layout they were read with instead, see [`Self::write`].
+#[derive(Clone, Debug, Default)]
+pub struct PakWriter {
+	pub files: Vec<(CString, PakWriterEntry)>,
+	original_layout: Option<PakOriginalLayout>,
+}
+
+/// Where everything was in the archive a [`PakWriter`] was loaded from.
+#[derive(Clone, Debug)]
+struct PakOriginalLayout {
+	data_table_offset: u64,
+	name_table_offset: u64,
+	archive_length: u64,
+	/// The name, header offset, name pointer and size of each file.
+	files: Vec<(CString, u64, u64, u64)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PakWriterEntry {
+	pub data: Vec<u8>,
+	/// Written as-is into the unidentified fields of the file's header.
+	pub idk1: u64,
+	pub idk2: u64,
+	pub idk3: u64,
+}
+
+impl PakWriterEntry {
+	pub fn new(data: Vec<u8>) -> Self {
+		Self { data, idk1: 0, idk2: 0, idk3: 0 }
+	}
+}
+
+impl PakWriter {
+	pub const FILE_ALIGNMENT: u64 = 16;
+	
+	pub fn new() -> Self {
+		Self::default()
+	}
+	
+	/// Loads every file listed in `index`, so individual files can be replaced before writing a new archive.
+	/// Where everything was in the original archive is remembered too.
+	pub fn from_index<R: BufRead + Seek>(index: &PakIndex, reader: &mut R) -> std::io::Result<Self> {
+		let files = index.files.iter().map(|(name, entry)| {
+			let data = read_whole_file(entry, reader)?;
+			Ok((name.clone(), PakWriterEntry { data, idk1: entry.idk1, idk2: entry.idk2, idk3: entry.idk3 }))
+		}).collect::<std::io::Result<_>>()?;
+		let original_layout = PakOriginalLayout {
+			data_table_offset: index.data_table_offset,
+			name_table_offset: index.name_table_offset,
+			archive_length: index.archive_length,
+			files: index.files.iter()
+				.map(|(name, entry)| (name.clone(), entry.header_offset, entry.name_pointer, entry.data_length))
+				.collect(),
+		};
+		Ok(Self { files, original_layout: Some(original_layout) })
+	}
+	
+	pub fn add_file(&mut self, name: CString, data: Vec<u8>) {
+		self.files.push((name, PakWriterEntry::new(data)));
+	}
+	
+	/// Swaps the contents of the file called `name`. Returns `false` if there is no such file.
+	pub fn replace_file(&mut self, name: &CStr, data: Vec<u8>) -> bool {
+		match self.files.iter_mut().find(|f| f.0.as_c_str() == name) {
+			Some(file) => {
+				file.1.data = data;
+				true
+			},
+			None => false,
+		}
+	}
+	
+	/// Writes the archive, starting from the beginning of `writer`.
+	///
+	/// If the writer was loaded with [`Self::from_index`] and still has the same files with the same sizes,
+	/// every table, name and file goes back where it was, so an unmodified archive comes out byte for byte the same.
+	/// Only the bytes between them aren't kept, and are written as zeros.
+	/// Otherwise the whole archive is laid out from scratch.
+	pub fn write<W: Write + Seek>(&self, writer: &mut W) -> Result<()> {
+		match &self.original_layout {
+			Some(layout) if self.fits_layout(layout) => self.write_original_layout(writer, layout),
+			_ => self.write_new_layout(writer),
+		}
+	}
+	
+	fn fits_layout(&self, layout: &PakOriginalLayout) -> bool {
+		self.files.len() == layout.files.len()
+			&& self.files.iter().zip(&layout.files).all(|((name, entry), (original_name, _, _, original_size))| {
+				name == original_name && entry.data.len() as u64 == *original_size
+			})
+	}
+	
+	fn write_original_layout<W: Write + Seek>(&self, writer: &mut W, layout: &PakOriginalLayout) -> Result<()> {
+		let header = PakHeader {
+			file_count: self.files.len().try_into().map_err(|_| Error::CountOverflow { offset: 4 })?,
+			data_table_offset: layout.data_table_offset,
+			name_table_offset: layout.name_table_offset,
+		};
+		let data_pointers: Vec<u64> = layout.files.iter().map(|file| file.1).collect();
+		let name_pointers: Vec<u64> = layout.files.iter().map(|file| file.2).collect();
+		
+		let mut pieces = Vec::<(u64, Cow<[u8]>)>::new();
+		pieces.push((0, struct_to_bytes(&header, 0)?.into()));
+		pieces.push((layout.data_table_offset, struct_to_bytes(&data_pointers, layout.data_table_offset)?.into()));
+		pieces.push((layout.name_table_offset, struct_to_bytes(&name_pointers, layout.name_table_offset)?.into()));
+		for ((name, entry), &(_, header_offset, name_pointer, _)) in self.files.iter().zip(&layout.files) {
+			let file_header = PakFileHeader {
+				file_size: entry.data.len() as u64,
+				idk1: entry.idk1,
+				idk2: entry.idk2,
+				idk3: entry.idk3,
+			};
+			pieces.push((name_pointer, name.as_bytes_with_nul().into()));
+			pieces.push((header_offset, struct_to_bytes(&file_header, header_offset)?.into()));
+			pieces.push((header_offset + PakFileHeader::SIZE, entry.data.as_slice().into()));
+		}
+		write_pieces(writer, pieces, layout.archive_length)?;
+		Ok(())
+	}
+	
+	fn write_new_layout<W: Write + Seek>(&self, writer: &mut W) -> Result<()> {
+		let file_count = self.files.len();
+		let table_size = file_count as u64 * 8;
+		
+		let data_table_offset = PakHeader::SIZE;
+		let name_table_offset = data_table_offset + table_size;
+		
+		let mut position = name_table_offset + table_size;
+		let mut name_pointers = Vec::<u64>::with_capacity(file_count);
+		for (name, _) in &self.files {
+			name_pointers.push(position);
+			position += name.as_bytes_with_nul().len() as u64;
+		}
+		
+		let mut data_pointers = Vec::<u64>::with_capacity(file_count);
+		for (_, entry) in &self.files {
+			position = position.next_multiple_of(Self::FILE_ALIGNMENT);
+			data_pointers.push(position);
+			position += PakFileHeader::SIZE + entry.data.len() as u64;
+		}
+		
+		let header = PakHeader {
+			file_count: file_count.try_into().map_err(|_| Error::CountOverflow { offset: 4 })?,
+			data_table_offset,
+			name_table_offset,
+		};
+		
+		writer.rewind()?;
+		header.write(writer).map_err(|e| Error::from_binrw(e, 0))?;
+		data_pointers.write_le(writer).map_err(|e| Error::from_binrw(e, data_table_offset))?;
+		name_pointers.write_le(writer).map_err(|e| Error::from_binrw(e, name_table_offset))?;
+		for (name, _) in &self.files {
+			writer.write_all(name.as_bytes_with_nul())?;
+		}
+		
+		for ((_, entry), data_pointer) in self.files.iter().zip(data_pointers) {
+			write_padding(writer, data_pointer)?;
+			let file_header = PakFileHeader {
+				file_size: entry.data.len() as u64,
+				idk1: entry.idk1,
+				idk2: entry.idk2,
+				idk3: entry.idk3,
+			};
+			file_header.write(writer).map_err(|e| Error::from_binrw(e, data_pointer))?;
+			writer.write_all(&entry.data)?;
+		}
+		
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+	
+	use crate::annotate::assert_layout;
+	
+	fn sample_writer() -> PakWriter {
+		let mut pak = PakWriter::new();
+		pak.add_file(c"hello.txt".into(), b"Hello!".to_vec());
+		pak.add_file(c"dir/empty".into(), Vec::new());
+		pak.add_file(c"dir/data.bin".into(), (0..=255).collect());
+		pak
+	}
+	
+	fn write_to_vec(pak: &PakWriter) -> Vec<u8> {
+		let mut writer = Cursor::new(Vec::<u8>::new());
+		pak.write(&mut writer).unwrap();
+		writer.into_inner()
+	}
+	
+	#[test]
+	fn write_single_file() {
+		let mut pak = PakWriter::new();
+		pak.add_file(c"a".into(), b"xyz".to_vec());
+		
+		let mut expected = Vec::<u8>::new();
+		expected.extend([0x00, 0x00, 0x00, 0x00]);
+		expected.extend(1u32.to_le_bytes());
+		expected.extend(0x18u64.to_le_bytes());
+		expected.extend(0x20u64.to_le_bytes());
+		// Data pointer table, name pointer table, then the name itself
+		expected.extend(0x30u64.to_le_bytes());
+		expected.extend(0x28u64.to_le_bytes());
+		expected.extend(b"a\0");
+		// Padding up to the file header
+		expected.extend([0u8; 6]);
+		expected.extend(3u64.to_le_bytes());
+		expected.extend([0u8; 24]);
+		expected.extend(b"xyz");
+		
+		assert_eq!(write_to_vec(&pak), expected);
+	}
+	
+	#[test]
+	fn layouts_match_fields() {
+		assert_eq!(PakHeader::layout_size(), PakHeader::SIZE);
+		assert_eq!(PakFileHeader::layout_size(), PakFileHeader::SIZE);
+		
+		let header = PakHeader { file_count: 1, data_table_offset: 2, name_table_offset: 3 };
+		assert_layout(&header, &[
+			("magic", &[0; 4]),
+			("file_count", &1u32.to_le_bytes()),
+			("data_table_offset", &2u64.to_le_bytes()),
+			("name_table_offset", &3u64.to_le_bytes()),
+		]);
+		let file_header = PakFileHeader { file_size: 1, idk1: 2, idk2: 3, idk3: 4 };
+		assert_layout(&file_header, &[
+			("file_size", &1u64.to_le_bytes()),
+			("idk1", &2u64.to_le_bytes()),
+			("idk2", &3u64.to_le_bytes()),
+			("idk3", &4u64.to_le_bytes()),
+		]);
+	}
+	
+	#[test]
+	fn annotated_single_file() {
+		let mut pak = PakWriter::new();
+		pak.add_file(c"a".into(), b"xyz".to_vec());
+		let mut reader = Cursor::new(write_to_vec(&pak));
+		let mut annotations = Annotations::new();
+		PakIndex::create_index_annotated(&mut reader, &mut annotations).unwrap();
+		
+		let top_level: Vec<_> = annotations.iter()
+			.filter(|a| a.parent.is_none())
+			.map(|a| (a.name.as_str(), a.offset, a.length))
+			.collect();
+		assert_eq!(top_level, vec![
+			("PakHeader", 0, 24),
+			("Data pointer table", 0x18, 8),
+			("Name pointer table", 0x20, 8),
+			("Name of file 0", 0x28, 2),
+			("File 0: a", 0x30, 35),
+		]);
+		let file = annotations.iter().position(|a| a.name == "File 0: a");
+		let header = annotations.child(file, "PakFileHeader");
+		assert_eq!(annotations.get(annotations.child(header, "file_size").unwrap()).unwrap().offset, 0x30);
+		assert_eq!(annotations.get(annotations.child(file, "Contents").unwrap()).unwrap().offset, 0x50);
+	}
+	
+	#[test]
+	fn written_archive_is_readable() {
+		let pak = sample_writer();
+		let mut reader = Cursor::new(write_to_vec(&pak));
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		
+		assert_eq!(index.files.len(), pak.files.len());
+		for ((read_name, read_entry), (name, entry)) in index.files.iter().zip(&pak.files) {
+			assert_eq!(read_name, name);
+			assert_eq!(read_entry.data_start % PakWriter::FILE_ALIGNMENT, PakFileHeader::SIZE % PakWriter::FILE_ALIGNMENT);
+			assert_eq!(read_whole_file(read_entry, &mut reader).unwrap(), entry.data);
+		}
+	}
+	
+	#[test]
+	fn round_trip_is_identical() {
+		let original = write_to_vec(&sample_writer());
+		let mut reader = Cursor::new(original.clone());
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		let rebuilt = PakWriter::from_index(&index, &mut reader).unwrap();
+		
+		assert_eq!(write_to_vec(&rebuilt), original);
+	}
+	
+	/// An archive laid out differently from [`PakWriter`]'s own layout: the name table comes before the data table,
+	/// the names and files are stored backwards, files aren't aligned, and there's padding at the end.
+	fn foreign_layout() -> Vec<u8> {
+		let mut bytes = Vec::<u8>::new();
+		bytes.extend([0x00, 0x00, 0x00, 0x00]);
+		bytes.extend(2u32.to_le_bytes());
+		bytes.extend(0x28u64.to_le_bytes());
+		bytes.extend(0x18u64.to_le_bytes());
+		// Name pointer table, then data pointer table
+		bytes.extend(0x3Au64.to_le_bytes());
+		bytes.extend(0x38u64.to_le_bytes());
+		bytes.extend(0x68u64.to_le_bytes());
+		bytes.extend(0x44u64.to_le_bytes());
+		bytes.extend(b"z\0first\0");
+		bytes.extend([0u8; 4]);
+		// The second file, then the first
+		bytes.extend(2u64.to_le_bytes());
+		bytes.extend([7u8; 24]);
+		bytes.extend(b"hi");
+		bytes.extend([0u8; 2]);
+		bytes.extend(3u64.to_le_bytes());
+		bytes.extend([0u8; 8]);
+		bytes.extend(0x1234u64.to_le_bytes());
+		bytes.extend([0u8; 8]);
+		bytes.extend(b"abc");
+		bytes.extend([0u8; 5]);
+		bytes
+	}
+	
+	#[test]
+	fn foreign_layout_round_trip_is_identical() {
+		let original = foreign_layout();
+		let mut reader = Cursor::new(original.clone());
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		let names: Vec<_> = index.files.iter().map(|(name, _)| name.as_c_str()).collect();
+		assert_eq!(names, [c"first", c"z"]);
+		assert_eq!(index.archive_length, 0x90);
+		
+		let rebuilt = PakWriter::from_index(&index, &mut reader).unwrap();
+		assert_eq!(rebuilt.files[0].1.data, b"abc");
+		assert_eq!(rebuilt.files[0].1.idk2, 0x1234);
+		assert_eq!(write_to_vec(&rebuilt), original);
+	}
+	
+	#[test]
+	fn replaced_files_keep_the_layout_only_if_they_fit() {
+		let original = foreign_layout();
+		let mut reader = Cursor::new(original.clone());
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		
+		let mut same_size = PakWriter::from_index(&index, &mut reader).unwrap();
+		assert!(same_size.replace_file(c"first", b"xyz".to_vec()));
+		let mut expected = original.clone();
+		expected[0x88..0x8B].copy_from_slice(b"xyz");
+		assert_eq!(write_to_vec(&same_size), expected);
+		
+		let mut bigger = PakWriter::from_index(&index, &mut reader).unwrap();
+		assert!(bigger.replace_file(c"first", b"longer contents".to_vec()));
+		let mut rewritten = Cursor::new(write_to_vec(&bigger));
+		let rewritten_index = PakIndex::create_index(&mut rewritten).unwrap();
+		assert_eq!(rewritten_index.data_table_offset, PakHeader::SIZE);
+		let contents: Vec<_> = rewritten_index.files.iter()
+			.map(|(_, entry)| read_whole_file(entry, &mut rewritten).unwrap())
+			.collect();
+		assert_eq!(contents, [b"longer contents".to_vec(), b"hi".to_vec()]);
+	}
+	
+	#[test]
+	fn unknown_fields_are_preserved() {
+		let mut pak = sample_writer();
+		pak.files[1].1.idk1 = 0x1111;
+		pak.files[1].1.idk2 = 0x2222_0000_0000;
+		pak.files[1].1.idk3 = u64::MAX;
+		let original = write_to_vec(&pak);
+		
+		let mut reader = Cursor::new(original.clone());
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		let entry = &index.files[1].1;
+		assert_eq!((entry.idk1, entry.idk2, entry.idk3), (0x1111, 0x2222_0000_0000, u64::MAX));
+		assert_eq!(entry.data_start, entry.header_offset + PakFileHeader::SIZE);
+		
+		let mut name_reader = Cursor::new(&original[entry.name_pointer as usize..]);
+		let mut name_buf = Vec::new();
+		name_reader.read_until(0, &mut name_buf).unwrap();
+		assert_eq!(name_buf, b"dir/empty\0");
+		
+		let rebuilt = PakWriter::from_index(&index, &mut reader).unwrap();
+		assert_eq!(write_to_vec(&rebuilt), original);
+	}
+	
+	#[test]
+	fn entry_reader_is_bounded() {
+		let pak = sample_writer();
+		let mut reader = Cursor::new(write_to_vec(&pak));
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		
+		for ((_, entry), (_, expected)) in index.files.iter().zip(&pak.files) {
+			let mut entry_reader = PakEntryReader::new(entry, &mut reader).unwrap();
+			assert_eq!(entry_reader.len(), expected.data.len() as u64);
+			let mut buf = Vec::new();
+			entry_reader.read_to_end(&mut buf).unwrap();
+			assert_eq!(buf, expected.data);
+		}
+	}
+	
+	#[test]
+	fn entry_reader_seeks_within_entry() {
+		let mut reader = Cursor::new(write_to_vec(&sample_writer()));
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		let mut entry_reader = PakEntryReader::new(&index.files[2].1, &mut reader).unwrap();
+		
+		assert_eq!(entry_reader.seek(SeekFrom::End(-6)).unwrap(), 250);
+		let mut buf = Vec::new();
+		entry_reader.read_until(0, &mut buf).unwrap();
+		assert_eq!(buf, [250, 251, 252, 253, 254, 255]);
+		
+		entry_reader.rewind().unwrap();
+		let mut buf = [0u8; 3];
+		entry_reader.read_exact(&mut buf).unwrap();
+		assert_eq!(buf, [0, 1, 2]);
+		assert_eq!(entry_reader.stream_position().unwrap(), 3);
+		
+		assert_eq!(entry_reader.seek(SeekFrom::Current(1000)).unwrap(), 1003);
+		assert_eq!(entry_reader.fill_buf().unwrap(), &[]);
+		assert!(entry_reader.seek(SeekFrom::Current(-2000)).is_err());
+		let error = entry_reader.seek(SeekFrom::Start(u64::MAX)).unwrap_err();
+		assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+		assert_eq!(entry_reader.stream_position().unwrap(), 1003);
+	}
+	
+	#[test]
+	fn damaged_archives_report_offsets() {
+		let original = write_to_vec(&sample_writer());
+		
+		let mut bad_magic = original.clone();
+		bad_magic[0] = 1;
+		assert!(matches!(PakIndex::create_index(&mut Cursor::new(bad_magic)), Err(Error::BadMagic { offset: 0 })));
+		
+		let truncated_header = &original[..10];
+		assert!(matches!(PakIndex::create_index(&mut Cursor::new(truncated_header)), Err(Error::Truncated { offset: 0 })));
+		
+		let truncated_table = &original[..0x20];
+		assert!(matches!(
+			PakIndex::create_index(&mut Cursor::new(truncated_table)),
+			Err(Error::TruncatedTable { offset: 0x18, count: 3 }),
+		));
+		
+		let mut huge_count = original.clone();
+		huge_count[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+		assert!(matches!(
+			PakIndex::create_index(&mut Cursor::new(huge_count)),
+			Err(Error::TruncatedTable { offset: 0x18, count: 0xFFFF_FFFF }),
+		));
+		
+		let mut name_table_past_end = original.clone();
+		name_table_past_end[16..24].copy_from_slice(&0xFFFF_0000u64.to_le_bytes());
+		assert!(matches!(
+			PakIndex::create_index(&mut Cursor::new(name_table_past_end)),
+			Err(Error::PointerOutOfBounds { offset: 16, pointer: 0xFFFF_0000 }),
+		));
+		
+		let mut tables_overlap = original.clone();
+		tables_overlap[16..24].copy_from_slice(&0x20u64.to_le_bytes());
+		assert!(matches!(
+			PakIndex::create_index(&mut Cursor::new(tables_overlap)),
+			Err(Error::OverlappingRegions { offset: 0x20, other: 0x18 }),
+		));
+		
+		let index = PakIndex::create_index(&mut Cursor::new(&original)).unwrap();
+		let first_header = index.files[0].1.header_offset as usize;
+		
+		let mut file_too_long = original.clone();
+		file_too_long[first_header..first_header + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+		assert!(matches!(
+			PakIndex::create_index(&mut Cursor::new(file_too_long)),
+			Err(Error::Truncated { offset }) if offset == index.files[0].1.data_start,
+		));
+		
+		// Point the second file at the first file's header
+		let mut files_overlap = original.clone();
+		files_overlap[0x20..0x28].copy_from_slice(&(first_header as u64).to_le_bytes());
+		assert!(matches!(
+			PakIndex::create_index(&mut Cursor::new(files_overlap)),
+			Err(Error::OverlappingRegions { offset, other }) if offset == first_header as u64 && other == offset,
+		));
+		
+		// Chop off the NUL at the end of the last name
+		let mut unterminated = original.clone();
+		let last_name_pointer = index.files[2].1.name_pointer;
+		unterminated.truncate(last_name_pointer as usize + 4);
+		assert!(matches!(
+			PakIndex::create_index(&mut Cursor::new(unterminated)),
+			Err(Error::MissingNulTerminator { offset }) if offset == last_name_pointer,
+		));
+	}
+	
+	#[test]
+	fn safe_relative_paths() {
+		assert_eq!(safe_relative_path(c"a/b.stl"), Some(["a", "b.stl"].iter().collect()));
+		assert_eq!(safe_relative_path(c"a\\b\\c.png"), Some(["a", "b", "c.png"].iter().collect()));
+		for unsafe_name in [c"", c"../b", c"a/../../b", c"/etc/passwd", c"\\server\\x", c"C:\\x", c"a//b", c"./a"] {
+			assert_eq!(safe_relative_path(unsafe_name), None, "{unsafe_name:?}");
+		}
+	}
+	
+	/// A fresh, empty directory for a test to write into.
+	fn test_directory(name: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("excavator_pak_{name}_{}", std::process::id()));
+		let _ = fs::remove_dir_all(&path);
+		fs::create_dir_all(&path).unwrap();
+		path
+	}
+	
+	#[test]
+	fn extract_all_files() {
+		let pak = sample_writer();
+		let mut reader = Cursor::new(write_to_vec(&pak));
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		let destination = test_directory("extract_all");
+		
+		let report = extract_all(&index, &mut reader, &destination).unwrap();
+		assert_eq!(report.extracted.len(), 3);
+		assert!(report.collisions.is_empty());
+		assert_eq!(fs::read(destination.join("hello.txt")).unwrap(), b"Hello!");
+		assert_eq!(fs::read(destination.join("dir/empty")).unwrap(), b"");
+		assert_eq!(fs::read(destination.join("dir/data.bin")).unwrap(), pak.files[2].1.data);
+		
+		// Extracting again leaves what's there alone
+		fs::write(destination.join("hello.txt"), b"Edited").unwrap();
+		let report = extract_all(&index, &mut reader, &destination).unwrap();
+		assert!(report.extracted.is_empty());
+		assert_eq!(report.collisions.len(), 3);
+		assert_eq!(fs::read(destination.join("hello.txt")).unwrap(), b"Edited");
+		fs::remove_dir_all(destination).unwrap();
+	}
+	
+	#[test]
+	fn extract_all_reports_collisions() {
+		let mut pak = PakWriter::new();
+		pak.add_file(c"a/b".into(), b"first".to_vec());
+		pak.add_file(c"A\\B".into(), b"same path".to_vec());
+		pak.add_file(c"a/b/c".into(), b"under a file".to_vec());
+		pak.add_file(c"a".into(), b"over a directory".to_vec());
+		pak.add_file(c"a/d".into(), b"fine".to_vec());
+		let mut reader = Cursor::new(write_to_vec(&pak));
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		let destination = test_directory("collisions");
+		
+		let report = extract_all(&index, &mut reader, &destination).unwrap();
+		let collided: Vec<&CStr> = report.collisions.iter().map(|c| c.name.as_c_str()).collect();
+		assert_eq!(collided, [c"A\\B", c"a/b/c", c"a"]);
+		assert_eq!(report.extracted.len(), 2);
+		assert_eq!(fs::read(destination.join("a/b")).unwrap(), b"first");
+		fs::remove_dir_all(destination).unwrap();
+	}
+	
+	#[test]
+	fn extract_selected_files() {
+		let mut pak = sample_writer();
+		pak.add_file(c"../escape".into(), Vec::new());
+		pak.add_file(c"DIR/EMPTY".into(), b"same path".to_vec());
+		let mut reader = Cursor::new(write_to_vec(&pak));
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		let destination = test_directory("selected");
+		fs::write(destination.join("hello.txt"), b"Already here").unwrap();
+		
+		// The unsafe name isn't selected, so it doesn't stop anything
+		let report = extract_selected(&index, &mut reader, &destination, |name| name != c"../escape").unwrap();
+		assert_eq!(report.extracted, [destination.join("dir/empty"), destination.join("dir/data.bin")]);
+		let collided: Vec<&CStr> = report.collisions.iter().map(|c| c.name.as_c_str()).collect();
+		assert_eq!(collided, [c"hello.txt", c"DIR/EMPTY"]);
+		assert_eq!(fs::read(destination.join("hello.txt")).unwrap(), b"Already here");
+		assert_eq!(fs::read(destination.join("dir/empty")).unwrap(), b"");
+		fs::remove_dir_all(destination).unwrap();
+	}
+	
+	#[test]
+	fn extract_all_refuses_traversal() {
+		let mut pak = PakWriter::new();
+		pak.add_file(c"fine".into(), Vec::new());
+		pak.add_file(c"../escape".into(), Vec::new());
+		let mut reader = Cursor::new(write_to_vec(&pak));
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		let destination = test_directory("traversal");
+		
+		let result = extract_all(&index, &mut reader, &destination);
+		assert!(matches!(result, Err(Error::UnsafeName { offset, .. }) if offset == index.files[1].1.name_pointer));
+		// Nothing gets written if any name is unsafe
+		assert!(!destination.join("fine").exists());
+		fs::remove_dir_all(destination).unwrap();
+	}
+	
+	#[test]
+	fn replace_file_contents() {
+		let mut pak = sample_writer();
+		assert!(pak.replace_file(c"dir/empty", b"not anymore".to_vec()));
+		assert!(!pak.replace_file(c"missing", Vec::new()));
+		
+		let mut reader = Cursor::new(write_to_vec(&pak));
+		let index = PakIndex::create_index(&mut reader).unwrap();
+		let entry = &index.files.iter().find(|f| f.0.as_c_str() == c"dir/empty").unwrap().1;
+		assert_eq!(read_whole_file(entry, &mut reader).unwrap(), b"not anymore");
+	}
+}
@@ -0,0 +1,424 @@
+//! Guessing a file's type from its contents, for files that have been renamed or have no extension.
+//!
+//! None of the game's formats have a real signature, so most checks look at whether the header's counts and pointers
+//! make sense for the length of the file. When the tables they point to start within the sniffed bytes,
+//! the pointers stored in those tables are checked too, which is much harder to pass by accident.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::FileType;
+
+/// How sure a [`Detection`] is.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Confidence {
+	/// Nothing suggests any type. Only used with [`FileType::Unknown`].
+	None,
+	/// Only the extension suggests the type. The contents don't look like it, so reading the file will probably fail.
+	Low,
+	/// The header makes sense for the type, but could also be a coincidence.
+	Medium,
+	/// The contents have the type's signature, or the header makes sense and so do the pointers in the tables it points to,
+	/// or the header makes sense and the extension agrees.
+	High,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Detection {
+	pub file_type: FileType,
+	pub confidence: Confidence,
+}
+
+impl Detection {
+	pub const UNKNOWN: Self = Self { file_type: FileType::Unknown, confidence: Confidence::None };
+}
+
+/// How many bytes from the start of a file the checks look at.
+pub const SNIFF_LENGTH: usize = 144;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1A\n";
+
+/// The types that can be sniffed, in order of preference when two are equally likely.
+const SNIFFABLE: [FileType; 6] = [
+	FileType::Png,
+	FileType::Pak,
+	FileType::StmOrStb,
+	FileType::Stl,
+	FileType::Ltb,
+	FileType::Lvb,
+];
+
+fn u32_at(prefix: &[u8], offset: usize) -> Option<u64> {
+	let bytes = prefix.get(offset..offset + 4)?;
+	Some(u32::from_le_bytes(bytes.try_into().unwrap()).into())
+}
+
+fn u64_at(prefix: &[u8], offset: usize) -> Option<u64> {
+	let bytes = prefix.get(offset..offset + 8)?;
+	Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn zeros_at(prefix: &[u8], offset: usize, length: usize) -> bool {
+	prefix.get(offset..offset + length).is_some_and(|bytes| bytes.iter().all(|&b| b == 0))
+}
+
+/// Whether a table of `count` entries of `entry_size` bytes at `start` fits between the header and the end of the file.
+fn table_fits(start: u64, count: u64, entry_size: u64, header_size: u64, file_length: u64) -> bool {
+	let end = count.checked_mul(entry_size).and_then(|size| start.checked_add(size));
+	start >= header_size && end.is_some_and(|end| end <= file_length)
+}
+
+/// The bytes taken by a table of `count` entries of `entry_size` bytes at `start`, or `None` if it would end past `u64::MAX`.
+fn table_range(start: u64, count: u64, entry_size: u64) -> Option<Range<u64>> {
+	let end = count.checked_mul(entry_size).and_then(|size| start.checked_add(size))?;
+	Some(start..end)
+}
+
+fn overlap(a: &Range<u64>, b: &Range<u64>) -> bool {
+	a.start < b.end && b.start < a.end
+}
+
+/// Whether the pointers in a table of `count` pointers at `start` all point into the file after the header,
+/// for the ones within `prefix`. `None` if the table doesn't start within `prefix`.
+fn pointers_fit(prefix: &[u8], start: u64, count: u64, header_size: u64, file_length: u64) -> Option<bool> {
+	let start = usize::try_from(start).ok()?;
+	let visible = usize::try_from(count).unwrap_or(usize::MAX).min(prefix.len().saturating_sub(start) / 8);
+	if visible == 0 {
+		return None;
+	}
+	Some((0..visible).all(|i| u64_at(prefix, start + i * 8).is_some_and(|pointer| (header_size..file_length).contains(&pointer))))
+}
+
+/// Pointers that don't fit rule the type out, and ones that do make it likely. Tables past the prefix can't say either way.
+fn confidence_from_pointers(checks: &[Option<bool>]) -> Option<Confidence> {
+	if checks.contains(&Some(false)) {
+		None
+	} else if checks.contains(&Some(true)) {
+		Some(Confidence::High)
+	} else {
+		Some(Confidence::Medium)
+	}
+}
+
+fn confidence_if(exact_layout: bool) -> Confidence {
+	if exact_layout { Confidence::High } else { Confidence::Medium }
+}
+
+fn check_png(prefix: &[u8], _file_length: u64) -> Option<Confidence> {
+	prefix.starts_with(PNG_SIGNATURE).then_some(Confidence::High)
+}
+
+fn check_pak(prefix: &[u8], file_length: u64) -> Option<Confidence> {
+	const HEADER_SIZE: u64 = 24;
+	if !zeros_at(prefix, 0, 4) {
+		return None;
+	}
+	let count = u32_at(prefix, 4)?;
+	let data_table = u64_at(prefix, 8)?;
+	let name_table = u64_at(prefix, 16)?;
+	if count == 0 {
+		// An empty archive is just a header, which is too easy to find by accident in anything else.
+		let just_a_header = data_table == HEADER_SIZE && name_table == HEADER_SIZE && file_length == HEADER_SIZE;
+		return just_a_header.then_some(Confidence::Medium);
+	}
+	
+	let tables_fit = table_fits(data_table, count, 8, HEADER_SIZE, file_length)
+		&& table_fits(name_table, count, 8, HEADER_SIZE, file_length);
+	if !tables_fit || overlap(&table_range(data_table, count, 8)?, &table_range(name_table, count, 8)?) {
+		return None;
+	}
+	confidence_from_pointers(&[
+		pointers_fit(prefix, data_table, count, HEADER_SIZE, file_length),
+		pointers_fit(prefix, name_table, count, HEADER_SIZE, file_length),
+	])
+}
+
+fn check_stl(prefix: &[u8], file_length: u64) -> Option<Confidence> {
+	const HEADER_SIZE: u64 = 24;
+	if !zeros_at(prefix, 0, 8) {
+		return None;
+	}
+	let string_count = u32_at(prefix, 8)?.checked_mul(u32_at(prefix, 12)?)?;
+	let data_pointer = u64_at(prefix, 16)?;
+	if !table_fits(data_pointer, string_count, 8, HEADER_SIZE, file_length) {
+		return None;
+	}
+	confidence_from_pointers(&[pointers_fit(prefix, data_pointer, string_count, HEADER_SIZE, file_length)])
+}
+
+fn check_stm(prefix: &[u8], file_length: u64) -> Option<Confidence> {
+	const HEADER_SIZE: u64 = 64;
+	if !zeros_at(prefix, 0, 8) || !zeros_at(prefix, 32, 4) || !zeros_at(prefix, 48, 4) {
+		return None;
+	}
+	let string_count = u32_at(prefix, 8)?.checked_mul(u32_at(prefix, 12)?)?;
+	let checksums_pointer = u64_at(prefix, 16)?;
+	let data_pointer = u64_at(prefix, 24)?;
+	let extras = [(u32_at(prefix, 36)?, u64_at(prefix, 40)?), (u32_at(prefix, 52)?, u64_at(prefix, 56)?)];
+	
+	let plausible = table_fits(checksums_pointer, string_count, 8, HEADER_SIZE, file_length)
+		&& table_fits(data_pointer, string_count, 8, HEADER_SIZE, file_length)
+		&& extras.iter().all(|&(count, pointer)| table_fits(pointer, count, 8, HEADER_SIZE, file_length));
+	if !plausible {
+		return None;
+	}
+	
+	// Every table has its own place in the file
+	let mut tables = vec![table_range(checksums_pointer, string_count, 8)?, table_range(data_pointer, string_count, 8)?];
+	for &(count, pointer) in &extras {
+		if count > 0 {
+			tables.push(table_range(pointer, count, 8)?);
+		}
+	}
+	let overlapping = tables.iter().enumerate().any(|(i, a)| tables[i + 1..].iter().any(|b| overlap(a, b)));
+	if overlapping {
+		return None;
+	}
+	confidence_from_pointers(&[
+		pointers_fit(prefix, data_pointer, string_count, HEADER_SIZE, file_length),
+		pointers_fit(prefix, extras[0].1, extras[0].0, HEADER_SIZE, file_length),
+		pointers_fit(prefix, extras[1].1, extras[1].0, HEADER_SIZE, file_length),
+	])
+}
+
+/// Whether every pointer in a table of level header elements is either unused or inside the file.
+fn level_elements_fit(prefix: &[u8], start: usize, count: usize, header_size: u64, file_length: u64) -> Option<bool> {
+	(0..count).map(|i| u64_at(prefix, start + i * 16 + 8)).try_fold(true, |fits, pointer| {
+		let pointer = pointer?;
+		Some(fits && (pointer == 0 || (header_size..=file_length).contains(&pointer)))
+	})
+}
+
+fn check_ltb(prefix: &[u8], file_length: u64) -> Option<Confidence> {
+	const HEADER_SIZE: u64 = 144;
+	if !zeros_at(prefix, 0, 8) || !level_elements_fit(prefix, 16, 8, HEADER_SIZE, file_length)? {
+		return None;
+	}
+	let layer_count = u32_at(prefix, 20)?;
+	let layers_pointer = u64_at(prefix, 24)?;
+	(layer_count > 0 && table_fits(layers_pointer, layer_count, 128, HEADER_SIZE, file_length))
+		.then(|| confidence_if(layers_pointer == HEADER_SIZE))
+}
+
+fn check_lvb(prefix: &[u8], file_length: u64) -> Option<Confidence> {
+	const HEADER_SIZE: u64 = 112;
+	if !zeros_at(prefix, 0, 4) || !level_elements_fit(prefix, 0, 7, HEADER_SIZE, file_length)? {
+		return None;
+	}
+	let object_count = u32_at(prefix, 16)?;
+	let objects_pointer = u64_at(prefix, 24)?;
+	if object_count == 0 || !table_fits(objects_pointer, object_count, 48, HEADER_SIZE, file_length) {
+		return None;
+	}
+	// Every object starts with four zero bytes. The first one can only be checked when it's right after the header.
+	let first_object_visible = usize::try_from(objects_pointer).ok().filter(|&pointer| pointer + 4 <= prefix.len());
+	match first_object_visible {
+		Some(pointer) if !zeros_at(prefix, pointer, 4) => None,
+		Some(_) => Some(Confidence::High),
+		None => Some(Confidence::Medium),
+	}
+}
+
+fn check(file_type: FileType, prefix: &[u8], file_length: u64) -> Option<Confidence> {
+	match file_type {
+		FileType::Unknown => None,
+		FileType::Png => check_png(prefix, file_length),
+		FileType::Pak => check_pak(prefix, file_length),
+		FileType::StmOrStb => check_stm(prefix, file_length),
+		FileType::Stl => check_stl(prefix, file_length),
+		FileType::Ltb => check_ltb(prefix, file_length),
+		FileType::Lvb => check_lvb(prefix, file_length),
+	}
+}
+
+impl FileType {
+	/// Guesses the type of a file from its first [`SNIFF_LENGTH`] bytes (or all of them, if it's shorter) and its length.
+	pub fn sniff(prefix: &[u8], file_length: u64) -> Detection {
+		let mut best = Detection::UNKNOWN;
+		for file_type in SNIFFABLE {
+			if let Some(confidence) = check(file_type, prefix, file_length) && confidence > best.confidence {
+				best = Detection { file_type, confidence };
+			}
+		}
+		best
+	}
+	
+	/// Guesses the type of a file from both its contents and the extension in `path`.
+	///
+	/// Some layouts are similar enough that a file can fit more than one, so when the contents make sense for the type the
+	/// extension suggests, that type wins unless another fits better.
+	pub fn detect(path: impl AsRef<Path>, prefix: &[u8], file_length: u64) -> Detection {
+		let from_extension = Self::from_path(path);
+		let sniffed = Self::sniff(prefix, file_length);
+		if check(from_extension, prefix, file_length).is_some_and(|confidence| confidence >= sniffed.confidence) {
+			return Detection { file_type: from_extension, confidence: Confidence::High };
+		}
+		if sniffed.confidence >= Confidence::Medium || from_extension == Self::Unknown {
+			sniffed
+		} else {
+			Detection { file_type: from_extension, confidence: Confidence::Low }
+		}
+	}
+	
+	/// Like [`Self::detect`], reading the start of the file from `reader`. The reader is rewound afterwards.
+	pub fn detect_reader<R: Read + Seek>(path: impl AsRef<Path>, reader: &mut R) -> io::Result<Detection> {
+		let file_length = reader.seek(SeekFrom::End(0))?;
+		reader.rewind()?;
+		let mut prefix = Vec::with_capacity(SNIFF_LENGTH);
+		reader.by_ref().take(SNIFF_LENGTH as u64).read_to_end(&mut prefix)?;
+		reader.rewind()?;
+		Ok(Self::detect(path, &prefix, file_length))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::pak::PakWriter;
+	use crate::st::{StEncoding, StExtendedData, StString, StTable, write_st};
+	use std::io::Cursor;
+	
+	fn pak_bytes() -> Vec<u8> {
+		let mut pak = PakWriter::new();
+		pak.add_file(c"a.txt".into(), b"Hello".to_vec());
+		pak.add_file(c"b.txt".into(), b"World".to_vec());
+		let mut writer = Cursor::new(Vec::new());
+		pak.write(&mut writer).unwrap();
+		writer.into_inner()
+	}
+	
+	fn st_bytes(extended: bool) -> Vec<u8> {
+		let strings: Vec<_> = ["Key", "Text", "A", "Apple"].iter()
+			.map(|text| StString::from_text(*text, StEncoding::Utf8).unwrap())
+			.collect();
+		let extended = extended.then(|| StExtendedData { checksums: vec![0; 4], extra1: vec![], extra2: vec![] });
+		let table = StTable { field_count: 2, strings, encoding: StEncoding::Utf8, extended, layout: None };
+		let mut writer = Cursor::new(Vec::new());
+		write_st(&mut writer, &table).unwrap();
+		writer.into_inner()
+	}
+	
+	/// An `.stl` with its pointer table moved to `offset`, past the end of the strings.
+	fn stl_with_table_at(offset: usize) -> Vec<u8> {
+		let mut bytes = st_bytes(false);
+		let table = bytes[24..56].to_vec();
+		assert!(offset >= bytes.len());
+		bytes.resize(offset, 0);
+		bytes.extend(table);
+		bytes[16..24].copy_from_slice(&(offset as u64).to_le_bytes());
+		bytes
+	}
+	
+	fn ltb_bytes() -> Vec<u8> {
+		let mut bytes = vec![0u8; 144 + 2 * 128];
+		bytes[8..16].copy_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0]);
+		bytes[20..24].copy_from_slice(&2u32.to_le_bytes());
+		bytes[24..32].copy_from_slice(&144u64.to_le_bytes());
+		bytes
+	}
+	
+	fn lvb_bytes() -> Vec<u8> {
+		let mut bytes = vec![0u8; 112 + 3 * 48];
+		bytes[16..20].copy_from_slice(&3u32.to_le_bytes());
+		bytes[24..32].copy_from_slice(&112u64.to_le_bytes());
+		bytes[116] = 7;
+		bytes
+	}
+	
+	fn sniff(bytes: &[u8]) -> Detection {
+		FileType::sniff(&bytes[..bytes.len().min(SNIFF_LENGTH)], bytes.len() as u64)
+	}
+	
+	fn detection(file_type: FileType, confidence: Confidence) -> Detection {
+		Detection { file_type, confidence }
+	}
+	
+	#[test]
+	fn sniff_known_layouts() {
+		assert_eq!(sniff(b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR"), detection(FileType::Png, Confidence::High));
+		assert_eq!(sniff(&pak_bytes()), detection(FileType::Pak, Confidence::High));
+		assert_eq!(sniff(&st_bytes(false)), detection(FileType::Stl, Confidence::High));
+		assert_eq!(sniff(&st_bytes(true)), detection(FileType::StmOrStb, Confidence::High));
+		assert_eq!(sniff(&ltb_bytes()), detection(FileType::Ltb, Confidence::High));
+		assert_eq!(sniff(&lvb_bytes()), detection(FileType::Lvb, Confidence::High));
+	}
+	
+	#[test]
+	fn sniff_rejects_nonsense() {
+		assert_eq!(sniff(b""), Detection::UNKNOWN);
+		assert_eq!(sniff(b"Just some text, nothing to see here"), Detection::UNKNOWN);
+		assert_eq!(sniff(&[0; 200]), Detection::UNKNOWN);
+		assert_eq!(sniff(&[0xFF; 200]), Detection::UNKNOWN);
+		
+		// Truncated files have pointers past the end
+		let pak = pak_bytes();
+		assert_ne!(sniff(&pak[..30]).file_type, FileType::Pak);
+		let ltb = ltb_bytes();
+		assert_eq!(sniff(&ltb[..200]), Detection::UNKNOWN);
+		
+		// A table whose pointers point outside the file
+		let mut stl = st_bytes(false);
+		stl[24..32].copy_from_slice(&4096u64.to_le_bytes());
+		assert_eq!(sniff(&stl), Detection::UNKNOWN);
+	}
+	
+	#[test]
+	fn sniff_huge_header_values() {
+		let mut pak = vec![0u8; 64];
+		pak[4..8].copy_from_slice(&1u32.to_le_bytes());
+		pak[8..16].copy_from_slice(&24u64.to_le_bytes());
+		pak[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+		assert_eq!(sniff(&pak), Detection::UNKNOWN);
+		
+		let mut stm = vec![0u8; 64];
+		stm[8..16].copy_from_slice(&[0xFF; 8]);
+		stm[16..24].copy_from_slice(&64u64.to_le_bytes());
+		assert_eq!(sniff(&stm), Detection::UNKNOWN);
+	}
+	
+	#[test]
+	fn sniff_unusual_layouts() {
+		// Tables somewhere other than where this crate puts them are just as good, as long as their pointers can be checked
+		assert_eq!(sniff(&stl_with_table_at(96)), detection(FileType::Stl, Confidence::High));
+		assert_eq!(sniff(&stl_with_table_at(160)), detection(FileType::Stl, Confidence::Medium));
+		
+		let mut lvb = lvb_bytes();
+		lvb.extend([0; 48]);
+		lvb[24..32].copy_from_slice(&160u64.to_le_bytes());
+		assert_eq!(sniff(&lvb), detection(FileType::Lvb, Confidence::Medium));
+	}
+	
+	#[test]
+	fn detect_with_extension() {
+		let stl = st_bytes(false);
+		let prefix = &stl[..stl.len().min(SNIFF_LENGTH)];
+		let length = stl.len() as u64;
+		assert_eq!(FileType::detect("strings.stl", prefix, length), detection(FileType::Stl, Confidence::High));
+		assert_eq!(FileType::detect("renamed.bin", prefix, length), detection(FileType::Stl, Confidence::High));
+		assert_eq!(FileType::detect("no_extension", prefix, length), detection(FileType::Stl, Confidence::High));
+		assert_eq!(FileType::detect("wrong.lvb", prefix, length), detection(FileType::Stl, Confidence::High));
+		
+		assert_eq!(FileType::detect("broken.stl", b"oops", 4), detection(FileType::Stl, Confidence::Low));
+		assert_eq!(FileType::detect("unknown.xyz", b"oops", 4), Detection::UNKNOWN);
+		
+		// An STM header also makes sense as an STL one, but it fits STM better
+		let stm = st_bytes(true);
+		let prefix = &stm[..stm.len().min(SNIFF_LENGTH)];
+		assert_eq!(FileType::detect("strings.stl", prefix, stm.len() as u64), detection(FileType::StmOrStb, Confidence::High));
+		
+		// A header that only makes sense is trusted more when the extension agrees
+		let stl = stl_with_table_at(160);
+		let prefix = &stl[..stl.len().min(SNIFF_LENGTH)];
+		assert_eq!(FileType::detect("strings.bin", prefix, stl.len() as u64), detection(FileType::Stl, Confidence::Medium));
+		assert_eq!(FileType::detect("strings.stl", prefix, stl.len() as u64), detection(FileType::Stl, Confidence::High));
+	}
+	
+	#[test]
+	fn detect_from_reader() {
+		let mut reader = Cursor::new(pak_bytes());
+		reader.set_position(10);
+		assert_eq!(FileType::detect_reader("", &mut reader).unwrap(), detection(FileType::Pak, Confidence::High));
+		assert_eq!(reader.position(), 0);
+	}
+}
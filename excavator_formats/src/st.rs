@@ -1,8 +1,20 @@
-use std::io::{BufRead, Seek, SeekFrom};
+pub mod edit;
+pub mod po;
+pub mod search;
+pub mod sheet;
 
-use binrw::{BinRead, BinResult, BinWrite, NullString};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{BufRead, Seek, SeekFrom, Write};
 
-use super::util_binary::read_pointers;
+use binrw::{BinRead, BinWrite};
+
+use crate::annotate::{Annotations, Layout};
+use crate::error::{Error, Result};
+use crate::util_binary::{
+	count_to_usize, read_nul_terminated, read_pointers, read_struct, seek_absolute, struct_to_bytes, write_padding, write_pieces,
+};
+use crate::validate::Regions;
 
 #[derive(BinRead, BinWrite, Copy, Clone, Eq, PartialEq, Debug)]
 #[brw(little, magic = b"\0\0\0\0\0\0\0\0")]
@@ -30,6 +42,38 @@ struct StbOrStmHeaderExtra {
 	pointer: u64,
 }
 
+impl Layout for StlHeader {
+	const NAME: &'static str = "StlHeader";
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("magic", 8),
+		("entry_count", 4),
+		("field_count", 4),
+		("data_pointer", 8),
+	];
+}
+
+impl Layout for StbOrStmHeader {
+	const NAME: &'static str = "StbOrStmHeader";
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("magic", 8),
+		("entry_count", 4),
+		("field_count", 4),
+		("checksums_pointer", 8),
+		("data_pointer", 8),
+		("extra1", 16),
+		("extra2", 16),
+	];
+}
+
+impl Layout for StbOrStmHeaderExtra {
+	const NAME: &'static str = "StbOrStmHeaderExtra";
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("magic", 4),
+		("extra_entry_count", 4),
+		("pointer", 8),
+	];
+}
+
 #[derive(BinRead, BinWrite, Copy, Clone, Debug)]
 #[brw(little, repr = u64)]
 enum MagicTen {
@@ -38,7 +82,6 @@ enum MagicTen {
 
 #[derive(BinRead, BinWrite, Clone, Debug)]
 #[brw(little)]
-#[expect(dead_code)] // we'll get back to this
 struct StbOrStmDataExtra {
 	piece_count: u64,
 	magic: MagicTen,
@@ -46,7 +89,15 @@ struct StbOrStmDataExtra {
 	pieces: Vec<(u32, u32)>,
 }
 
-// TODO: stick in an Option field with the stb/stm exclusive data. or maybe our own enum
+impl Layout for StbOrStmDataExtra {
+	const NAME: &'static str = "StbOrStmDataExtra";
+	/// The pieces that follow aren't included, since how many there are varies.
+	const FIELDS: &'static [(&'static str, u64)] = &[
+		("piece_count", 8),
+		("magic", 8),
+	];
+}
+
 #[derive(Copy, Clone, Debug)]
 struct StHeaderCommon {
 	entry_count: u32,
@@ -74,84 +125,612 @@ impl From<StbOrStmHeader> for StHeaderCommon {
 	}
 }
 
-pub struct StReadOutcome {
+/// The text encodings that strings in a table can be decoded with.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum StEncoding {
+	#[default]
+	Utf8,
+	ShiftJis,
+	Windows1252,
+}
+
+impl StEncoding {
+	pub const ALL: [Self; 3] = [Self::Utf8, Self::ShiftJis, Self::Windows1252];
+	
+	fn encoding(self) -> &'static encoding_rs::Encoding {
+		match self {
+			Self::Utf8 => encoding_rs::UTF_8,
+			Self::ShiftJis => encoding_rs::SHIFT_JIS,
+			Self::Windows1252 => encoding_rs::WINDOWS_1252,
+		}
+	}
+	
+	pub fn name(self) -> &'static str {
+		self.encoding().name()
+	}
+	
+	/// Returns the decoded text, and whether any bytes couldn't be decoded and were replaced.
+	pub fn decode(self, bytes: &[u8]) -> (String, bool) {
+		let (text, had_errors) = self.encoding().decode_without_bom_handling(bytes);
+		(text.into_owned(), had_errors)
+	}
+	
+	/// Returns `None` if some character can't be represented in this encoding.
+	pub fn encode(self, text: &str) -> Option<Vec<u8>> {
+		let (bytes, _, had_unmappable) = self.encoding().encode(text);
+		(!had_unmappable).then(|| bytes.into_owned())
+	}
+}
+
+/// A single string from a table, kept both as the bytes in the file and as decoded text.
+///
+/// The bytes are what get written back, so strings that don't decode cleanly survive a round trip unchanged.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StString {
+	raw: Vec<u8>,
+	text: String,
+	lossy: bool,
+}
+
+impl StString {
+	pub fn from_raw(raw: Vec<u8>, encoding: StEncoding) -> Self {
+		let (text, lossy) = encoding.decode(&raw);
+		Self { raw, text, lossy }
+	}
+	
+	/// Fails if the text can't be represented in `encoding`, or contains a NUL character.
+	pub fn from_text(text: impl Into<String>, encoding: StEncoding) -> Result<Self> {
+		let text = text.into();
+		let raw = encoding.encode(&text).ok_or_else(|| Error::Unwritable {
+			message: format!("{text:?} can't be encoded as {}", encoding.name()),
+		})?;
+		if raw.contains(&0) {
+			return Err(Error::Unwritable { message: format!("{text:?} contains a NUL character") });
+		}
+		Ok(Self { raw, text, lossy: false })
+	}
+	
+	/// The string's bytes as stored in the file, without the NUL terminator.
+	pub fn raw(&self) -> &[u8] {
+		&self.raw
+	}
+	
+	/// The decoded text. Bytes that couldn't be decoded are shown as U+FFFD.
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+	
+	/// Whether some bytes couldn't be decoded, meaning [`Self::text`] doesn't fully represent the string.
+	pub fn is_lossy(&self) -> bool {
+		self.lossy
+	}
+	
+	/// Decodes the bytes again with a different encoding.
+	fn redecode(&mut self, encoding: StEncoding) {
+		*self = Self::from_raw(std::mem::take(&mut self.raw), encoding);
+	}
+	
+	/// Whether the string's bytes are valid UTF-8, regardless of what encoding it was decoded with.
+	pub fn is_utf8(&self) -> bool {
+		std::str::from_utf8(&self.raw).is_ok()
+	}
+}
+
+/// Everything in a string table file.
+///
+/// The strings are stored row by row, with `field_count` strings per row.
+/// The first row seems to always hold the names of the fields.
+///
+/// Tables are compared by their contents, so [`Self::layout`] doesn't count.
+#[derive(Clone, Debug)]
+pub struct StTable {
 	pub field_count: usize,
-	pub strings: Vec<String>,
+	pub strings: Vec<StString>,
+	/// The encoding the strings were decoded with, and that edited strings are encoded with.
+	pub encoding: StEncoding,
+	/// The extra data found in `.stm` and `.stb` files. Always `None` for `.stl` files.
+	pub extended: Option<StExtendedData>,
+	/// Where everything was in the file the table was read from. `None` for tables made from scratch.
+	pub layout: Option<StLayout>,
+}
+
+impl PartialEq for StTable {
+	fn eq(&self, other: &Self) -> bool {
+		self.field_count == other.field_count
+			&& self.strings == other.strings
+			&& self.encoding == other.encoding
+			&& self.extended == other.extended
+	}
+}
+
+impl Eq for StTable {}
+
+/// The parts of a `.stm` or `.stb` file that `.stl` files don't have.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StExtendedData {
+	/// One value for each string, in the same order as [`StTable::strings`].
+	pub checksums: Vec<u64>,
+	pub extra1: Vec<StExtraEntry>,
+	pub extra2: Vec<StExtraEntry>,
+}
+
+/// One entry of either of the extra sections. What the pieces mean is unknown.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StExtraEntry {
+	pub pieces: Vec<(u32, u32)>,
+}
+
+/// Where the headers, tables, strings and extra entries were in a string table file, so [`write_st`] can put them back.
+#[derive(Clone, Debug)]
+pub struct StLayout {
+	file_length: u64,
+	data_pointer: u64,
+	/// The pointer and length of each string, without the NUL terminator.
+	strings: Vec<(u64, usize)>,
+	extended: Option<StExtendedLayout>,
+}
+
+#[derive(Clone, Debug)]
+struct StExtendedLayout {
+	checksums_pointer: u64,
+	extra1: StExtraLayout,
+	extra2: StExtraLayout,
+}
+
+#[derive(Clone, Debug)]
+struct StExtraLayout {
+	pointer: u64,
+	/// The pointer and piece count of each entry.
+	entries: Vec<(u64, usize)>,
 }
 
-pub fn read_st<R: BufRead + Seek>(reader: &mut R, stl: bool) -> BinResult<StReadOutcome> {
+impl StTable {
+	pub fn entry_count(&self) -> usize {
+		self.strings.len().checked_div(self.field_count).unwrap_or(0)
+	}
+	
+	/// Iterates over the rows of the table, including the first one.
+	pub fn rows(&self) -> impl Iterator<Item = &[StString]> {
+		self.strings.chunks(self.field_count.max(1))
+	}
+	
+	/// Replaces the text of the string at `index`, encoding it with the table's encoding.
+	pub fn set_text(&mut self, index: usize, text: impl Into<String>) -> Result<()> {
+		self.strings[index] = StString::from_text(text, self.encoding)?;
+		Ok(())
+	}
+	
+	/// Replaces a string's text if it's different.
+	/// Unchanged strings are left alone, so their original bytes survive even if they couldn't be decoded cleanly.
+	///
+	/// The stored checksum is kept, since [`string_checksum`] isn't confirmed. [`verify_checksums`] reports the mismatch.
+	fn update_text(&mut self, index: usize, text: String) -> Result<bool> {
+		if text == self.strings[index].text() {
+			return Ok(false);
+		}
+		self.set_text(index, text)?;
+		Ok(true)
+	}
+	
+	/// Decodes every string again with a different encoding. The bytes of the strings don't change.
+	pub fn redecode(&mut self, encoding: StEncoding) {
+		self.encoding = encoding;
+		for string in &mut self.strings {
+			string.redecode(encoding);
+		}
+	}
+	
+	/// The positions of every string that isn't valid UTF-8.
+	pub fn non_utf8_strings(&self) -> impl Iterator<Item = usize> {
+		self.strings.iter().enumerate().filter(|(_, string)| !string.is_utf8()).map(|(i, _)| i)
+	}
+}
+
+/// Reads a string table, decoding the strings as UTF-8.
+pub fn read_st<R: BufRead + Seek>(reader: &mut R, stl: bool) -> Result<StTable> {
+	read_st_with_encoding(reader, stl, StEncoding::Utf8)
+}
+
+pub fn read_st_with_encoding<R: BufRead + Seek>(reader: &mut R, stl: bool, encoding: StEncoding) -> Result<StTable> {
+	read_st_annotated(reader, stl, encoding, &mut Annotations::disabled())
+}
+
+/// Like [`read_st_with_encoding`], also recording the headers, tables, strings and extra entries it comes across.
+pub fn read_st_annotated<R: BufRead + Seek>(
+	reader: &mut R,
+	stl: bool,
+	encoding: StEncoding,
+	annotations: &mut Annotations,
+) -> Result<StTable> {
 	reader.rewind()?;
-	let header: StHeaderCommon = if stl {
-		StlHeader::read(reader)?.into()
+	let mut regions = Regions::new(reader)?;
+	let (header, extended_header, data_pointer_offset) = if stl {
+		let header = read_struct::<StlHeader, _>(reader)?;
+		annotations.add_struct::<StlHeader>(None, 0);
+		regions.claim(0, 24, 0)?;
+		(StHeaderCommon::from(header), None, 16)
 	} else {
-		StbOrStmHeader::read(reader)?.into()
+		let header = read_struct::<StbOrStmHeader, _>(reader)?;
+		let id = annotations.add_struct::<StbOrStmHeader>(None, 0);
+		annotations.add_fields::<StbOrStmHeaderExtra>(annotations.child(id, "extra1"), 32);
+		annotations.add_fields::<StbOrStmHeaderExtra>(annotations.child(id, "extra2"), 48);
+		regions.claim(0, 64, 0)?;
+		(StHeaderCommon::from(header), Some(header), 24)
 	};
 	
-	// TODO: checked conversion and multiplication
-	let entry_count = header.entry_count as usize;
-	let field_count = header.field_count as usize;
-	let raw_count = entry_count * field_count;
+	let entry_count = count_to_usize(header.entry_count, 8)?;
+	let field_count = count_to_usize(header.field_count, 12)?;
+	let raw_count = entry_count.checked_mul(field_count).ok_or(Error::CountOverflow { offset: 8 })?;
+	annotations.add(None, header.data_pointer, (raw_count as u64).saturating_mul(8), "String pointer table");
+	regions.claim_table(header.data_pointer, raw_count as u64, 8, data_pointer_offset)?;
 	
 	reader.seek(SeekFrom::Start(header.data_pointer))?;
 	let string_pointers = read_pointers(reader, raw_count)?;
-	let strings: Vec<String> = string_pointers.iter().map(|pointer| -> BinResult<_> {
+	let strings: Vec<StString> = string_pointers.iter().enumerate().map(|(i, pointer)| -> Result<_> {
+		// Strings aren't claimed, since identical strings might be shared
+		regions.check_pointer(*pointer, header.data_pointer + i as u64 * 8)?;
 		reader.seek(SeekFrom::Start(*pointer))?;
-		Ok(NullString::read_le(reader)?.to_string())
-	}).collect::<BinResult<_>>()?;
+		let raw = read_nul_terminated(reader)?;
+		annotations.add(None, *pointer, raw.len() as u64 + 1, format_args!("Row {}, field {}", i / field_count, i % field_count));
+		Ok(StString::from_raw(raw, encoding))
+	}).collect::<Result<_>>()?;
+	
+	let (extended, extended_layout) = match extended_header {
+		Some(header) => {
+			let (extended, layout) = read_extended(reader, &mut regions, annotations, &header, raw_count)?;
+			(Some(extended), Some(layout))
+		},
+		None => (None, None),
+	};
 	
-	Ok(StReadOutcome {
+	let layout = StLayout {
+		file_length: regions.stream_length(),
+		data_pointer: header.data_pointer,
+		strings: string_pointers.into_iter().zip(strings.iter().map(|string| string.raw.len())).collect(),
+		extended: extended_layout,
+	};
+	Ok(StTable {
 		field_count,
 		strings,
+		encoding,
+		extended,
+		layout: Some(layout),
 	})
+}
+
+fn read_extended<R: BufRead + Seek>(
+	reader: &mut R,
+	regions: &mut Regions,
+	annotations: &mut Annotations,
+	header: &StbOrStmHeader,
+	raw_count: usize,
+) -> Result<(StExtendedData, StExtendedLayout)> {
+	annotations.add(None, header.checksums_pointer, (raw_count as u64).saturating_mul(8), "Checksums");
+	regions.claim_table(header.checksums_pointer, raw_count as u64, 8, 16)?;
+	reader.seek(SeekFrom::Start(header.checksums_pointer))?;
+	let checksums = read_pointers(reader, raw_count)?;
 	
-	// Debugging output garbage. The next time I work on these things, I should make sure this function only reads raw data, and have it be processed/displayed elsewhere.
-	/*
-	let mut checksums = None;
-	if let Some(header_full) = header_full {
-		reader.seek(SeekFrom::Start(header_full.checksums_pointer))?;
-		checksums = Some(read_pointers(reader, raw_count)?);
-	}
-	let checksums = checksums;
+	let (extra1, extra1_layout) = read_extra_section(reader, regions, annotations, &header.extra1, "extra1", 32)?;
+	let (extra2, extra2_layout) = read_extra_section(reader, regions, annotations, &header.extra2, "extra2", 48)?;
+	
+	let layout = StExtendedLayout {
+		checksums_pointer: header.checksums_pointer,
+		extra1: extra1_layout,
+		extra2: extra2_layout,
+	};
+	Ok((StExtendedData { checksums, extra1, extra2 }, layout))
+}
+
+/// `header_offset` is where the section's header is within the file header.
+fn read_extra_section<R: BufRead + Seek>(
+	reader: &mut R,
+	regions: &mut Regions,
+	annotations: &mut Annotations,
+	section: &StbOrStmHeaderExtra,
+	name: &str,
+	header_offset: u64,
+) -> Result<(Vec<StExtraEntry>, StExtraLayout)> {
+	let count = count_to_usize(section.extra_entry_count, header_offset + 4)?;
+	annotations.add(None, section.pointer, count as u64 * 8, format_args!("{name} pointer table"));
+	regions.claim_table(section.pointer, count as u64, 8, header_offset + 8)?;
+	reader.seek(SeekFrom::Start(section.pointer))?;
+	let pointers = read_pointers(reader, count)?;
 	
-	println!("Entry count: {}", header.entry_count);
-	println!("Field count: {}", header.field_count);
+	let entries = pointers.iter().enumerate().map(|(i, &pointer)| {
+		let stored_at = section.pointer + i as u64 * 8;
+		regions.claim(pointer, 16, stored_at)?;
+		seek_absolute(reader, pointer)?;
+		let piece_count: u64 = read_struct(reader)?;
+		let pieces_length = piece_count.saturating_mul(8);
+		let entry = annotations.add(None, pointer, pieces_length.saturating_add(16), format_args!("{name} entry {i}"));
+		annotations.add_fields::<StbOrStmDataExtra>(entry, pointer);
+		annotations.add(entry, pointer + 16, pieces_length, "pieces");
+		regions.claim_table(pointer + 16, piece_count, 8, pointer)?;
+		
+		seek_absolute(reader, pointer)?;
+		let extra: StbOrStmDataExtra = read_struct(reader)?;
+		Ok(StExtraEntry { pieces: extra.pieces })
+	}).collect::<Result<Vec<_>>>()?;
 	
-	if let Some(header_full) = header_full {
-		for extra in [(1, header_full.extra1), (2, header_full.extra2)] {
-			println!("Extra data {} count: {}", extra.0, extra.1.extra_entry_count);
-		}
+	let layout = StExtraLayout {
+		pointer: section.pointer,
+		entries: pointers.into_iter().zip(entries.iter().map(|entry| entry.pieces.len())).collect(),
+	};
+	Ok((entries, layout))
+}
+
+/// Writes a whole string table. `.stm`/`.stb` files are written if the table has extended data, otherwise `.stl`.
+///
+/// If the table has a [`StLayout`] that still fits it, everything goes back where it was read from,
+/// so an unmodified table comes out byte for byte the same. Only the bytes between the structures aren't kept,
+/// and are written as zeros. A layout stops fitting once any string changes length, strings that shared their bytes
+/// stop agreeing, or the rows, extra entries or their pieces are added or removed.
+///
+/// Otherwise the layout is: header, checksums (if any), string pointers, then the strings,
+/// with identical strings only stored once.
+/// For `.stm`/`.stb`, each extra section's pointer table and entries follow, aligned to 8 bytes.
+pub fn write_st<W: Write + Seek>(writer: &mut W, table: &StTable) -> Result<()> {
+	let raw_count = table.strings.len();
+	let entry_count = table.entry_count();
+	if entry_count * table.field_count != raw_count {
+		return Err(Error::Unwritable {
+			message: format!("{raw_count} strings don't fit into rows of {} fields", table.field_count),
+		});
 	}
+	if let Some(extended) = &table.extended && extended.checksums.len() != raw_count {
+		return Err(Error::Unwritable {
+			message: format!("there are {} checksums for {raw_count} strings", extended.checksums.len()),
+		});
+	}
+	if let Some(index) = table.strings.iter().position(|string| string.raw.contains(&0)) {
+		return Err(Error::Unwritable { message: format!("string {index} contains a NUL character") });
+	}
+	let entry_count: u32 = entry_count.try_into().map_err(|_| Error::CountOverflow { offset: 8 })?;
+	let field_count: u32 = table.field_count.try_into().map_err(|_| Error::CountOverflow { offset: 12 })?;
 	
-	for i in raw_count.saturating_sub(20)..raw_count {
-		let string = &strings[i];
-		if let Some(chk) = &checksums {
-			let their_checksum = chk[i];
-			println!("{:?} {:08X}", string, their_checksum);
-		} else {
-			println!("{:?}", string);
+	match &table.layout {
+		Some(layout) if fits_layout(table, layout) => write_original_layout(writer, table, layout, entry_count, field_count),
+		_ => write_new_layout(writer, table, entry_count, field_count),
+	}
+}
+
+fn fits_layout(table: &StTable, layout: &StLayout) -> bool {
+	if table.strings.len() != layout.strings.len() {
+		return false;
+	}
+	let mut at_pointer = HashMap::<u64, &[u8]>::new();
+	for (string, &(pointer, length)) in table.strings.iter().zip(&layout.strings) {
+		if string.raw.len() != length || *at_pointer.entry(pointer).or_insert(&string.raw) != string.raw.as_slice() {
+			return false;
 		}
 	}
+	// A string can also start in the middle of another one, sharing its ending
+	let mut shared: Vec<(u64, &[u8])> = at_pointer.into_iter().collect();
+	shared.sort_unstable_by_key(|string| string.0);
+	let strings_agree = shared.windows(2).all(|pair| {
+		let ((first_pointer, first), (second_pointer, second)) = (pair[0], pair[1]);
+		let skip = second_pointer - first_pointer;
+		skip > first.len() as u64 || first[skip as usize..] == *second
+	});
 	
-	if let Some(header_full) = header_full {
-		for extra in [(1, header_full.extra1), (2, header_full.extra2)] {
-			println!("(Extra data {})", extra.0);
-			reader.seek(SeekFrom::Start(extra.1.pointer))?;
-			let extra_entry_count = extra.1.extra_entry_count as usize; // TODO: Checked conversion again...
-			let pointers = read_pointers(reader, extra_entry_count)?;
-			for (i, pointer) in pointers.iter().enumerate() {
-				seek_absolute(reader, *pointer)?;
-				let extra_data = StbOrStmDataExtra::read(reader)?;
-				if i >= pointers.len().saturating_sub(20) {
-					println!("{:?}", extra_data.pieces);
+	let extra_fits = |section: &[StExtraEntry], layout: &StExtraLayout| {
+		section.len() == layout.entries.len()
+			&& section.iter().zip(&layout.entries).all(|(entry, &(_, piece_count))| entry.pieces.len() == piece_count)
+	};
+	let extended_fits = match (&table.extended, &layout.extended) {
+		(None, None) => true,
+		(Some(extended), Some(layout)) => extra_fits(&extended.extra1, &layout.extra1) && extra_fits(&extended.extra2, &layout.extra2),
+		_ => false,
+	};
+	strings_agree && extended_fits
+}
+
+fn write_original_layout<W: Write + Seek>(
+	writer: &mut W,
+	table: &StTable,
+	layout: &StLayout,
+	entry_count: u32,
+	field_count: u32,
+) -> Result<()> {
+	let string_pointers: Vec<u64> = layout.strings.iter().map(|string| string.0).collect();
+	let mut pieces = Vec::<(u64, Cow<[u8]>)>::new();
+	pieces.push((layout.data_pointer, struct_to_bytes(&string_pointers, layout.data_pointer)?.into()));
+	for (string, &(pointer, length)) in table.strings.iter().zip(&layout.strings) {
+		pieces.push((pointer, string.raw().into()));
+		pieces.push((pointer + length as u64, b"\0".into()));
+	}
+	
+	match (&table.extended, &layout.extended) {
+		(Some(extended), Some(extended_layout)) => {
+			let extra_header = |section: &[StExtraEntry], layout: &StExtraLayout, offset: u64| -> Result<_> {
+				Ok(StbOrStmHeaderExtra {
+					extra_entry_count: section.len().try_into().map_err(|_| Error::CountOverflow { offset })?,
+					pointer: layout.pointer,
+				})
+			};
+			let header = StbOrStmHeader {
+				entry_count,
+				field_count,
+				checksums_pointer: extended_layout.checksums_pointer,
+				data_pointer: layout.data_pointer,
+				extra1: extra_header(&extended.extra1, &extended_layout.extra1, 36)?,
+				extra2: extra_header(&extended.extra2, &extended_layout.extra2, 52)?,
+			};
+			pieces.push((0, struct_to_bytes(&header, 0)?.into()));
+			let checksums_pointer = extended_layout.checksums_pointer;
+			pieces.push((checksums_pointer, struct_to_bytes(&extended.checksums, checksums_pointer)?.into()));
+			for (section, section_layout) in [(&extended.extra1, &extended_layout.extra1), (&extended.extra2, &extended_layout.extra2)] {
+				let pointers: Vec<u64> = section_layout.entries.iter().map(|entry| entry.0).collect();
+				pieces.push((section_layout.pointer, struct_to_bytes(&pointers, section_layout.pointer)?.into()));
+				for (entry, pointer) in section.iter().zip(pointers) {
+					pieces.push((pointer, struct_to_bytes(&extra_entry(entry), pointer)?.into()));
 				}
 			}
+		},
+		_ => {
+			let header = StlHeader { entry_count, field_count, data_pointer: layout.data_pointer };
+			pieces.push((0, struct_to_bytes(&header, 0)?.into()));
+		},
+	}
+	
+	write_pieces(writer, pieces, layout.file_length)?;
+	Ok(())
+}
+
+fn write_new_layout<W: Write + Seek>(writer: &mut W, table: &StTable, entry_count: u32, field_count: u32) -> Result<()> {
+	let raw_count = table.strings.len();
+	let header_size = if table.extended.is_some() { 64 } else { 24 };
+	let table_size = raw_count as u64 * 8;
+	let checksums_pointer = header_size;
+	let data_pointer = if table.extended.is_some() { checksums_pointer + table_size } else { header_size };
+	
+	let mut position = data_pointer + table_size;
+	let mut string_pointers = Vec::<u64>::with_capacity(raw_count);
+	let mut unique_strings = Vec::<&[u8]>::new();
+	let mut seen = HashMap::<&[u8], u64>::new();
+	for string in &table.strings {
+		let string = string.raw();
+		let pointer = *seen.entry(string).or_insert_with(|| {
+			let pointer = position;
+			position += string.len() as u64 + 1;
+			unique_strings.push(string);
+			pointer
+		});
+		string_pointers.push(pointer);
+	}
+	
+	writer.rewind()?;
+	match &table.extended {
+		None => {
+			let header = StlHeader { entry_count, field_count, data_pointer };
+			header.write(writer).map_err(|e| Error::from_binrw(e, 0))?;
+		},
+		Some(extended) => {
+			let extra1_pointer = position.next_multiple_of(8);
+			let extra2_pointer = extra1_pointer + extra_section_size(&extended.extra1);
+			let header = StbOrStmHeader {
+				entry_count,
+				field_count,
+				checksums_pointer,
+				data_pointer,
+				extra1: StbOrStmHeaderExtra {
+					extra_entry_count: extended.extra1.len().try_into().map_err(|_| Error::CountOverflow { offset: 36 })?,
+					pointer: extra1_pointer,
+				},
+				extra2: StbOrStmHeaderExtra {
+					extra_entry_count: extended.extra2.len().try_into().map_err(|_| Error::CountOverflow { offset: 52 })?,
+					pointer: extra2_pointer,
+				},
+			};
+			header.write(writer).map_err(|e| Error::from_binrw(e, 0))?;
+			extended.checksums.write_le(writer).map_err(|e| Error::from_binrw(e, checksums_pointer))?;
+		},
+	}
+	
+	string_pointers.write_le(writer).map_err(|e| Error::from_binrw(e, data_pointer))?;
+	for string in unique_strings {
+		writer.write_all(string)?;
+		writer.write_all(&[0])?;
+	}
+	
+	if let Some(extended) = &table.extended {
+		for section in [&extended.extra1, &extended.extra2] {
+			let section_pointer = writer.stream_position()?.next_multiple_of(8);
+			write_padding(writer, section_pointer)?;
+			write_extra_section(writer, section, section_pointer)?;
 		}
 	}
 	
-	//println!("{:?}", idk);
-	//todo!("we've gotta show some entries and those possibly-checksums side by side, i guess");
 	Ok(())
-	*/
+}
+
+fn extra_section_size(section: &[StExtraEntry]) -> u64 {
+	section.iter().map(|entry| 8 + 16 + entry.pieces.len() as u64 * 8).sum()
+}
+
+/// Writes the section's pointer table at `section_pointer`, followed by the entries themselves.
+fn write_extra_section<W: Write + Seek>(writer: &mut W, section: &[StExtraEntry], section_pointer: u64) -> Result<()> {
+	let mut position = section_pointer + section.len() as u64 * 8;
+	let pointers: Vec<u64> = section.iter().map(|entry| {
+		let pointer = position;
+		position += 16 + entry.pieces.len() as u64 * 8;
+		pointer
+	}).collect();
+	pointers.write_le(writer).map_err(|e| Error::from_binrw(e, section_pointer))?;
+	
+	for (entry, pointer) in section.iter().zip(pointers) {
+		extra_entry(entry).write(writer).map_err(|e| Error::from_binrw(e, pointer))?;
+	}
+	Ok(())
+}
+
+fn extra_entry(entry: &StExtraEntry) -> StbOrStmDataExtra {
+	StbOrStmDataExtra {
+		piece_count: entry.pieces.len() as u64,
+		magic: MagicTen::HexTen,
+		pieces: entry.pieces.clone(),
+	}
+}
+
+/// A string whose stored checksum doesn't match the one computed from its contents.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ChecksumMismatch {
+	/// The position of the string in [`StTable::strings`].
+	pub index: usize,
+	pub stored: u64,
+	pub computed: u64,
+}
+
+/// A guess at the checksum that `.stm`/`.stb` files store for each string, for working out the real algorithm.
+///
+/// The guess is the CRC-32 used by zlib and PNG, over the string's bytes without the NUL terminator, widened to 64 bits.
+/// It hasn't been checked against any of the game's tables, so it might be wrong, and tables with checksums written
+/// from it might be rejected by the game. That's why nothing in this crate writes it unless asked to with
+/// [`recompute_checksums`], and edited strings keep their stored checksums instead.
+///
+/// [`verify_checksums`] and the table view's mismatch count are how to check it: if a real table's strings mostly
+/// match, the guess is right. The ignored `checksum_matches_real_table` test does that for a table named by
+/// `EXCAVATOR_REAL_ST`. Once it passes, a small table from the game should be checked in as a fixture for it.
+pub fn string_checksum(bytes: &[u8]) -> u64 {
+	let mut crc = !0u32;
+	for &byte in bytes {
+		crc = CRC32_TABLE[((crc ^ u32::from(byte)) & 0xFF) as usize] ^ (crc >> 8);
+	}
+	u64::from(!crc)
+}
+
+const CRC32_TABLE: [u32; 256] = {
+	let mut table = [0u32; 256];
+	let mut i = 0;
+	while i < 256 {
+		let mut value = i as u32;
+		let mut bit = 0;
+		while bit < 8 {
+			value = if value & 1 != 0 { 0xEDB8_8320 ^ (value >> 1) } else { value >> 1 };
+			bit += 1;
+		}
+		table[i] = value;
+		i += 1;
+	}
+	table
+};
+
+/// Lists every string whose stored checksum doesn't match [`string_checksum`]. Tables without checksums never mismatch.
+pub fn verify_checksums(table: &StTable) -> Vec<ChecksumMismatch> {
+	let Some(extended) = &table.extended else { return Vec::new(); };
+	table.strings.iter().zip(&extended.checksums).enumerate().filter_map(|(index, (string, &stored))| {
+		let computed = string_checksum(string.raw());
+		(stored != computed).then_some(ChecksumMismatch { index, stored, computed })
+	}).collect()
+}
+
+/// Replaces the stored checksums with ones from [`string_checksum`]. Does nothing to tables without checksums.
+///
+/// Since [`string_checksum`] isn't confirmed, the game might reject a table written after this.
+/// Only call it when someone asked for it.
+pub fn recompute_checksums(table: &mut StTable) {
+	let Some(extended) = &mut table.extended else { return; };
+	extended.checksums = table.strings.iter().map(|string| string_checksum(string.raw())).collect();
 }
 
 #[cfg(test)]
@@ -159,6 +738,8 @@ mod tests {
 	use super::*;
 	use std::io::Cursor;
 	
+	use crate::annotate::assert_layout;
+	
 	const STL_HEADER_SAMPLE_RAW: [u8; 24] = [
 		0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 		0x6B, 0x0B, 0x00, 0x00,
@@ -214,6 +795,390 @@ mod tests {
 		},
 	};
 	
+	fn texts(table: &StTable) -> Vec<&str> {
+		table.strings.iter().map(StString::text).collect()
+	}
+	
+	/// A two-entry, two-field table with the strings right after the pointers.
+	fn small_stl() -> Vec<u8> {
+		let mut data = Vec::<u8>::new();
+		data.extend([0u8; 8]);
+		data.extend(2u32.to_le_bytes());
+		data.extend(2u32.to_le_bytes());
+		data.extend(0x18u64.to_le_bytes());
+		for pointer in [0x38u64, 0x3B, 0x3E, 0x3E] {
+			data.extend(pointer.to_le_bytes());
+		}
+		data.extend(b"ab\0cd\0\0");
+		data
+	}
+	
+	#[test]
+	fn stl_read() {
+		let result = read_st(&mut Cursor::new(small_stl()), true).unwrap();
+		assert_eq!(result.field_count, 2);
+		assert_eq!(texts(&result), ["ab", "cd", "", ""]);
+		assert_eq!(result.extended, None);
+	}
+	
+	#[test]
+	fn stl_read_damaged() {
+		let mut huge_count = small_stl();
+		huge_count[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+		huge_count[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+		assert!(matches!(read_st(&mut Cursor::new(huge_count), true), Err(Error::TruncatedTable { offset: 0x18, .. })));
+		
+		let mut table_in_header = small_stl();
+		table_in_header[16..24].copy_from_slice(&0x10u64.to_le_bytes());
+		assert!(matches!(
+			read_st(&mut Cursor::new(table_in_header), true),
+			Err(Error::OverlappingRegions { offset: 0x10, other: 0 }),
+		));
+		
+		let mut string_past_end = small_stl();
+		string_past_end[0x20..0x28].copy_from_slice(&0x1000u64.to_le_bytes());
+		assert!(matches!(
+			read_st(&mut Cursor::new(string_past_end), true),
+			Err(Error::PointerOutOfBounds { offset: 0x20, pointer: 0x1000 }),
+		));
+	}
+	
+	/// A one-entry, two-field table with one entry in the first extra section, and none in the second.
+	fn small_stm() -> Vec<u8> {
+		let mut data = Vec::<u8>::new();
+		data.extend([0u8; 8]);
+		data.extend(1u32.to_le_bytes());
+		data.extend(2u32.to_le_bytes());
+		data.extend(0x40u64.to_le_bytes());
+		data.extend(0x50u64.to_le_bytes());
+		data.extend([0u8; 4]);
+		data.extend(1u32.to_le_bytes());
+		data.extend(0x68u64.to_le_bytes());
+		data.extend([0u8; 4]);
+		data.extend(0u32.to_le_bytes());
+		data.extend(0x90u64.to_le_bytes());
+		// Checksums, then string pointers
+		data.extend(0x1234u64.to_le_bytes());
+		data.extend(0xABCDu64.to_le_bytes());
+		data.extend(0x60u64.to_le_bytes());
+		data.extend(0x62u64.to_le_bytes());
+		data.extend(b"x\0yz\0\0\0\0");
+		// First extra section
+		data.extend(0x70u64.to_le_bytes());
+		data.extend(2u64.to_le_bytes());
+		data.extend(0x10u64.to_le_bytes());
+		for piece in [1u32, 2, 3, 4] {
+			data.extend(piece.to_le_bytes());
+		}
+		data
+	}
+	
+	#[test]
+	fn stm_read() {
+		let result = read_st(&mut Cursor::new(small_stm()), false).unwrap();
+		assert_eq!(result.field_count, 2);
+		assert_eq!(result.entry_count(), 1);
+		assert_eq!(texts(&result), ["x", "yz"]);
+		
+		let extended = result.extended.unwrap();
+		assert_eq!(extended.checksums, [0x1234, 0xABCD]);
+		assert_eq!(extended.extra1, [StExtraEntry { pieces: vec![(1, 2), (3, 4)] }]);
+		assert_eq!(extended.extra2, []);
+	}
+	
+	#[test]
+	fn layouts_match_fields() {
+		assert_layout(&StlHeader { entry_count: 1, field_count: 2, data_pointer: 3 }, &[
+			("magic", &[0; 8]),
+			("entry_count", &1u32.to_le_bytes()),
+			("field_count", &2u32.to_le_bytes()),
+			("data_pointer", &3u64.to_le_bytes()),
+		]);
+		
+		let extra1 = StbOrStmHeaderExtra { extra_entry_count: 5, pointer: 6 };
+		let extra2 = StbOrStmHeaderExtra { extra_entry_count: 7, pointer: 8 };
+		assert_layout(&extra1, &[
+			("magic", &[0; 4]),
+			("extra_entry_count", &5u32.to_le_bytes()),
+			("pointer", &6u64.to_le_bytes()),
+		]);
+		let header = StbOrStmHeader { entry_count: 1, field_count: 2, checksums_pointer: 3, data_pointer: 4, extra1, extra2 };
+		let extra_bytes = |extra: &StbOrStmHeaderExtra| struct_to_bytes(extra, 0).unwrap();
+		assert_layout(&header, &[
+			("magic", &[0; 8]),
+			("entry_count", &1u32.to_le_bytes()),
+			("field_count", &2u32.to_le_bytes()),
+			("checksums_pointer", &3u64.to_le_bytes()),
+			("data_pointer", &4u64.to_le_bytes()),
+			("extra1", &extra_bytes(&extra1)),
+			("extra2", &extra_bytes(&extra2)),
+		]);
+		
+		let data_extra = StbOrStmDataExtra { piece_count: 0, magic: MagicTen::HexTen, pieces: Vec::new() };
+		assert_layout(&data_extra, &[
+			("piece_count", &0u64.to_le_bytes()),
+			("magic", &0x10u64.to_le_bytes()),
+		]);
+	}
+	
+	#[test]
+	fn stm_annotated() {
+		let mut annotations = Annotations::new();
+		read_st_annotated(&mut Cursor::new(small_stm()), false, StEncoding::Utf8, &mut annotations).unwrap();
+		let find = |name: &str| annotations.iter().find(|a| a.name == name).map(|a| (a.offset, a.length));
+		
+		assert_eq!(find("StbOrStmHeader"), Some((0, 64)));
+		assert_eq!(find("Checksums"), Some((0x40, 16)));
+		assert_eq!(find("String pointer table"), Some((0x50, 16)));
+		assert_eq!(find("Row 0, field 1"), Some((0x62, 3)));
+		assert_eq!(find("extra1 pointer table"), Some((0x68, 8)));
+		assert_eq!(find("extra1 entry 0"), Some((0x70, 32)));
+		assert_eq!(find("pieces"), Some((0x80, 16)));
+		
+		// The extra section headers are broken down inside the file header
+		let header = annotations.iter().position(|a| a.name == "StbOrStmHeader");
+		let extra2 = annotations.child(header, "extra2");
+		let pointer = annotations.get(annotations.child(extra2, "pointer").unwrap()).unwrap();
+		assert_eq!((pointer.offset, pointer.length), (56, 8));
+	}
+	
+	#[test]
+	fn damaged_stm_is_annotated_up_to_the_damage() {
+		let mut data = small_stm();
+		// The first extra entry's pointer now points past the end
+		data[0x68..0x70].copy_from_slice(&0x1000u64.to_le_bytes());
+		let mut annotations = Annotations::new();
+		assert!(read_st_annotated(&mut Cursor::new(data), false, StEncoding::Utf8, &mut annotations).is_err());
+		assert!(annotations.iter().any(|a| a.name == "extra1 pointer table"));
+		assert!(!annotations.iter().any(|a| a.name == "extra1 entry 0"));
+	}
+	
+	#[test]
+	fn stm_read_damaged() {
+		let mut bad_extra_magic = small_stm();
+		bad_extra_magic[0x78] = 0x11;
+		assert!(matches!(read_st(&mut Cursor::new(bad_extra_magic), false), Err(Error::Malformed { offset: 0x70, .. })));
+		
+		let mut too_many_pieces = small_stm();
+		too_many_pieces[0x70] = 3;
+		assert!(matches!(
+			read_st(&mut Cursor::new(too_many_pieces), false),
+			Err(Error::TruncatedTable { offset: 0x80, count: 3 }),
+		));
+		
+		let mut checksums_overlap_strings = small_stm();
+		checksums_overlap_strings[16] = 0x48;
+		assert!(matches!(
+			read_st(&mut Cursor::new(checksums_overlap_strings), false),
+			Err(Error::OverlappingRegions { offset: 0x48, other: 0x50 }),
+		));
+	}
+	
+	fn write_to_vec(table: &StTable) -> Vec<u8> {
+		let mut writer = Cursor::new(Vec::<u8>::new());
+		write_st(&mut writer, table).unwrap();
+		writer.into_inner()
+	}
+	
+	#[test]
+	fn stl_round_trip() {
+		let original = small_stl();
+		let table = read_st(&mut Cursor::new(&original), true).unwrap();
+		assert_eq!(write_to_vec(&table), original);
+	}
+	
+	#[test]
+	fn stm_round_trip() {
+		let original = small_stm();
+		let table = read_st(&mut Cursor::new(&original), false).unwrap();
+		assert_eq!(write_to_vec(&table), original);
+	}
+	
+	/// An `.stl` laid out differently from [`write_st`]'s own layout: the strings come before the pointer table,
+	/// the second string is the end of the first one, the empty strings aren't shared, and there's padding at the end.
+	fn foreign_stl() -> Vec<u8> {
+		let mut data = Vec::<u8>::new();
+		data.extend([0u8; 8]);
+		data.extend(2u32.to_le_bytes());
+		data.extend(2u32.to_le_bytes());
+		data.extend(0x20u64.to_le_bytes());
+		data.extend(b"abcd\0\0\0\0");
+		for pointer in [0x18u64, 0x1A, 0x1D, 0x1E] {
+			data.extend(pointer.to_le_bytes());
+		}
+		data.extend([0u8; 8]);
+		data
+	}
+	
+	/// An `.stm` laid out differently from [`write_st`]'s own layout: the checksums come after the strings,
+	/// the second extra section comes before the first, and nothing is aligned.
+	fn foreign_stm() -> Vec<u8> {
+		let mut data = Vec::<u8>::new();
+		data.extend([0u8; 8]);
+		data.extend(1u32.to_le_bytes());
+		data.extend(2u32.to_le_bytes());
+		data.extend(0x55u64.to_le_bytes());
+		data.extend(0x40u64.to_le_bytes());
+		data.extend([0u8; 4]);
+		data.extend(1u32.to_le_bytes());
+		data.extend(0x7Du64.to_le_bytes());
+		data.extend([0u8; 4]);
+		data.extend(1u32.to_le_bytes());
+		data.extend(0x65u64.to_le_bytes());
+		// String pointers, strings, then checksums
+		data.extend(0x50u64.to_le_bytes());
+		data.extend(0x52u64.to_le_bytes());
+		data.extend(b"x\0yz\0");
+		data.extend(0x1234u64.to_le_bytes());
+		data.extend(0xABCDu64.to_le_bytes());
+		// Second extra section
+		data.extend(0x6Du64.to_le_bytes());
+		data.extend(0u64.to_le_bytes());
+		data.extend(0x10u64.to_le_bytes());
+		// First extra section
+		data.extend(0x85u64.to_le_bytes());
+		data.extend(1u64.to_le_bytes());
+		data.extend(0x10u64.to_le_bytes());
+		data.extend(5u32.to_le_bytes());
+		data.extend(6u32.to_le_bytes());
+		data
+	}
+	
+	#[test]
+	fn foreign_layout_round_trip() {
+		let original = foreign_stl();
+		let table = read_st(&mut Cursor::new(&original), true).unwrap();
+		assert_eq!(texts(&table), ["abcd", "cd", "", ""]);
+		assert_eq!(write_to_vec(&table), original);
+		
+		let original = foreign_stm();
+		let table = read_st(&mut Cursor::new(&original), false).unwrap();
+		assert_eq!(texts(&table), ["x", "yz"]);
+		let extended = table.extended.as_ref().unwrap();
+		assert_eq!(extended.checksums, [0x1234, 0xABCD]);
+		assert_eq!(extended.extra1, [StExtraEntry { pieces: vec![(5, 6)] }]);
+		assert_eq!(extended.extra2, [StExtraEntry { pieces: vec![] }]);
+		assert_eq!(write_to_vec(&table), original);
+	}
+	
+	#[test]
+	fn edits_keep_the_layout_only_if_they_fit() {
+		let mut table = read_st(&mut Cursor::new(foreign_stm()), false).unwrap();
+		table.set_text(1, "ab").unwrap();
+		let mut expected = foreign_stm();
+		expected[0x52..0x54].copy_from_slice(b"ab");
+		assert_eq!(write_to_vec(&table), expected);
+		
+		table.set_text(1, "longer").unwrap();
+		let written = write_to_vec(&table);
+		assert_eq!(&written[16..24], 64u64.to_le_bytes());
+		assert_eq!(read_st(&mut Cursor::new(&written), false).unwrap(), table);
+		
+		// The same length, but "abcd" still needs to end in "cd"
+		let mut table = read_st(&mut Cursor::new(foreign_stl()), true).unwrap();
+		table.set_text(1, "xy").unwrap();
+		let written = write_to_vec(&table);
+		assert_eq!(&written[16..24], 24u64.to_le_bytes());
+		assert_eq!(texts(&read_st(&mut Cursor::new(&written), true).unwrap()), ["abcd", "xy", "", ""]);
+	}
+	
+	#[test]
+	fn edited_table_is_readable() {
+		let mut table = read_st(&mut Cursor::new(small_stm()), false).unwrap();
+		table.strings.extend(["new", "x"].map(|text| StString::from_text(text, StEncoding::Utf8).unwrap()));
+		let extended = table.extended.as_mut().unwrap();
+		extended.checksums.extend([5, 6]);
+		extended.extra2.push(StExtraEntry { pieces: vec![(7, 8)] });
+		extended.extra2.push(StExtraEntry { pieces: vec![] });
+		
+		let written = write_to_vec(&table);
+		let reread = read_st(&mut Cursor::new(&written), false).unwrap();
+		assert_eq!(reread, table);
+		assert_eq!(write_to_vec(&reread), written);
+	}
+	
+	#[test]
+	fn write_rejects_inconsistent_tables() {
+		let mut table = read_st(&mut Cursor::new(small_stm()), false).unwrap();
+		table.extended.as_mut().unwrap().checksums.pop();
+		assert!(matches!(write_st(&mut Cursor::new(Vec::new()), &table), Err(Error::Unwritable { .. })));
+		
+		let mut table = read_st(&mut Cursor::new(small_stl()), true).unwrap();
+		table.strings[1] = StString::from_raw(b"a\0b".to_vec(), StEncoding::Utf8);
+		assert!(matches!(write_st(&mut Cursor::new(Vec::new()), &table), Err(Error::Unwritable { .. })));
+		assert!(matches!(table.set_text(1, "a\0b"), Err(Error::Unwritable { .. })));
+		table.strings.pop();
+		assert!(matches!(write_st(&mut Cursor::new(Vec::new()), &table), Err(Error::Unwritable { .. })));
+	}
+	
+	#[test]
+	fn checksum_known_values() {
+		assert_eq!(string_checksum(b""), 0);
+		assert_eq!(string_checksum(b"123456789"), 0xCBF4_3926);
+		assert_eq!(string_checksum(b"The quick brown fox jumps over the lazy dog"), 0x414F_A339);
+	}
+	
+	#[test]
+	fn checksum_verify_and_recompute() {
+		let mut table = read_st(&mut Cursor::new(small_stm()), false).unwrap();
+		assert_eq!(verify_checksums(&table), [
+			ChecksumMismatch { index: 0, stored: 0x1234, computed: string_checksum(b"x") },
+			ChecksumMismatch { index: 1, stored: 0xABCD, computed: string_checksum(b"yz") },
+		]);
+		
+		recompute_checksums(&mut table);
+		assert_eq!(verify_checksums(&table), []);
+		
+		table.set_text(1, "edited").unwrap();
+		assert_eq!(verify_checksums(&table).iter().map(|m| m.index).collect::<Vec<_>>(), [1]);
+		
+		let mut stl = read_st(&mut Cursor::new(small_stl()), true).unwrap();
+		recompute_checksums(&mut stl);
+		assert_eq!(stl.extended, None);
+		assert_eq!(verify_checksums(&stl), []);
+	}
+	
+	/// Run with `EXCAVATOR_REAL_ST=path/to/table.stm cargo test -- --ignored` to check [`string_checksum`]
+	/// against a table from the game. Until it's been run, the checksum is only a guess.
+	#[test]
+	#[ignore = "needs a .stm or .stb file from the game, named by EXCAVATOR_REAL_ST"]
+	fn checksum_matches_real_table() {
+		let path = std::env::var_os("EXCAVATOR_REAL_ST").expect("EXCAVATOR_REAL_ST should name a .stm or .stb file");
+		let table = read_st(&mut std::io::BufReader::new(std::fs::File::open(path).unwrap()), false).unwrap();
+		assert!(table.extended.as_ref().is_some_and(|extended| !extended.checksums.is_empty()));
+		assert_eq!(verify_checksums(&table), []);
+	}
+	
+	#[test]
+	fn legacy_encodings_survive_round_trip() {
+		// "テスト" in Shift-JIS, and "café" in Windows-1252
+		let shift_jis = [0x83, 0x65, 0x83, 0x58, 0x83, 0x67];
+		let windows_1252 = [b'c', b'a', b'f', 0xE9];
+		let mut table = read_st(&mut Cursor::new(small_stl()), true).unwrap();
+		table.strings[0] = StString::from_raw(shift_jis.to_vec(), StEncoding::Utf8);
+		table.strings[1] = StString::from_raw(windows_1252.to_vec(), StEncoding::Utf8);
+		let written = write_to_vec(&table);
+		
+		let mut reread = read_st(&mut Cursor::new(&written), true).unwrap();
+		assert!(reread.strings[0].is_lossy());
+		assert!(!reread.strings[2].is_lossy());
+		assert_eq!(reread.non_utf8_strings().collect::<Vec<_>>(), [0, 1]);
+		assert_eq!(reread.strings[0].raw(), shift_jis);
+		assert_eq!(write_to_vec(&reread), written);
+		
+		let shift_jis_table = read_st_with_encoding(&mut Cursor::new(&written), true, StEncoding::ShiftJis).unwrap();
+		assert_eq!(shift_jis_table.strings[0].text(), "テスト");
+		assert!(!shift_jis_table.strings[0].is_lossy());
+		
+		reread.redecode(StEncoding::Windows1252);
+		assert_eq!(reread.strings[1].text(), "café");
+		assert_eq!(write_to_vec(&reread), written);
+		
+		reread.set_text(3, "thé").unwrap();
+		assert_eq!(reread.strings[3].raw(), [b't', b'h', 0xE9]);
+		assert!(matches!(reread.set_text(3, "テスト"), Err(Error::Unwritable { .. })));
+	}
+	
 	#[test]
 	fn stm_header_deserialize() {
 		let mut reader = Cursor::new(STM_HEADER_SAMPLE_RAW);
@@ -0,0 +1,379 @@
+//! Editing a string table one change at a time, with undo and redo, for views that let the table be changed by hand.
+
+use std::mem;
+
+use super::{StEncoding, StString, StTable};
+use crate::error::Result;
+
+/// A row taken out of a table with its checksums, so putting it back restores it exactly.
+#[derive(Clone, Debug)]
+struct RowContents {
+	strings: Vec<StString>,
+	checksums: Option<Vec<u64>>,
+}
+
+/// One change to a table. Applying an edit gives back the edit that undoes it.
+#[derive(Clone, Debug)]
+enum Edit {
+	/// Puts a string into a cell. Its checksum, if the table has them, stays as it was.
+	Cell { index: usize, string: StString },
+	InsertRow { row: usize, contents: RowContents },
+	RemoveRow { row: usize },
+}
+
+impl Edit {
+	fn apply(self, table: &mut StTable) -> (Self, StChange) {
+		let field_count = table.field_count;
+		match self {
+			Self::Cell { index, string } => {
+				let string = mem::replace(&mut table.strings[index], string);
+				(Self::Cell { index, string }, StChange::Cell(index))
+			},
+			Self::InsertRow { row, contents } => {
+				let start = row * field_count;
+				table.strings.splice(start..start, contents.strings);
+				if let (Some(extended), Some(checksums)) = (&mut table.extended, contents.checksums) {
+					extended.checksums.splice(start..start, checksums);
+				}
+				(Self::RemoveRow { row }, StChange::RowInserted(row))
+			},
+			Self::RemoveRow { row } => {
+				let range = row * field_count..(row + 1) * field_count;
+				let strings = table.strings.drain(range.clone()).collect();
+				let checksums = table.extended.as_mut().map(|extended| extended.checksums.drain(range).collect());
+				(Self::InsertRow { row, contents: RowContents { strings, checksums } }, StChange::RowRemoved(row))
+			},
+		}
+	}
+	
+	fn redecode(&mut self, encoding: StEncoding) {
+		match self {
+			Self::Cell { string, .. } => string.redecode(encoding),
+			Self::InsertRow { contents, .. } => contents.strings.iter_mut().for_each(|string| string.redecode(encoding)),
+			Self::RemoveRow { .. } => {},
+		}
+	}
+}
+
+/// What an edit, undo or redo changed, so a view of the table only has to update that part.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StChange {
+	/// The string at this position in [`StTable::strings`] changed.
+	Cell(usize),
+	/// A row was inserted here, moving the rows after it down.
+	RowInserted(usize),
+	/// The row here was removed, moving the rows after it up.
+	RowRemoved(usize),
+}
+
+/// Which version of the table an editor has, for telling whether it's been saved.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StEditState(u64);
+
+/// Keeps a table along with the edits made to it, so they can be undone and redone.
+///
+/// Edited strings keep their stored checksums, since [`super::string_checksum`] isn't confirmed,
+/// and new rows get checksums of zero. [`super::verify_checksums`] reports which ones don't match.
+/// The extra sections of `.stm`/`.stb` files aren't tied to rows as far as we know, so they're never changed.
+#[derive(Clone, Debug)]
+pub struct StEditor {
+	table: StTable,
+	/// Each edit is paired with the state that making it led to.
+	undo: Vec<(Edit, StEditState)>,
+	/// Each edit is paired with the state that redoing it leads to.
+	redo: Vec<(Edit, StEditState)>,
+	next_state: u64,
+	saved: StEditState,
+}
+
+impl StEditor {
+	/// Starts editing a table, which is taken to be saved as it is.
+	pub fn new(table: StTable) -> Self {
+		Self { table, undo: Vec::new(), redo: Vec::new(), next_state: 1, saved: StEditState(0) }
+	}
+	
+	pub fn table(&self) -> &StTable {
+		&self.table
+	}
+	
+	pub fn state(&self) -> StEditState {
+		self.undo.last().map_or(StEditState(0), |(_, state)| *state)
+	}
+	
+	/// Records that the table was saved as it was in `state`, which can be from before the latest edits,
+	/// if they were made while saving.
+	pub fn mark_saved(&mut self, state: StEditState) {
+		self.saved = state;
+	}
+	
+	/// Whether the table has changed since it was last saved. Undoing back to the saved table makes it clean again.
+	pub fn is_dirty(&self) -> bool {
+		self.state() != self.saved
+	}
+	
+	pub fn can_undo(&self) -> bool {
+		!self.undo.is_empty()
+	}
+	
+	pub fn can_redo(&self) -> bool {
+		!self.redo.is_empty()
+	}
+	
+	fn make(&mut self, edit: Edit) -> StChange {
+		let (inverse, change) = edit.apply(&mut self.table);
+		self.undo.push((inverse, StEditState(self.next_state)));
+		self.next_state += 1;
+		self.redo.clear();
+		change
+	}
+	
+	/// Replaces the text of the string at `index`, encoding it with the table's encoding.
+	/// Returns `None` if the text is already the same, in which case the string's bytes are left alone.
+	pub fn set_text(&mut self, index: usize, text: impl Into<String>) -> Result<Option<StChange>> {
+		let text = text.into();
+		if text == self.table.strings[index].text() {
+			return Ok(None);
+		}
+		let string = StString::from_text(text, self.table.encoding)?;
+		Ok(Some(self.make(Edit::Cell { index, string })))
+	}
+	
+	/// Inserts a row of empty strings before `row`. Inserting at [`StTable::entry_count`] adds it at the end.
+	///
+	/// # Panics
+	/// If `row` is past the end of the table.
+	pub fn insert_row(&mut self, row: usize) -> StChange {
+		assert!(row <= self.table.entry_count(), "row {row} is past the end of the table");
+		let field_count = self.table.field_count;
+		let contents = RowContents {
+			strings: vec![StString::from_raw(Vec::new(), self.table.encoding); field_count],
+			checksums: self.table.extended.is_some().then(|| vec![0; field_count]),
+		};
+		self.make(Edit::InsertRow { row, contents })
+	}
+	
+	/// # Panics
+	/// If there's no such row.
+	pub fn remove_row(&mut self, row: usize) -> StChange {
+		assert!(row < self.table.entry_count(), "there's no row {row}");
+		self.make(Edit::RemoveRow { row })
+	}
+	
+	/// Decodes every string again with a different encoding, including the ones kept for undoing and redoing.
+	/// Edited text is encoded with it from then on.
+	///
+	/// The bytes don't change, so this isn't an edit, and doesn't make the table any more or less saved.
+	pub fn redecode(&mut self, encoding: StEncoding) {
+		self.table.redecode(encoding);
+		for (edit, _) in self.undo.iter_mut().chain(&mut self.redo) {
+			edit.redecode(encoding);
+		}
+	}
+	
+	/// Undoes the latest edit that hasn't been undone yet.
+	pub fn undo(&mut self) -> Option<StChange> {
+		let (edit, state) = self.undo.pop()?;
+		let (inverse, change) = edit.apply(&mut self.table);
+		self.redo.push((inverse, state));
+		Some(change)
+	}
+	
+	/// Makes the latest undone edit again.
+	pub fn redo(&mut self) -> Option<StChange> {
+		let (edit, state) = self.redo.pop()?;
+		let (inverse, change) = edit.apply(&mut self.table);
+		self.undo.push((inverse, state));
+		Some(change)
+	}
+}
+
+/// The order a view shows the rows of a table in, for when they're sorted. Row 0 names the fields, so it's left out.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StRowOrder {
+	rows: Vec<usize>,
+}
+
+impl StRowOrder {
+	/// Every row after the first, in the table's order.
+	pub fn new(table: &StTable) -> Self {
+		Self { rows: (1..table.entry_count()).collect() }
+	}
+	
+	pub fn len(&self) -> usize {
+		self.rows.len()
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.rows.is_empty()
+	}
+	
+	/// The row shown at `position`.
+	pub fn row_at(&self, position: usize) -> Option<usize> {
+		self.rows.get(position).copied()
+	}
+	
+	pub fn position_of(&self, row: usize) -> Option<usize> {
+		self.rows.iter().position(|&other| other == row)
+	}
+	
+	/// Puts the rows back in the table's order.
+	pub fn reset(&mut self) {
+		self.rows.sort_unstable();
+	}
+	
+	/// Sorts the rows by a key. Rows with the same key stay in the table's order, whichever way they're sorted.
+	pub fn sort_by_key<K: Ord>(&mut self, mut key: impl FnMut(usize) -> K, descending: bool) {
+		let mut keyed: Vec<(K, usize)> = self.rows.iter().map(|&row| (key(row), row)).collect();
+		keyed.sort_by(|(a, a_row), (b, b_row)| {
+			let by_key = if descending { b.cmp(a) } else { a.cmp(b) };
+			by_key.then(a_row.cmp(b_row))
+		});
+		self.rows = keyed.into_iter().map(|(_, row)| row).collect();
+	}
+	
+	/// Keeps up with rows being inserted and removed. An inserted row is shown just after the row before it in the table,
+	/// which is where an added row is wanted, and near where a removed row was when it's put back.
+	/// Changes to the first row aren't expected, since it isn't shown.
+	pub fn apply(&mut self, change: StChange) {
+		match change {
+			StChange::Cell(_) => {},
+			StChange::RowInserted(row) => {
+				for other in &mut self.rows {
+					if *other >= row { *other += 1; }
+				}
+				let position = row.checked_sub(1).and_then(|before| self.position_of(before)).map_or(0, |position| position + 1);
+				self.rows.insert(position, row);
+			},
+			StChange::RowRemoved(row) => {
+				self.rows.retain(|&other| other != row);
+				for other in &mut self.rows {
+					if *other > row { *other -= 1; }
+				}
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::st::{StEncoding, StExtendedData, StExtraEntry};
+	
+	fn sample_table() -> StTable {
+		let text = |t: &str| StString::from_text(t, StEncoding::Utf8).unwrap();
+		StTable {
+			field_count: 2,
+			strings: vec![text("key"), text("english"), text("hello"), text("Hello"), text("bye"), text("Bye")],
+			encoding: StEncoding::Utf8,
+			extended: Some(StExtendedData {
+				checksums: vec![1, 2, 3, 4, 5, 6],
+				extra1: vec![StExtraEntry { pieces: vec![(1, 2)] }],
+				extra2: vec![],
+			}),
+			layout: None,
+		}
+	}
+	
+	fn texts(editor: &StEditor) -> Vec<&str> {
+		editor.table().strings.iter().map(StString::text).collect()
+	}
+	
+	#[test]
+	fn edits_undo_and_redo() {
+		let original = sample_table();
+		let mut editor = StEditor::new(original.clone());
+		assert!(!editor.is_dirty());
+		
+		assert_eq!(editor.set_text(3, "Hi").unwrap(), Some(StChange::Cell(3)));
+		assert_eq!(editor.set_text(3, "Hi").unwrap(), None);
+		assert_eq!(editor.table().extended.as_ref().unwrap().checksums[3], 4);
+		assert_eq!(editor.insert_row(2), StChange::RowInserted(2));
+		assert_eq!(editor.remove_row(1), StChange::RowRemoved(1));
+		assert_eq!(texts(&editor), ["key", "english", "", "", "bye", "Bye"]);
+		assert_eq!(editor.table().extended.as_ref().unwrap().checksums, [1, 2, 0, 0, 5, 6]);
+		assert!(editor.is_dirty());
+		
+		assert_eq!(editor.undo(), Some(StChange::RowInserted(1)));
+		assert_eq!(editor.undo(), Some(StChange::RowRemoved(2)));
+		assert_eq!(editor.undo(), Some(StChange::Cell(3)));
+		assert_eq!(editor.undo(), None);
+		assert_eq!(editor.table(), &original);
+		assert!(!editor.is_dirty());
+		
+		assert_eq!(editor.redo(), Some(StChange::Cell(3)));
+		assert_eq!(editor.redo(), Some(StChange::RowInserted(2)));
+		assert_eq!(texts(&editor), ["key", "english", "hello", "Hi", "", "", "bye", "Bye"]);
+		editor.set_text(0, "id").unwrap();
+		assert!(!editor.can_redo());
+	}
+	
+	#[test]
+	fn saved_state() {
+		let mut editor = StEditor::new(sample_table());
+		editor.set_text(2, "hi").unwrap();
+		let saving = editor.state();
+		editor.set_text(2, "hey").unwrap();
+		editor.mark_saved(saving);
+		assert!(editor.is_dirty());
+		editor.undo();
+		assert!(!editor.is_dirty());
+		
+		// A different edit from the same point isn't the saved table, even though as many edits were made
+		editor.undo();
+		editor.set_text(2, "yo").unwrap();
+		assert!(editor.is_dirty());
+	}
+	
+	#[test]
+	fn unencodable_text_is_rejected() {
+		let mut table = sample_table();
+		table.encoding = StEncoding::Windows1252;
+		let mut editor = StEditor::new(table);
+		assert!(editor.set_text(2, "こんにちは").is_err());
+		assert!(editor.set_text(2, "a\0b").is_err());
+		assert!(!editor.can_undo());
+	}
+	
+	#[test]
+	fn redecode_keeps_edits() {
+		let mut editor = StEditor::new(sample_table());
+		editor.redecode(StEncoding::ShiftJis);
+		// "テスト" in Shift-JIS
+		editor.set_text(3, "テスト").unwrap();
+		assert_eq!(editor.table().strings[3].raw(), b"\x83\x65\x83\x58\x83\x67");
+		editor.undo();
+		
+		editor.redecode(StEncoding::Windows1252);
+		assert!(!editor.is_dirty());
+		editor.redo();
+		assert_eq!(editor.table().strings[3].raw(), b"\x83\x65\x83\x58\x83\x67");
+		assert_eq!(editor.table().strings[3].text(), "\u{192}e\u{192}X\u{192}g");
+		assert_eq!(editor.table().encoding, StEncoding::Windows1252);
+	}
+	
+	#[test]
+	fn row_order() {
+		let mut editor = StEditor::new(sample_table());
+		let mut order = StRowOrder::new(editor.table());
+		assert_eq!(order.rows, [1, 2]);
+		
+		order.sort_by_key(|row| editor.table().strings[row * 2].text().to_string(), false);
+		assert_eq!(order.rows, [2, 1]);
+		order.apply(editor.insert_row(2));
+		assert_eq!(order.rows, [3, 1, 2]);
+		// Inserting at row 1 puts the new row after the field names, so it goes first
+		order.apply(editor.insert_row(1));
+		assert_eq!(order.rows, [1, 4, 2, 3]);
+		order.apply(editor.remove_row(2));
+		assert_eq!(order.rows, [1, 3, 2]);
+		order.apply(editor.undo().unwrap());
+		assert_eq!(order.rows, [1, 2, 4, 3]);
+		
+		order.sort_by_key(|row| editor.table().strings[row * 2].text().is_empty(), true);
+		assert_eq!(order.rows, [1, 3, 2, 4]);
+		assert_eq!(order.position_of(2), Some(2));
+		assert_eq!(order.row_at(4), None);
+		order.reset();
+		assert_eq!(order.rows, [1, 2, 3, 4]);
+	}
+}
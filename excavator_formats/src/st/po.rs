@@ -0,0 +1,487 @@
+//! Exporting string tables as gettext POT templates and PO translations, and importing translated PO files.
+//!
+//! Row 0 of a table names its fields. In every other row, field 0 is the key that identifies the row, which is written as
+//! the entry's `msgctxt`. If a key appears more than once, later rows get `#2`, `#3` and so on appended to it.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+
+use super::StTable;
+use crate::error::{Error, Result};
+
+/// What happened to each entry of an imported PO file, identified by `msgctxt`.
+///
+/// Only the entries in `changed` were written to the table. Everything else was left as it was.
+#[derive(Debug)]
+pub struct PoImport {
+	pub table: StTable,
+	pub changed: Vec<String>,
+	/// Entries marked `#, fuzzy`, which a translator still needs to check.
+	pub fuzzy: Vec<String>,
+	/// Entries with an empty `msgstr`.
+	pub untranslated: Vec<String>,
+	/// Entries whose `msgid` doesn't match the source text in the table anymore.
+	pub outdated: Vec<String>,
+	/// Entries whose `msgctxt` doesn't match any row, or that have no `msgctxt` at all.
+	pub unknown: Vec<String>,
+}
+
+/// Finds a field by the name in row 0 of the table.
+pub fn field_by_name(table: &StTable, name: &str) -> Option<usize> {
+	table.rows().next()?.iter().position(|string| string.text() == name)
+}
+
+/// Writes a template with the text of `source_field` as each `msgid`, and every `msgstr` empty.
+///
+/// Rows where the source text is empty have nothing to translate, so they're left out.
+/// Panics if `source_field` isn't less than the table's field count.
+pub fn export_pot<W: Write>(table: &StTable, source_field: usize, writer: W) -> Result<()> {
+	export(table, source_field, None, writer)
+}
+
+/// Writes a PO file translating `source_field` into `target_field`, with the table's current text as each `msgstr`.
+///
+/// Rows where the source text is empty have nothing to translate, so they're left out.
+/// Panics if either field isn't less than the table's field count.
+pub fn export_po<W: Write>(table: &StTable, source_field: usize, target_field: usize, writer: W) -> Result<()> {
+	export(table, source_field, Some(target_field), writer)
+}
+
+fn export<W: Write>(table: &StTable, source_field: usize, target_field: Option<usize>, mut writer: W) -> Result<()> {
+	assert!(source_field < table.field_count && target_field.is_none_or(|field| field < table.field_count));
+	let field_name = |field: usize| table.strings.get(field).map_or("", |string| string.text());
+	
+	if target_field.is_none() {
+		writeln!(writer, "#, fuzzy")?;
+	}
+	writeln!(writer, "msgid \"\"")?;
+	writeln!(writer, "msgstr \"\"")?;
+	writeln!(writer, "\"MIME-Version: 1.0\\n\"")?;
+	writeln!(writer, "\"Content-Type: text/plain; charset=UTF-8\\n\"")?;
+	writeln!(writer, "\"Content-Transfer-Encoding: 8bit\\n\"")?;
+	writeln!(writer, "\"Language: {}\\n\"", escape(target_field.map_or("", field_name)))?;
+	writeln!(writer, "\"X-Source-Field: {}\\n\"", escape(field_name(source_field)))?;
+	
+	for (row_index, (row, context)) in table.rows().zip(contexts(table)).enumerate().skip(1) {
+		let source = row[source_field].text();
+		if source.is_empty() {
+			continue;
+		}
+		writeln!(writer)?;
+		writeln!(writer, "#. row {row_index}")?;
+		write_field(&mut writer, "msgctxt", &context)?;
+		write_field(&mut writer, "msgid", source)?;
+		write_field(&mut writer, "msgstr", target_field.map_or("", |field| row[field].text()))?;
+	}
+	writer.flush()?;
+	Ok(())
+}
+
+/// Reads a translated PO file into a copy of `original`, writing each finished translation to `target_field`.
+///
+/// Fuzzy, untranslated, outdated and unknown entries are reported in the result instead of being written.
+/// Panics if either field isn't less than the table's field count.
+pub fn import_po<R: BufRead>(original: &StTable, source_field: usize, target_field: usize, reader: R) -> Result<PoImport> {
+	assert!(source_field < original.field_count && target_field < original.field_count);
+	let rows: HashMap<String, usize> = contexts(original).into_iter().enumerate().skip(1)
+		.map(|(row_index, context)| (context, row_index))
+		.collect();
+	
+	let mut result = PoImport {
+		table: original.clone(),
+		changed: Vec::new(),
+		fuzzy: Vec::new(),
+		untranslated: Vec::new(),
+		outdated: Vec::new(),
+		unknown: Vec::new(),
+	};
+	for entry in parse_po(reader)? {
+		let Some(context) = entry.context else {
+			if !entry.id.is_empty() {
+				result.unknown.push(entry.id);
+			}
+			continue;
+		};
+		let Some(&row_index) = rows.get(&context) else {
+			result.unknown.push(context);
+			continue;
+		};
+		
+		let field_count = original.field_count;
+		if entry.fuzzy {
+			result.fuzzy.push(context);
+		} else if entry.translation.is_empty() {
+			result.untranslated.push(context);
+		} else if entry.id != original.strings[row_index * field_count + source_field].text() {
+			result.outdated.push(context);
+		} else {
+			let changed = result.table.update_text(row_index * field_count + target_field, entry.translation)
+				.map_err(|e| Error::BadPo { line: entry.line, message: e.to_string() })?;
+			if changed {
+				result.changed.push(context);
+			}
+		}
+	}
+	Ok(result)
+}
+
+/// The `msgctxt` of every row, including row 0 so the positions line up.
+fn contexts(table: &StTable) -> Vec<String> {
+	let mut seen = HashSet::new();
+	let mut counts: HashMap<&str, usize> = HashMap::new();
+	table.rows().map(|row| {
+		let key = row.first().map_or("", |string| string.text());
+		let count = counts.entry(key).or_default();
+		*count += 1;
+		let mut context = if *count == 1 { key.to_string() } else { format!("{key}#{count}") };
+		// A key that already ends in something like "#2" could clash with a generated one.
+		while !seen.insert(context.clone()) {
+			*count += 1;
+			context = format!("{key}#{count}");
+		}
+		context
+	}).collect()
+}
+
+fn write_field<W: Write>(writer: &mut W, keyword: &str, text: &str) -> Result<()> {
+	let lines: Vec<&str> = text.split_inclusive('\n').collect();
+	if lines.len() > 1 {
+		writeln!(writer, "{keyword} \"\"")?;
+		for line in lines {
+			writeln!(writer, "\"{}\"", escape(line))?;
+		}
+	} else {
+		writeln!(writer, "{keyword} \"{}\"", escape(text))?;
+	}
+	Ok(())
+}
+
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'\\' => escaped.push_str("\\\\"),
+			'"' => escaped.push_str("\\\""),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => escaped.push_str("\\r"),
+			'\t' => escaped.push_str("\\t"),
+			c if c.is_ascii_control() => escaped.push_str(&format!("\\{:03o}", c as u32)),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+/// Reads a C-style quoted string, like `"a\tb"`.
+fn parse_quoted(text: &str) -> std::result::Result<String, String> {
+	let inner = text.trim().strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+		.ok_or_else(|| format!("expected a quoted string, found {text:?}"))?;
+	
+	let mut unescaped = String::with_capacity(inner.len());
+	let mut chars = inner.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'"' => return Err("unescaped quote inside a string".to_string()),
+			'\\' => {},
+			c => {
+				unescaped.push(c);
+				continue;
+			},
+		}
+		let escaped = match chars.next() {
+			Some('\\') => '\\',
+			Some('"') => '"',
+			Some('n') => '\n',
+			Some('r') => '\r',
+			Some('t') => '\t',
+			Some('a') => '\x07',
+			Some('b') => '\x08',
+			Some('f') => '\x0C',
+			Some('v') => '\x0B',
+			Some(first @ '0'..='7') => {
+				let mut code = first.to_digit(8).unwrap();
+				for _ in 0..2 {
+					let Some(digit) = chars.peek().and_then(|c| c.to_digit(8)) else { break; };
+					code = code * 8 + digit;
+					chars.next();
+				}
+				char::from_u32(code).filter(char::is_ascii).ok_or_else(|| format!("octal escape \\{code:o} isn't ASCII"))?
+			},
+			Some(other) => return Err(format!("unknown escape sequence \\{other}")),
+			None => return Err("a lone backslash ends the string".to_string()),
+		};
+		unescaped.push(escaped);
+	}
+	Ok(unescaped)
+}
+
+struct PoEntry {
+	/// Where the entry starts, for error messages.
+	line: u64,
+	fuzzy: bool,
+	context: Option<String>,
+	id: String,
+	translation: String,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum PoKeyword {
+	Context,
+	Id,
+	Translation,
+}
+
+fn parse_po<R: BufRead>(reader: R) -> Result<Vec<PoEntry>> {
+	let mut entries = Vec::new();
+	let mut entry: Option<PoEntry> = None;
+	let mut pending_fuzzy = false;
+	let mut last_keyword: Option<PoKeyword> = None;
+	
+	for (line_index, line) in reader.lines().enumerate() {
+		let line_number = line_index as u64 + 1;
+		let line = line?;
+		let line = line.trim();
+		let bad = |message: String| Error::BadPo { line: line_number, message };
+		
+		if line.is_empty() || line.starts_with('#') {
+			// Anything that isn't a string ends the entry before it.
+			finish_entry(&mut entries, &mut entry, &mut last_keyword)?;
+			// Obsolete entries (`#~`) are ignored entirely.
+			if let Some(flags) = line.strip_prefix("#,") {
+				pending_fuzzy |= flags.split(',').any(|flag| flag.trim() == "fuzzy");
+			}
+			continue;
+		}
+		
+		if line.starts_with('"') {
+			let text = parse_quoted(line).map_err(bad)?;
+			let (Some(current), Some(keyword)) = (&mut entry, last_keyword) else {
+				return Err(bad("string continues nothing".to_string()));
+			};
+			match keyword {
+				PoKeyword::Context => current.context.get_or_insert_default().push_str(&text),
+				PoKeyword::Id => current.id.push_str(&text),
+				PoKeyword::Translation => current.translation.push_str(&text),
+			}
+			continue;
+		}
+		
+		let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+		let keyword = match keyword {
+			"msgctxt" => PoKeyword::Context,
+			"msgid" => PoKeyword::Id,
+			"msgstr" => PoKeyword::Translation,
+			"msgid_plural" => return Err(bad("plural forms can't be stored in string tables".to_string())),
+			other if other.starts_with("msgstr[") => {
+				return Err(bad("plural forms can't be stored in string tables".to_string()));
+			},
+			other => return Err(bad(format!("unknown keyword {other:?}"))),
+		};
+		let text = parse_quoted(rest).map_err(bad)?;
+		
+		// A new msgctxt or msgid after a msgstr starts the next entry, even without a blank line between them.
+		if keyword != PoKeyword::Translation && last_keyword == Some(PoKeyword::Translation) {
+			finish_entry(&mut entries, &mut entry, &mut last_keyword)?;
+		}
+		let current = entry.get_or_insert_with(|| PoEntry {
+			line: line_number,
+			fuzzy: std::mem::take(&mut pending_fuzzy),
+			context: None,
+			id: String::new(),
+			translation: String::new(),
+		});
+		let order_ok = match keyword {
+			PoKeyword::Context => last_keyword.is_none() && current.context.is_none(),
+			PoKeyword::Id => last_keyword.is_none_or(|last| last == PoKeyword::Context),
+			PoKeyword::Translation => last_keyword == Some(PoKeyword::Id),
+		};
+		if !order_ok {
+			return Err(bad("entries must be msgctxt, then msgid, then msgstr".to_string()));
+		}
+		match keyword {
+			PoKeyword::Context => current.context = Some(text),
+			PoKeyword::Id => current.id = text,
+			PoKeyword::Translation => current.translation = text,
+		}
+		last_keyword = Some(keyword);
+	}
+	finish_entry(&mut entries, &mut entry, &mut last_keyword)?;
+	Ok(entries)
+}
+
+fn finish_entry(entries: &mut Vec<PoEntry>, entry: &mut Option<PoEntry>, last_keyword: &mut Option<PoKeyword>) -> Result<()> {
+	if let Some(finished) = entry.take() {
+		if *last_keyword != Some(PoKeyword::Translation) {
+			return Err(Error::BadPo { line: finished.line, message: "entry has no msgstr".to_string() });
+		}
+		entries.push(finished);
+	}
+	*last_keyword = None;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::st::{StEncoding, StExtendedData, StString, string_checksum};
+	
+	fn sample_table() -> StTable {
+		let text = |t: &str| StString::from_text(t, StEncoding::Utf8).unwrap();
+		let strings = vec![
+			text("Key"), text("English"), text("French"),
+			text("GREETING"), text("Hello, \"world\""), text("Bonjour"),
+			text("LINES"), text("One\nTwo\n"), text(""),
+			text("GREETING"), text("Hi again"), StString::from_raw(b"\xFF".to_vec(), StEncoding::Utf8),
+			text("BLANK"), text(""), text(""),
+		];
+		let checksums = strings.iter().map(|s| string_checksum(s.raw())).collect();
+		StTable {
+			field_count: 3,
+			strings,
+			encoding: StEncoding::Utf8,
+			extended: Some(StExtendedData { checksums, extra1: vec![], extra2: vec![] }),
+			layout: None,
+		}
+	}
+	
+	fn export_to_string(table: &StTable, target_field: Option<usize>) -> String {
+		let mut buf = Vec::new();
+		match target_field {
+			Some(field) => export_po(table, 1, field, &mut buf).unwrap(),
+			None => export_pot(table, 1, &mut buf).unwrap(),
+		}
+		String::from_utf8(buf).unwrap()
+	}
+	
+	#[test]
+	fn quoted_round_trip() {
+		let text = "a\\b\"c\nd\re\tf\u{1}g\u{7F}h\u{85}";
+		assert_eq!(escape(text), "a\\\\b\\\"c\\nd\\re\\tf\\001g\\177h\u{85}");
+		assert_eq!(parse_quoted(&format!("\"{}\"", escape(text))).unwrap(), text);
+		assert_eq!(parse_quoted(r#""\a\b\f\v\0\12""#).unwrap(), "\x07\x08\x0C\x0B\0\n");
+		assert!(parse_quoted(r#""\q""#).is_err());
+		assert!(parse_quoted(r#""\400""#).is_err());
+		assert!(parse_quoted(r#""a"b""#).is_err());
+		assert!(parse_quoted("unquoted").is_err());
+	}
+	
+	#[test]
+	fn export_template() {
+		let expected = concat!(
+			"#, fuzzy\n",
+			"msgid \"\"\n",
+			"msgstr \"\"\n",
+			"\"MIME-Version: 1.0\\n\"\n",
+			"\"Content-Type: text/plain; charset=UTF-8\\n\"\n",
+			"\"Content-Transfer-Encoding: 8bit\\n\"\n",
+			"\"Language: \\n\"\n",
+			"\"X-Source-Field: English\\n\"\n",
+			"\n",
+			"#. row 1\n",
+			"msgctxt \"GREETING\"\n",
+			"msgid \"Hello, \\\"world\\\"\"\n",
+			"msgstr \"\"\n",
+			"\n",
+			"#. row 2\n",
+			"msgctxt \"LINES\"\n",
+			"msgid \"\"\n",
+			"\"One\\n\"\n",
+			"\"Two\\n\"\n",
+			"msgstr \"\"\n",
+			"\n",
+			"#. row 3\n",
+			"msgctxt \"GREETING#2\"\n",
+			"msgid \"Hi again\"\n",
+			"msgstr \"\"\n",
+		);
+		assert_eq!(export_to_string(&sample_table(), None), expected);
+	}
+	
+	#[test]
+	fn unchanged_import_is_identical() {
+		let table = sample_table();
+		let po = export_to_string(&table, Some(2));
+		assert!(po.contains("\"Language: French\\n\""));
+		
+		let imported = import_po(&table, 1, 2, po.as_bytes()).unwrap();
+		assert_eq!(imported.table, table);
+		assert!(imported.changed.is_empty());
+		assert_eq!(imported.untranslated, ["LINES"]);
+		assert!(imported.fuzzy.is_empty() && imported.outdated.is_empty() && imported.unknown.is_empty());
+	}
+	
+	#[test]
+	fn translated_import() {
+		let table = sample_table();
+		let po = concat!(
+			"msgid \"\"\n",
+			"msgstr \"Language: French\\n\"\n",
+			"\n",
+			"#. row 2\n",
+			"msgctxt \"LINES\"\n",
+			"msgid \"One\\n\"\n",
+			"\"Two\\n\"\n",
+			"msgstr \"\"\n",
+			"\"Un\\nDeux\\n\"\n",
+			"msgctxt \"GREETING\"\n",
+			"msgid \"Hello, \\\"world\\\"\"\n",
+			"msgstr \"Salut\"\n",
+			"\n",
+			"#, c-format, fuzzy\n",
+			"msgctxt \"GREETING#2\"\n",
+			"msgid \"Hi again\"\n",
+			"msgstr \"Re-bonjour\"\n",
+			"\n",
+			"msgctxt \"BLANK\"\n",
+			"msgid \"Used to have text\"\n",
+			"msgstr \"Avait du texte\"\n",
+			"\n",
+			"msgctxt \"MISSING\"\n",
+			"msgid \"Gone\"\n",
+			"msgstr \"Parti\"\n",
+			"\n",
+			"#~ msgctxt \"OLD\"\n",
+			"#~ msgid \"Old\"\n",
+			"#~ msgstr \"Vieux\"\n",
+		);
+		let imported = import_po(&table, 1, 2, po.as_bytes()).unwrap();
+		
+		assert_eq!(imported.changed, ["LINES", "GREETING"]);
+		assert_eq!(imported.fuzzy, ["GREETING#2"]);
+		assert_eq!(imported.outdated, ["BLANK"]);
+		assert_eq!(imported.unknown, ["MISSING"]);
+		assert!(imported.untranslated.is_empty());
+		
+		let strings = &imported.table.strings;
+		assert_eq!(strings[5].text(), "Salut");
+		assert_eq!(strings[8].text(), "Un\nDeux\n");
+		assert_eq!(strings[11], table.strings[11]);
+		assert_eq!(strings[14], table.strings[14]);
+		assert_eq!(imported.table.extended, table.extended);
+	}
+	
+	#[test]
+	fn bad_po_files() {
+		let table = sample_table();
+		let line_of = |po: &str| match import_po(&table, 1, 2, po.as_bytes()) {
+			Err(Error::BadPo { line, .. }) => line,
+			other => panic!("expected BadPo, got {other:?}"),
+		};
+		
+		assert_eq!(line_of("msgid \"a\"\nmsgstr \"b\"\n\nmsgid \"c\"\nmsgid_plural \"d\"\n"), 5);
+		assert_eq!(line_of("msgid \"a\"\nmsgstr[0] \"b\"\n"), 2);
+		assert_eq!(line_of("msgid \"a\"\nmsgstr \"b\\q\"\n"), 2);
+		assert_eq!(line_of("msgstr \"b\"\n"), 1);
+		assert_eq!(line_of("\"stray\"\n"), 1);
+		assert_eq!(line_of("msgid \"a\"\nmsgctxt \"b\"\n"), 2);
+		assert_eq!(line_of("msgctxt \"GREETING\"\n\nmsgid \"a\"\nmsgstr \"b\"\n"), 1);
+		assert_eq!(line_of("msgfoo \"a\"\n"), 1);
+	}
+	
+	#[test]
+	fn fields_by_name() {
+		let table = sample_table();
+		assert_eq!(field_by_name(&table, "French"), Some(2));
+		assert_eq!(field_by_name(&table, "German"), None);
+	}
+}
@@ -0,0 +1,228 @@
+//! Searching the text of every string table in the game data at once, to find where a line lives.
+//!
+//! Building a [`StIndex`] reads every `.stl`, `.stm` and `.stb` file under a path, including ones inside archives.
+//! After that, queries only look at the text kept in memory.
+
+use regex::{Regex, RegexBuilder};
+
+use super::{read_st_with_encoding, StEncoding};
+use crate::FileType;
+use crate::error::Error;
+use crate::vfs::find::{find, NamePattern};
+use crate::vfs::{Interruptible, Vfs, VfsPath};
+
+/// The text of one string table, as kept by [`StIndex`].
+#[derive(Clone, Debug)]
+pub struct IndexedTable {
+	pub path: VfsPath,
+	pub field_count: usize,
+	/// Stored row by row, like [`super::StTable::strings`].
+	texts: Vec<String>,
+}
+
+impl IndexedTable {
+	pub fn text(&self, row: usize, field: usize) -> Option<&str> {
+		self.texts.get(row * self.field_count + field).map(String::as_str)
+	}
+	
+	/// The name of a field, from row 0 of the table.
+	pub fn field_name(&self, field: usize) -> Option<&str> {
+		self.text(0, field)
+	}
+}
+
+/// What to look for in the text of each string.
+#[derive(Clone, Debug)]
+pub struct StQuery(Regex);
+
+impl StQuery {
+	/// Matches strings containing `text` anywhere, ignoring case.
+	pub fn substring(text: &str) -> Self {
+		let regex = RegexBuilder::new(&regex::escape(text))
+			.case_insensitive(true)
+			.build()
+			.expect("escaped text is always a valid regex");
+		Self(regex)
+	}
+	
+	/// Matches strings where the regular expression matches anywhere. Case matters unless the pattern starts with `(?i)`.
+	pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+		Regex::new(pattern).map(Self)
+	}
+	
+	pub fn is_match(&self, text: &str) -> bool {
+		self.0.is_match(text)
+	}
+}
+
+/// Where a query matched: which table in [`StIndex::tables`], and which cell of it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StMatch {
+	pub table: usize,
+	pub row: usize,
+	pub field: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct StIndex {
+	encoding: StEncoding,
+	tables: Vec<IndexedTable>,
+	errors: Vec<(VfsPath, Error)>,
+}
+
+impl StIndex {
+	/// Reads every string table under each of `roots`, decoding their strings with `encoding`.
+	/// Tables are found by their extension.
+	///
+	/// Directories, archives and tables that can't be read are skipped, and listed in [`Self::errors`].
+	/// `keep_going` is asked before each listing and each table, and while they're being read. Once it returns `false`,
+	/// the index only has the tables read so far.
+	pub fn build<V: Vfs + ?Sized>(vfs: &V, roots: &[VfsPath], encoding: StEncoding, mut keep_going: impl FnMut() -> bool) -> Self {
+		let mut index = Self { encoding, ..Self::default() };
+		let pattern = NamePattern::new("*.st?");
+		for root in roots {
+			let found = find(vfs, root, &pattern, &mut keep_going);
+			index.errors.extend(found.errors);
+			for found in found.matches {
+				if !keep_going() { return index; }
+				let path = found.item.path;
+				let stl = match path.file_type() {
+					FileType::Stl => true,
+					FileType::StmOrStb => false,
+					_ => continue,
+				};
+				let table = vfs.open_while(&path, &mut keep_going)
+					.and_then(|reader| read_st_with_encoding(&mut Interruptible::new(reader, &mut keep_going), stl, encoding));
+				match table {
+					Ok(table) => index.tables.push(IndexedTable {
+						path,
+						field_count: table.field_count,
+						texts: table.strings.into_iter().map(|string| string.text().to_string()).collect(),
+					}),
+					// Reading the table was given up on because the index is being stopped
+					Err(_) if !keep_going() => return index,
+					Err(e) => index.errors.push((path, e)),
+				}
+			}
+			if found.stopped { break; }
+		}
+		index
+	}
+	
+	/// What the strings were decoded with.
+	pub fn encoding(&self) -> StEncoding {
+		self.encoding
+	}
+	
+	pub fn tables(&self) -> &[IndexedTable] {
+		&self.tables
+	}
+	
+	/// Tables, directories and archives that couldn't be read.
+	pub fn errors(&self) -> &[(VfsPath, Error)] {
+		&self.errors
+	}
+	
+	/// Finds every string the query matches, in order of table, row and field.
+	///
+	/// Row 0 only names the fields, so it isn't searched.
+	pub fn search(&self, query: &StQuery) -> Vec<StMatch> {
+		let mut matches = Vec::new();
+		for (table_index, table) in self.tables.iter().enumerate() {
+			let cells = table.texts.iter().enumerate().skip(table.field_count);
+			matches.extend(cells.filter(|(_, text)| query.is_match(text)).map(|(i, _)| StMatch {
+				table: table_index,
+				row: i / table.field_count,
+				field: i % table.field_count,
+			}));
+		}
+		matches
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::pak::PakWriter;
+	use crate::st::{write_st, StString, StTable};
+	use crate::vfs::MountedVfs;
+	use crate::vfs::tests::test_directory;
+	use std::ffi::CString;
+	use std::fs;
+	use std::io::Cursor;
+	
+	fn table_bytes(texts: &[&str]) -> Vec<u8> {
+		encoded_table_bytes(texts, StEncoding::Utf8)
+	}
+	
+	fn encoded_table_bytes(texts: &[&str], encoding: StEncoding) -> Vec<u8> {
+		let table = StTable {
+			field_count: 2,
+			strings: texts.iter().map(|text| StString::from_text(*text, encoding).unwrap()).collect(),
+			encoding,
+			extended: None,
+			layout: None,
+		};
+		let mut writer = Cursor::new(Vec::new());
+		write_st(&mut writer, &table).unwrap();
+		writer.into_inner()
+	}
+	
+	/// A loose table, and one inside an archive.
+	fn sample_data(name: &str) -> std::path::PathBuf {
+		let root = test_directory(name);
+		fs::write(root.join("menu.stl"), table_bytes(&["id", "text", "start", "Start game", "quit", "Quit"])).unwrap();
+		let mut pak = PakWriter::new();
+		pak.add_file(CString::new("talk.stl").unwrap(), table_bytes(&["id", "text", "hello", "Hello, start here!"]));
+		pak.add_file(CString::new("readme.txt").unwrap(), b"start".to_vec());
+		let mut writer = Cursor::new(Vec::new());
+		pak.write(&mut writer).unwrap();
+		fs::write(root.join("data.pak"), writer.into_inner()).unwrap();
+		fs::write(root.join("broken.stl"), b"nope").unwrap();
+		root
+	}
+	
+	#[test]
+	fn search_loose_and_archived_tables() {
+		let root = sample_data("st_search");
+		let vfs = MountedVfs::new();
+		let index = StIndex::build(&vfs, &[VfsPath::disk(&root)], StEncoding::Utf8, || true);
+		assert_eq!(index.tables().len(), 2);
+		assert_eq!(index.errors().len(), 1);
+		assert_eq!(index.errors()[0].0, VfsPath::disk(root.join("broken.stl")));
+		
+		let found: Vec<_> = index.search(&StQuery::substring("START")).into_iter().map(|found| {
+			let table = &index.tables()[found.table];
+			(table.path.name().into_owned(), found.row, table.text(found.row, found.field).unwrap().to_string())
+		}).collect();
+		assert_eq!(found, [
+			("talk.stl".to_string(), 1, "Hello, start here!".to_string()),
+			("menu.stl".to_string(), 1, "start".to_string()),
+			("menu.stl".to_string(), 1, "Start game".to_string()),
+		]);
+		
+		let regex = StQuery::regex("^[A-Z][a-z]+$").unwrap();
+		let found: Vec<_> = index.search(&regex).into_iter().map(|found| (found.row, found.field)).collect();
+		assert_eq!(found, [(2, 1)]);
+		assert_eq!(index.tables()[1].field_name(1), Some("text"));
+		assert!(StQuery::regex("(").is_err());
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+	
+	#[test]
+	fn search_other_encodings() {
+		let root = test_directory("st_search_encoding");
+		fs::write(root.join("talk.stl"), encoded_table_bytes(&["id", "text", "hello", "こんにちは"], StEncoding::ShiftJis)).unwrap();
+		let vfs = MountedVfs::new();
+		let query = StQuery::substring("こんにちは");
+		
+		let index = StIndex::build(&vfs, &[VfsPath::disk(&root)], StEncoding::Utf8, || true);
+		assert_eq!(index.search(&query), []);
+		let index = StIndex::build(&vfs, &[VfsPath::disk(&root)], StEncoding::ShiftJis, || true);
+		assert_eq!(index.encoding(), StEncoding::ShiftJis);
+		assert_eq!(index.search(&query), [StMatch { table: 0, row: 1, field: 1 }]);
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+}
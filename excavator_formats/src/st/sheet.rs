@@ -0,0 +1,227 @@
+//! Exporting string tables to CSV/TSV spreadsheets, and importing edited spreadsheets back.
+//!
+//! The first row of a table is written as the spreadsheet's header. Backslashes, newlines, tabs and other
+//! control characters are written as escape sequences (`\\`, `\n`, `\r`, `\t`, `\u{1F}`), since spreadsheet
+//! programs tend to mangle them.
+
+use std::io::{Read, Write};
+
+use super::StTable;
+use crate::error::{Error, Result};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SheetFormat {
+	Csv,
+	Tsv,
+}
+
+impl SheetFormat {
+	fn delimiter(self) -> u8 {
+		match self {
+			Self::Csv => b',',
+			Self::Tsv => b'\t',
+		}
+	}
+}
+
+pub fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => escaped.push_str("\\r"),
+			'\t' => escaped.push_str("\\t"),
+			c if c.is_control() => escaped.push_str(&format!("\\u{{{:X}}}", c as u32)),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+/// Reverses [`escape`]. The error describes the first invalid escape sequence.
+pub fn unescape(text: &str) -> std::result::Result<String, String> {
+	let mut unescaped = String::with_capacity(text.len());
+	let mut chars = text.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			unescaped.push(c);
+			continue;
+		}
+		match chars.next() {
+			Some('\\') => unescaped.push('\\'),
+			Some('n') => unescaped.push('\n'),
+			Some('r') => unescaped.push('\r'),
+			Some('t') => unescaped.push('\t'),
+			Some('u') => {
+				let rest = chars.as_str();
+				let code = rest.strip_prefix('{')
+					.and_then(|rest| rest.split_once('}'))
+					.and_then(|(hex, _)| u32::from_str_radix(hex, 16).ok().map(|code| (hex.len(), code)));
+				let Some((hex_len, code)) = code else {
+					return Err("\\u must be followed by a hex number in braces".to_string());
+				};
+				unescaped.push(char::from_u32(code).ok_or_else(|| format!("\\u{{{code:X}}} isn't a valid character"))?);
+				chars = rest[hex_len + 2..].chars();
+			},
+			Some(other) => return Err(format!("unknown escape sequence \\{other}")),
+			None => return Err("a lone backslash ends the text".to_string()),
+		}
+	}
+	Ok(unescaped)
+}
+
+/// Writes every row of the table, starting with the field names.
+pub fn export_sheet<W: Write>(table: &StTable, writer: W, format: SheetFormat) -> Result<()> {
+	let mut sheet = csv::WriterBuilder::new().delimiter(format.delimiter()).from_writer(writer);
+	for row in table.rows() {
+		sheet.write_record(row.iter().map(|string| escape(string.text()))).map_err(csv_error)?;
+	}
+	sheet.flush()?;
+	Ok(())
+}
+
+/// Reads an edited spreadsheet back into a copy of `original`, which must have the same number of rows and fields.
+///
+/// Strings whose text didn't change are kept exactly as they were, including any bytes that couldn't be decoded.
+/// Changed strings are encoded with the table's encoding. Their stored checksums are kept as they were.
+pub fn import_sheet<R: Read>(original: &StTable, reader: R, format: SheetFormat) -> Result<StTable> {
+	let mut sheet = csv::ReaderBuilder::new()
+		.delimiter(format.delimiter())
+		.has_headers(false)
+		.flexible(true)
+		.from_reader(reader);
+	
+	let mut table = original.clone();
+	let field_count = table.field_count;
+	let mut row_count = 0;
+	for (row_index, record) in sheet.records().enumerate() {
+		let record = record.map_err(csv_error)?;
+		let line = record.position().map_or(0, |position| position.line());
+		if row_index >= original.entry_count() {
+			row_count = row_index + 1;
+			continue;
+		}
+		if record.len() != field_count {
+			return Err(Error::BadSheet {
+				line,
+				message: format!("row has {} columns, but the table has {field_count} fields", record.len()),
+			});
+		}
+		
+		for (field_index, cell) in record.iter().enumerate() {
+			let index = row_index * field_count + field_index;
+			let text = unescape(cell).map_err(|message| Error::BadSheet { line, message })?;
+			table.update_text(index, text).map_err(|e| Error::BadSheet { line, message: e.to_string() })?;
+		}
+		row_count = row_index + 1;
+	}
+	
+	if row_count != original.entry_count() {
+		return Err(Error::SheetRowCount { expected: original.entry_count() as u64, found: row_count as u64 });
+	}
+	Ok(table)
+}
+
+fn csv_error(error: csv::Error) -> Error {
+	let line = error.position().map_or(0, |position| position.line());
+	match error.into_kind() {
+		csv::ErrorKind::Io(e) => Error::Io(e),
+		other => Error::BadSheet { line, message: format!("{other:?}") },
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::st::{StEncoding, StExtendedData, StString, string_checksum};
+	
+	fn sample_table() -> StTable {
+		let text = |t: &str| StString::from_text(t, StEncoding::Utf8).unwrap();
+		let strings = vec![
+			text("Key"), text("English"),
+			text("GREETING"), text("Hello,\n\"world\"\t!"),
+			text("PATH"), StString::from_raw(b"C:\\\xFF".to_vec(), StEncoding::Utf8),
+		];
+		let checksums = strings.iter().map(|s| string_checksum(s.raw())).collect();
+		StTable {
+			field_count: 2,
+			strings,
+			encoding: StEncoding::Utf8,
+			extended: Some(StExtendedData { checksums, extra1: vec![], extra2: vec![] }),
+			layout: None,
+		}
+	}
+	
+	fn export_to_string(table: &StTable, format: SheetFormat) -> String {
+		let mut buf = Vec::new();
+		export_sheet(table, &mut buf, format).unwrap();
+		String::from_utf8(buf).unwrap()
+	}
+	
+	#[test]
+	fn escape_round_trip() {
+		let text = "a\\b\nc\rd\te\u{1}f\u{7F}g";
+		assert_eq!(escape(text), "a\\\\b\\nc\\rd\\te\\u{1}f\\u{7F}g");
+		assert_eq!(unescape(&escape(text)).unwrap(), text);
+		assert!(unescape("\\q").is_err());
+		assert!(unescape("\\u{110000}").is_err());
+		assert!(unescape("\\u12").is_err());
+		assert!(unescape("trailing\\").is_err());
+	}
+	
+	#[test]
+	fn export_csv_and_tsv() {
+		let table = sample_table();
+		assert_eq!(
+			export_to_string(&table, SheetFormat::Csv),
+			"Key,English\nGREETING,\"Hello,\\n\"\"world\"\"\\t!\"\nPATH,C:\\\\\u{FFFD}\n",
+		);
+		assert_eq!(
+			export_to_string(&table, SheetFormat::Tsv),
+			"Key\tEnglish\nGREETING\t\"Hello,\\n\"\"world\"\"\\t!\"\nPATH\tC:\\\\\u{FFFD}\n",
+		);
+	}
+	
+	#[test]
+	fn unchanged_import_is_identical() {
+		let table = sample_table();
+		for format in [SheetFormat::Csv, SheetFormat::Tsv] {
+			let sheet = export_to_string(&table, format);
+			assert_eq!(import_sheet(&table, sheet.as_bytes(), format).unwrap(), table);
+		}
+	}
+	
+	#[test]
+	fn edited_import() {
+		let table = sample_table();
+		let sheet = export_to_string(&table, SheetFormat::Csv).replace("GREETING,\"Hello,", "GREETING,\"Goodbye,");
+		let imported = import_sheet(&table, sheet.as_bytes(), SheetFormat::Csv).unwrap();
+		
+		assert_eq!(imported.strings[3].text(), "Goodbye,\n\"world\"\t!");
+		assert_eq!(imported.extended, table.extended);
+		assert_eq!(imported.strings[5], table.strings[5]);
+	}
+	
+	#[test]
+	fn import_rejects_wrong_shape() {
+		let table = sample_table();
+		let sheet = export_to_string(&table, SheetFormat::Csv);
+		
+		let extra_column = sheet.replacen("GREETING,", "GREETING,oops,", 1);
+		let result = import_sheet(&table, extra_column.as_bytes(), SheetFormat::Csv);
+		assert!(matches!(result, Err(Error::BadSheet { line: 2, .. })), "{result:?}");
+		
+		let extra_row = format!("{sheet}ANOTHER,row\n");
+		let result = import_sheet(&table, extra_row.as_bytes(), SheetFormat::Csv);
+		assert!(matches!(result, Err(Error::SheetRowCount { expected: 3, found: 4 })), "{result:?}");
+		
+		let missing_row = sheet.lines().take(2).collect::<Vec<_>>().join("\n");
+		let result = import_sheet(&table, missing_row.as_bytes(), SheetFormat::Csv);
+		assert!(matches!(result, Err(Error::SheetRowCount { expected: 3, found: 2 })), "{result:?}");
+		
+		let bad_escape = sheet.replace("PATH", "PA\\TH");
+		let result = import_sheet(&table, bad_escape.as_bytes(), SheetFormat::Csv);
+		assert!(matches!(result, Err(Error::BadSheet { line: 3, .. })), "{result:?}");
+	}
+}
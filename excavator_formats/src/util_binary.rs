@@ -1,8 +1,11 @@
 //! General functions used for parsing binary formats.
 
-use std::io::{BufRead, Read, Seek, SeekFrom};
+use std::borrow::Cow;
+use std::io::{BufRead, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
 
-use binrw::{BinRead, BinResult, Endian, VecArgs};
+use binrw::{BinRead, BinWrite, Endian, VecArgs};
+
+use crate::error::{Error, Result};
 
 /// Using `BufReader::seek` always discards the internal buffer, even if the seek position is within it.
 /// This function wraps `BufReader::seek_relative`, so the buffer is used if applicable.
@@ -15,7 +18,19 @@ pub fn seek_absolute<R: BufRead + Seek>(reader: &mut R, position: u64) -> std::i
 	Ok(())
 }
 
-pub fn read_pointers<R: Read + Seek>(reader: &mut R, count: usize) -> BinResult<Vec<u64>> {
+/// Reads a little-endian structure, reporting any failure at the offset where the structure starts.
+pub fn read_struct<T, R>(reader: &mut R) -> Result<T>
+where
+	T: BinRead,
+	for<'a> T::Args<'a>: Default,
+	R: Read + Seek,
+{
+	let offset = reader.stream_position()?;
+	T::read_options(reader, Endian::Little, <_>::default()).map_err(|e| Error::from_binrw(e, offset))
+}
+
+pub fn read_pointers<R: Read + Seek>(reader: &mut R, count: usize) -> Result<Vec<u64>> {
+	let offset = reader.stream_position()?;
 	Vec::<u64>::read_options(
 		reader,
 		Endian::Little,
@@ -23,5 +38,65 @@ pub fn read_pointers<R: Read + Seek>(reader: &mut R, count: usize) -> BinResult<
 			count,
 			inner: <_>::default(),
 		},
-	)
+	).map_err(|e| match Error::from_binrw(e, offset) {
+		Error::Truncated { offset } => Error::TruncatedTable { offset, count: count as u64 },
+		other => other,
+	})
+}
+
+/// Reads a NUL-terminated string, returning it without the terminator.
+pub fn read_nul_terminated<R: BufRead + Seek>(reader: &mut R) -> Result<Vec<u8>> {
+	let offset = reader.stream_position()?;
+	let mut buf = Vec::<u8>::new();
+	reader.read_until(0, &mut buf)?;
+	match buf.pop() {
+		Some(0) => Ok(buf),
+		_ => Err(Error::MissingNulTerminator { offset }),
+	}
+}
+
+/// Converts a count read from the file at `offset` into a `usize`.
+pub fn count_to_usize(count: impl TryInto<usize>, offset: u64) -> Result<usize> {
+	count.try_into().map_err(|_| Error::CountOverflow { offset })
+}
+
+/// Writes zeroes until the writer reaches `position`.
+pub fn write_padding<W: Write + Seek>(writer: &mut W, position: u64) -> std::io::Result<()> {
+	let current = writer.stream_position()?;
+	let padding = position.checked_sub(current).ok_or(ErrorKind::InvalidInput)?;
+	std::io::copy(&mut std::io::repeat(0).take(padding), writer)?;
+	Ok(())
+}
+
+/// Writes a little-endian structure into a new buffer, reporting any failure at `offset`, where it's going to end up.
+pub fn struct_to_bytes<T>(value: &T, offset: u64) -> Result<Vec<u8>>
+where
+	T: BinWrite,
+	for<'a> T::Args<'a>: Default,
+{
+	let mut buffer = Cursor::new(Vec::new());
+	value.write_le(&mut buffer).map_err(|e| Error::from_binrw(e, offset))?;
+	Ok(buffer.into_inner())
+}
+
+/// Writes each piece at its offset from the beginning of `writer`, with zeroes in the gaps between them,
+/// and after the last one until the output is at least `length` bytes long.
+///
+/// Pieces may overlap, like strings that share their ending, as long as they agree on the overlapping bytes.
+pub fn write_pieces<W: Write + Seek>(writer: &mut W, mut pieces: Vec<(u64, Cow<[u8]>)>, length: u64) -> std::io::Result<()> {
+	pieces.sort_by_key(|piece| piece.0);
+	let mut end = 0;
+	writer.rewind()?;
+	for (offset, bytes) in pieces {
+		if offset > end {
+			writer.seek(SeekFrom::Start(end))?;
+			write_padding(writer, offset)?;
+		} else {
+			writer.seek(SeekFrom::Start(offset))?;
+		}
+		writer.write_all(&bytes)?;
+		end = end.max(offset + bytes.len() as u64);
+	}
+	writer.seek(SeekFrom::Start(end))?;
+	write_padding(writer, length.max(end))
 }
@@ -0,0 +1,118 @@
+//! Sanity checks for the offsets and counts read out of untrusted files.
+//!
+//! Every format here is a web of pointers. Before anything is read or allocated based on them,
+//! parsers hand the pointers to a [`Regions`] tracker, which makes sure they land inside the file,
+//! and that the structures they point at don't overlap.
+
+use std::io::{Seek, SeekFrom};
+
+use crate::error::{Error, Result};
+
+/// The regions of a file that have been claimed by some structure so far.
+pub struct Regions {
+	stream_length: u64,
+	/// Sorted by start offset. Regions never overlap, so they're also sorted by end offset.
+	claimed: Vec<(u64, u64)>,
+}
+
+impl Regions {
+	/// Measures the length of the stream, then puts it back where it was.
+	pub fn new<R: Seek>(reader: &mut R) -> Result<Self> {
+		let position = reader.stream_position()?;
+		let stream_length = reader.seek(SeekFrom::End(0))?;
+		reader.seek(SeekFrom::Start(position))?;
+		Ok(Self::with_length(stream_length))
+	}
+	
+	pub fn with_length(stream_length: u64) -> Self {
+		Self { stream_length, claimed: Vec::new() }
+	}
+	
+	pub fn stream_length(&self) -> u64 {
+		self.stream_length
+	}
+	
+	/// Checks that `pointer`, which was read from `stored_at`, points somewhere inside the file.
+	pub fn check_pointer(&self, pointer: u64, stored_at: u64) -> Result<()> {
+		if pointer < self.stream_length {
+			Ok(())
+		} else {
+			Err(Error::PointerOutOfBounds { offset: stored_at, pointer })
+		}
+	}
+	
+	/// Checks that a region lies inside the file, and doesn't overlap any region claimed earlier.
+	/// `stored_at` is where the pointer to the region was read from.
+	pub fn claim(&mut self, start: u64, length: u64, stored_at: u64) -> Result<()> {
+		if start > self.stream_length {
+			return Err(Error::PointerOutOfBounds { offset: stored_at, pointer: start });
+		}
+		let end = start.checked_add(length)
+			.filter(|end| *end <= self.stream_length)
+			.ok_or(Error::Truncated { offset: start })?;
+		self.insert(start, end)
+	}
+	
+	/// Like [`Self::claim`], for a table of `count` entries that are `entry_size` bytes each.
+	pub fn claim_table(&mut self, start: u64, count: u64, entry_size: u64, stored_at: u64) -> Result<()> {
+		if start > self.stream_length {
+			return Err(Error::PointerOutOfBounds { offset: stored_at, pointer: start });
+		}
+		let end = count.checked_mul(entry_size)
+			.and_then(|length| start.checked_add(length))
+			.filter(|end| *end <= self.stream_length)
+			.ok_or(Error::TruncatedTable { offset: start, count })?;
+		self.insert(start, end)
+	}
+	
+	fn insert(&mut self, start: u64, end: u64) -> Result<()> {
+		// Empty regions can't overlap anything, and don't need to be remembered
+		if start == end {
+			return Ok(());
+		}
+		
+		let index = self.claimed.partition_point(|region| region.1 <= start);
+		if let Some(&(other_start, other_end)) = self.claimed.get(index) && other_start < end && start < other_end {
+			return Err(Error::OverlappingRegions { offset: start, other: other_start });
+		}
+		self.claimed.insert(index, (start, end));
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn pointers_must_be_inside() {
+		let regions = Regions::with_length(100);
+		assert!(regions.check_pointer(99, 0).is_ok());
+		assert!(matches!(regions.check_pointer(100, 8), Err(Error::PointerOutOfBounds { offset: 8, pointer: 100 })));
+	}
+	
+	#[test]
+	fn regions_must_fit() {
+		let mut regions = Regions::with_length(100);
+		assert!(regions.claim(90, 10, 0).is_ok());
+		assert!(matches!(regions.claim(95, 10, 0), Err(Error::Truncated { offset: 95 })));
+		assert!(matches!(regions.claim(101, 0, 4), Err(Error::PointerOutOfBounds { offset: 4, pointer: 101 })));
+		assert!(matches!(regions.claim(1, u64::MAX, 0), Err(Error::Truncated { offset: 1 })));
+		assert!(matches!(regions.claim_table(0, u64::MAX, 8, 0), Err(Error::TruncatedTable { offset: 0, count: u64::MAX })));
+	}
+	
+	#[test]
+	fn regions_must_not_overlap() {
+		let mut regions = Regions::with_length(100);
+		regions.claim(10, 10, 0).unwrap();
+		regions.claim(30, 10, 0).unwrap();
+		regions.claim(20, 10, 0).unwrap();
+		regions.claim(0, 10, 0).unwrap();
+		regions.claim(25, 0, 0).unwrap();
+		
+		assert!(matches!(regions.claim(39, 2, 0), Err(Error::OverlappingRegions { offset: 39, other: 30 })));
+		assert!(matches!(regions.claim(5, 50, 0), Err(Error::OverlappingRegions { offset: 5, other: 0 })));
+		assert!(matches!(regions.claim_table(12, 1, 8, 0), Err(Error::OverlappingRegions { offset: 12, other: 10 })));
+		regions.claim(40, 60, 0).unwrap();
+	}
+}
@@ -0,0 +1,556 @@
+//! A virtual filesystem that treats archives like directories, so a file is found the same way wherever it's stored.
+//!
+//! A [`VfsPath`] is a path on disk, followed by the names to look up inside each archive on the way down.
+//! Archives can be nested to any depth, and their indexes are cached so opening another file doesn't re-read them.
+
+pub mod find;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::{CString, NulError};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+use crate::FileType;
+use crate::error::{Error, Result};
+use crate::pak::{PakEntryReader, PakIndex, PakIndexFileEntry, PakWriter};
+
+/// Where a file is: a path on disk, then the names of files inside archives, each one inside the last.
+///
+/// When written as text, the parts are separated with `::`, like `data.pak::levels.pak::level1.lvb`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct VfsPath {
+	pub disk: PathBuf,
+	pub archived: Vec<CString>,
+}
+
+impl VfsPath {
+	pub const SEPARATOR: &str = "::";
+	
+	pub fn disk(path: impl Into<PathBuf>) -> Self {
+		Self { disk: path.into(), archived: Vec::new() }
+	}
+	
+	/// The path of a file inside this archive.
+	pub fn join_archived(&self, name: CString) -> Self {
+		let mut archived = self.archived.clone();
+		archived.push(name);
+		Self { disk: self.disk.clone(), archived }
+	}
+	
+	/// The archive this file is stored in, or `None` if it's on disk.
+	pub fn parent_archive(&self) -> Option<Self> {
+		let (_, parent) = self.archived.split_last()?;
+		Some(Self { disk: self.disk.clone(), archived: parent.to_vec() })
+	}
+	
+	/// The last part of the path. For archived files, this is the whole name stored in the archive.
+	pub fn name(&self) -> Cow<'_, str> {
+		match self.archived.last() {
+			Some(name) => name.to_string_lossy(),
+			None => self.disk.file_name().unwrap_or(self.disk.as_os_str()).to_string_lossy(),
+		}
+	}
+	
+	/// Guesses the file's type from its extension.
+	pub fn file_type(&self) -> FileType {
+		match self.archived.last() {
+			Some(name) => FileType::from_path(name.to_string_lossy().as_ref()),
+			None => FileType::from_path(&self.disk),
+		}
+	}
+}
+
+impl fmt::Display for VfsPath {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.disk.display())?;
+		for name in &self.archived {
+			write!(f, "{}{}", Self::SEPARATOR, name.to_string_lossy())?;
+		}
+		Ok(())
+	}
+}
+
+impl FromStr for VfsPath {
+	type Err = NulError;
+	
+	fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+		let mut parts = s.split(Self::SEPARATOR);
+		let disk = PathBuf::from(parts.next().unwrap_or_default());
+		let archived = parts.map(CString::new).collect::<std::result::Result<_, _>>()?;
+		Ok(Self { disk, archived })
+	}
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VfsItemType {
+	Directory,
+	/// A file that can also be listed like a directory.
+	Archive,
+	File,
+	/// Symlinks and other special files, which aren't followed.
+	Other,
+}
+
+impl VfsItemType {
+	pub fn can_be_listed(self) -> bool {
+		matches!(self, Self::Directory | Self::Archive)
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct VfsItem {
+	pub path: VfsPath,
+	pub item_type: VfsItemType,
+	/// The size of files, if known.
+	pub size: Option<u64>,
+	/// For files stored in an archive, their entry in its index.
+	pub archive_entry: Option<PakIndexFileEntry>,
+}
+
+/// A reader for any file in a [`Vfs`].
+pub trait VfsReader: BufRead + Seek + Send {}
+
+impl<T: BufRead + Seek + Send> VfsReader for T {}
+
+/// Wraps a reader so it fails once `keep_going` returns `false`, which stops whatever is parsing from it soon after.
+///
+/// `keep_going` is asked every time the inner reader is used, so it should be cheap.
+pub struct Interruptible<R, F> {
+	inner: R,
+	keep_going: F,
+}
+
+impl<R, F: FnMut() -> bool> Interruptible<R, F> {
+	pub fn new(inner: R, keep_going: F) -> Self {
+		Self { inner, keep_going }
+	}
+	
+	fn check(&mut self) -> io::Result<()> {
+		// Not `ErrorKind::Interrupted`, since `read_exact` and `read_until` retry those
+		if (self.keep_going)() { Ok(()) } else { Err(io::Error::other("stopped before finishing")) }
+	}
+}
+
+impl<R: Read, F: FnMut() -> bool> Read for Interruptible<R, F> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.check()?;
+		self.inner.read(buf)
+	}
+}
+
+impl<R: BufRead, F: FnMut() -> bool> BufRead for Interruptible<R, F> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		self.check()?;
+		self.inner.fill_buf()
+	}
+	
+	fn consume(&mut self, amount: usize) {
+		self.inner.consume(amount);
+	}
+}
+
+impl<R: Seek, F: FnMut() -> bool> Seek for Interruptible<R, F> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		self.check()?;
+		self.inner.seek(pos)
+	}
+	
+	fn stream_position(&mut self) -> io::Result<u64> {
+		self.inner.stream_position()
+	}
+}
+
+/// Something that can list and open files by [`VfsPath`].
+pub trait Vfs {
+	/// The paths that browsing starts from, in the order they were mounted.
+	fn mounts(&self) -> Vec<VfsPath>;
+	
+	/// Lists the items directly inside a directory or archive.
+	fn read_dir(&self, path: &VfsPath) -> Result<Vec<VfsItem>> {
+		self.read_dir_while(path, &mut || true)
+	}
+	
+	/// Like [`Self::read_dir`], giving up with an error once `keep_going` returns `false`.
+	/// It's asked while the indexes of archives are read, which is what takes a while.
+	fn read_dir_while(&self, path: &VfsPath, keep_going: &mut dyn FnMut() -> bool) -> Result<Vec<VfsItem>>;
+	
+	/// The index of an archive, which may be cached.
+	fn archive_index(&self, path: &VfsPath) -> Result<Arc<PakIndex>> {
+		self.archive_index_while(path, &mut || true)
+	}
+	
+	/// Like [`Self::archive_index`], giving up with an error once `keep_going` returns `false`.
+	fn archive_index_while(&self, path: &VfsPath, keep_going: &mut dyn FnMut() -> bool) -> Result<Arc<PakIndex>>;
+	
+	/// Opens a file for reading. Positions are relative to the start of the file, even if it's stored in an archive.
+	fn open(&self, path: &VfsPath) -> Result<Box<dyn VfsReader>> {
+		self.open_while(path, &mut || true)
+	}
+	
+	/// Like [`Self::open`], giving up with an error once `keep_going` returns `false`
+	/// while the indexes of the archives around the file are read.
+	/// The reader doesn't ask `keep_going` itself, but can be wrapped in an [`Interruptible`] that does.
+	fn open_while(&self, path: &VfsPath, keep_going: &mut dyn FnMut() -> bool) -> Result<Box<dyn VfsReader>>;
+	
+	/// Reads a whole file into memory.
+	fn read(&self, path: &VfsPath) -> Result<Vec<u8>> {
+		let mut buf = Vec::new();
+		self.open(path)?.read_to_end(&mut buf)?;
+		Ok(buf)
+	}
+}
+
+/// Enough information to tell whether a file on disk has changed since it was last looked at.
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct DiskStamp {
+	modified: Option<SystemTime>,
+	len: u64,
+}
+
+impl DiskStamp {
+	fn of(path: &Path) -> io::Result<Self> {
+		let metadata = fs::metadata(path)?;
+		Ok(Self { modified: metadata.modified().ok(), len: metadata.len() })
+	}
+}
+
+struct CachedIndex {
+	/// The state of the outermost archive when the index was read, since a nested archive changes along with it.
+	stamp: DiskStamp,
+	index: Arc<PakIndex>,
+}
+
+/// The [`Vfs`] for real files and the archives among them.
+///
+/// Archive indexes are kept until the file on disk changes. It's safe to share between threads.
+#[derive(Default)]
+pub struct MountedVfs {
+	mounts: Mutex<Vec<VfsPath>>,
+	indexes: Mutex<HashMap<VfsPath, CachedIndex>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+	// Nothing is left half-updated while these locks are held, so a panic elsewhere doesn't matter.
+	mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn not_in_archive() -> Error {
+	Error::Io(io::Error::new(io::ErrorKind::NotFound, "no file with this name is in the archive"))
+}
+
+impl MountedVfs {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	
+	/// Adds a directory or archive to browse from.
+	pub fn mount(&self, root: VfsPath) -> Result<()> {
+		if root.archived.is_empty() {
+			fs::metadata(&root.disk)?;
+		} else {
+			self.archive_index(&root)?;
+		}
+		let mut mounts = lock(&self.mounts);
+		if !mounts.contains(&root) {
+			mounts.push(root);
+		}
+		Ok(())
+	}
+	
+	pub fn unmount_all(&self) {
+		lock(&self.mounts).clear();
+	}
+	
+	/// Forgets every cached index, even ones that are still up to date.
+	pub fn clear_cache(&self) {
+		lock(&self.indexes).clear();
+	}
+	
+	/// Replaces the contents of a file. A file inside an archive is saved by rebuilding each archive around it,
+	/// from the innermost out, keeping the other files in their order with their header fields.
+	/// If the new contents are the same size as the old ones, each archive keeps its original layout.
+	///
+	/// The file on disk is only replaced once everything has been written, so a failure leaves it as it was.
+	pub fn write(&self, path: &VfsPath, data: Vec<u8>) -> Result<()> {
+		let mut path = path.clone();
+		let mut data = data;
+		while let Some(archive) = path.parent_archive() {
+			let index = self.archive_index(&archive)?;
+			let mut pak = PakWriter::from_index(&index, &mut self.open(&archive)?)?;
+			let name = path.archived.last().expect("only archived files have a parent archive");
+			if !pak.replace_file(name, data) {
+				return Err(not_in_archive());
+			}
+			let mut writer = Cursor::new(Vec::new());
+			pak.write(&mut writer)?;
+			data = writer.into_inner();
+			path = archive;
+		}
+		
+		let mut temporary = path.disk.clone().into_os_string();
+		temporary.push(".excavator-new");
+		let temporary = PathBuf::from(temporary);
+		fs::write(&temporary, data)
+			.and_then(|()| fs::rename(&temporary, &path.disk))
+			.inspect_err(|_| { let _ = fs::remove_file(&temporary); })?;
+		// The file's timestamp might not have changed enough to notice
+		lock(&self.indexes).retain(|cached, _| cached.disk != path.disk);
+		Ok(())
+	}
+	
+	fn read_disk_dir(path: &Path) -> Result<Vec<VfsItem>> {
+		let mut items = Vec::new();
+		for entry in fs::read_dir(path)? {
+			let entry = entry?;
+			let file_type = entry.file_type()?;
+			let item_path = VfsPath::disk(entry.path());
+			let (item_type, size) = if file_type.is_dir() {
+				(VfsItemType::Directory, None)
+			} else if file_type.is_file() {
+				let item_type = if item_path.file_type() == FileType::Pak { VfsItemType::Archive } else { VfsItemType::File };
+				(item_type, Some(entry.metadata()?.len()))
+			} else {
+				(VfsItemType::Other, None)
+			};
+			items.push(VfsItem { path: item_path, item_type, size, archive_entry: None });
+		}
+		Ok(items)
+	}
+}
+
+impl Vfs for MountedVfs {
+	fn mounts(&self) -> Vec<VfsPath> {
+		lock(&self.mounts).clone()
+	}
+	
+	fn read_dir_while(&self, path: &VfsPath, keep_going: &mut dyn FnMut() -> bool) -> Result<Vec<VfsItem>> {
+		if path.archived.is_empty() && fs::metadata(&path.disk)?.is_dir() {
+			return Self::read_disk_dir(&path.disk);
+		}
+		
+		let index = self.archive_index_while(path, keep_going)?;
+		Ok(index.files.iter().map(|(name, entry)| {
+			let item_path = path.join_archived(name.clone());
+			let item_type = if item_path.file_type() == FileType::Pak { VfsItemType::Archive } else { VfsItemType::File };
+			VfsItem { path: item_path, item_type, size: Some(entry.data_length), archive_entry: Some(entry.clone()) }
+		}).collect())
+	}
+	
+	fn archive_index_while(&self, path: &VfsPath, keep_going: &mut dyn FnMut() -> bool) -> Result<Arc<PakIndex>> {
+		let stamp = DiskStamp::of(&path.disk)?;
+		if let Some(cached) = lock(&self.indexes).get(path) && cached.stamp == stamp {
+			return Ok(cached.index.clone());
+		}
+		
+		// The lock isn't held while reading, since opening a nested archive needs the index of the one around it.
+		let reader = self.open_while(path, keep_going)?;
+		let index = Arc::new(PakIndex::create_index(&mut Interruptible::new(reader, &mut *keep_going))?);
+		lock(&self.indexes).insert(path.clone(), CachedIndex { stamp, index: index.clone() });
+		Ok(index)
+	}
+	
+	fn open_while(&self, path: &VfsPath, keep_going: &mut dyn FnMut() -> bool) -> Result<Box<dyn VfsReader>> {
+		let Some(archive) = path.parent_archive() else {
+			if fs::metadata(&path.disk)?.is_dir() {
+				return Err(Error::Io(io::Error::new(io::ErrorKind::IsADirectory, format!("{path} is a directory"))));
+			}
+			return Ok(Box::new(BufReader::new(File::open(&path.disk)?)));
+		};
+		
+		let index = self.archive_index_while(&archive, keep_going)?;
+		let name = path.archived.last().expect("only archived files have a parent archive");
+		let (_, entry) = index.files.iter().find(|(other, _)| other == name).ok_or_else(not_in_archive)?;
+		Ok(Box::new(PakEntryReader::new(entry, self.open_while(&archive, keep_going)?)?))
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use crate::pak::PakWriter;
+	use std::io::{Cursor, SeekFrom};
+	
+	pub(crate) fn test_directory(name: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("excavator_vfs_{name}_{}", std::process::id()));
+		let _ = fs::remove_dir_all(&path);
+		fs::create_dir_all(&path).unwrap();
+		path
+	}
+	
+	pub(crate) fn pak_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+		let mut pak = PakWriter::new();
+		for (name, data) in files {
+			pak.add_file(CString::new(*name).unwrap(), data.to_vec());
+		}
+		let mut writer = Cursor::new(Vec::new());
+		pak.write(&mut writer).unwrap();
+		writer.into_inner()
+	}
+	
+	/// A directory with a loose file and an archive, which has another archive inside it.
+	pub(crate) fn sample_tree(name: &str) -> PathBuf {
+		let root = test_directory(name);
+		let inner = pak_bytes(&[("deep.txt", b"Deep down"), ("other.txt", b"")]);
+		let outer = pak_bytes(&[("top.txt", b"On top"), ("nested/inner.pak", &inner)]);
+		fs::write(root.join("loose.txt"), b"Loose").unwrap();
+		fs::create_dir(root.join("sub")).unwrap();
+		fs::write(root.join("sub/outer.pak"), outer).unwrap();
+		root
+	}
+	
+	#[test]
+	fn write_inside_nested_archives() {
+		let root = sample_tree("write");
+		let vfs = MountedVfs::new();
+		let outer = VfsPath::disk(root.join("sub/outer.pak"));
+		let inner = outer.join_archived(c"nested/inner.pak".into());
+		let before = vfs.read_dir(&inner).unwrap();
+		
+		vfs.write(&inner.join_archived(c"deep.txt".into()), b"Changed".to_vec()).unwrap();
+		assert_eq!(vfs.read(&inner.join_archived(c"deep.txt".into())).unwrap(), b"Changed");
+		assert_eq!(vfs.read(&outer.join_archived(c"top.txt".into())).unwrap(), b"On top");
+		let after = vfs.read_dir(&inner).unwrap();
+		assert_eq!(names(&before), names(&after));
+		assert!(!root.join("sub/outer.pak.excavator-new").exists());
+		
+		vfs.write(&VfsPath::disk(root.join("loose.txt")), b"Still loose".to_vec()).unwrap();
+		assert_eq!(fs::read(root.join("loose.txt")).unwrap(), b"Still loose");
+		assert!(vfs.write(&outer.join_archived(c"missing.txt".into()), Vec::new()).is_err());
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+	
+	#[test]
+	fn reading_an_index_can_be_stopped() {
+		let root = sample_tree("stop");
+		let vfs = MountedVfs::new();
+		let outer = VfsPath::disk(root.join("sub/outer.pak"));
+		let inner = outer.join_archived(c"nested/inner.pak".into());
+		
+		let mut asked = 0;
+		let stopped = vfs.read_dir_while(&inner, &mut || {
+			asked += 1;
+			asked <= 2
+		});
+		assert!(matches!(stopped, Err(Error::Io(_))));
+		assert_eq!(asked, 3);
+		assert!(vfs.open_while(&inner, &mut || false).is_err());
+		// Nothing half-read was cached
+		assert_eq!(names(&vfs.read_dir(&inner).unwrap()), [
+			("deep.txt".to_string(), VfsItemType::File),
+			("other.txt".to_string(), VfsItemType::File),
+		]);
+		
+		let mut reader = Interruptible::new(vfs.open(&inner).unwrap(), || false);
+		assert!(PakIndex::create_index(&mut reader).is_err());
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+	
+	fn names(items: &[VfsItem]) -> Vec<(String, VfsItemType)> {
+		let mut names: Vec<_> = items.iter().map(|item| (item.path.name().into_owned(), item.item_type)).collect();
+		names.sort_by(|a, b| a.0.cmp(&b.0));
+		names
+	}
+	
+	#[test]
+	fn path_text() {
+		let path: VfsPath = "sub/outer.pak::nested/inner.pak::deep.txt".parse().unwrap();
+		assert_eq!(path.disk, Path::new("sub/outer.pak"));
+		assert_eq!(path.archived, [c"nested/inner.pak", c"deep.txt"]);
+		assert_eq!(path.to_string(), "sub/outer.pak::nested/inner.pak::deep.txt");
+		assert_eq!(path.name(), "deep.txt");
+		assert_eq!(path.parent_archive().unwrap().file_type(), FileType::Pak);
+		assert_eq!(VfsPath::disk("a/b.stl").parent_archive(), None);
+		assert_eq!(VfsPath::disk("a/b.stl").name(), "b.stl");
+	}
+	
+	#[test]
+	fn list_nested() {
+		let root = sample_tree("list");
+		let vfs = MountedVfs::new();
+		vfs.mount(VfsPath::disk(&root)).unwrap();
+		assert_eq!(vfs.mounts(), [VfsPath::disk(&root)]);
+		
+		let items = vfs.read_dir(&VfsPath::disk(&root)).unwrap();
+		assert_eq!(names(&items), [("loose.txt".into(), VfsItemType::File), ("sub".into(), VfsItemType::Directory)]);
+		
+		let outer = VfsPath::disk(root.join("sub/outer.pak"));
+		let items = vfs.read_dir(&outer).unwrap();
+		assert_eq!(names(&items), [("nested/inner.pak".into(), VfsItemType::Archive), ("top.txt".into(), VfsItemType::File)]);
+		assert!(items.iter().all(|item| item.archive_entry.is_some()));
+		
+		let inner = outer.join_archived(c"nested/inner.pak".into());
+		let items = vfs.read_dir(&inner).unwrap();
+		assert_eq!(names(&items), [("deep.txt".into(), VfsItemType::File), ("other.txt".into(), VfsItemType::File)]);
+		assert_eq!(items[0].path.to_string(), format!("{}::nested/inner.pak::deep.txt", outer.disk.display()));
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+	
+	#[test]
+	fn open_nested() {
+		let root = sample_tree("open");
+		let vfs = MountedVfs::new();
+		let outer = VfsPath::disk(root.join("sub/outer.pak"));
+		let deep = outer.join_archived(c"nested/inner.pak".into()).join_archived(c"deep.txt".into());
+		
+		assert_eq!(vfs.read(&VfsPath::disk(root.join("loose.txt"))).unwrap(), b"Loose");
+		assert_eq!(vfs.read(&outer.join_archived(c"top.txt".into())).unwrap(), b"On top");
+		assert_eq!(vfs.read(&deep).unwrap(), b"Deep down");
+		
+		let mut reader = vfs.open(&deep).unwrap();
+		reader.seek(SeekFrom::Start(5)).unwrap();
+		let mut rest = String::new();
+		reader.read_to_string(&mut rest).unwrap();
+		assert_eq!(rest, "down");
+		
+		assert!(matches!(vfs.read(&outer.join_archived(c"missing".into())), Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound));
+		assert!(vfs.open(&VfsPath::disk(&root)).is_err());
+		assert!(vfs.read_dir(&VfsPath::disk(root.join("loose.txt"))).is_err());
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+	
+	#[test]
+	fn indexes_are_cached_until_the_file_changes() {
+		let root = sample_tree("cache");
+		let vfs = MountedVfs::new();
+		let outer = VfsPath::disk(root.join("sub/outer.pak"));
+		let inner = outer.join_archived(c"nested/inner.pak".into());
+		
+		let first = vfs.archive_index(&inner).unwrap();
+		assert!(Arc::ptr_eq(&first, &vfs.archive_index(&inner).unwrap()));
+		
+		fs::write(&outer.disk, pak_bytes(&[("nested/inner.pak", &pak_bytes(&[("new.txt", b"New")]))])).unwrap();
+		let changed = vfs.archive_index(&inner).unwrap();
+		assert!(!Arc::ptr_eq(&first, &changed));
+		assert_eq!(changed.files[0].0.as_c_str(), c"new.txt");
+		
+		vfs.clear_cache();
+		assert!(!Arc::ptr_eq(&changed, &vfs.archive_index(&inner).unwrap()));
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+	
+	#[test]
+	fn mount_archive() {
+		let root = sample_tree("mount");
+		let vfs = MountedVfs::new();
+		let inner = VfsPath::disk(root.join("sub/outer.pak")).join_archived(c"nested/inner.pak".into());
+		vfs.mount(inner.clone()).unwrap();
+		assert_eq!(vfs.mounts(), [inner]);
+		assert!(vfs.mount(VfsPath::disk(root.join("missing"))).is_err());
+		
+		vfs.unmount_all();
+		assert!(vfs.mounts().is_empty());
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+}
@@ -0,0 +1,205 @@
+//! Finding items by name anywhere under a path, including inside archives, without browsing to them one by one.
+
+use crate::error::Error;
+use crate::vfs::{Vfs, VfsItem, VfsPath};
+
+/// What names are being looked for. Matching ignores ASCII case.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NamePattern {
+	/// Matches names containing the text anywhere.
+	Substring(String),
+	/// Matches whole names, where `*` stands for any number of characters and `?` for exactly one.
+	Glob(Vec<char>),
+}
+
+impl NamePattern {
+	/// Queries containing `*` or `?` are globs. Anything else is looked for as a substring.
+	pub fn new(query: &str) -> Self {
+		let query = query.to_ascii_lowercase();
+		if query.contains(['*', '?']) {
+			Self::Glob(query.chars().collect())
+		} else {
+			Self::Substring(query)
+		}
+	}
+	
+	pub fn is_match(&self, name: &str) -> bool {
+		let name = name.to_ascii_lowercase();
+		match self {
+			Self::Substring(text) => name.contains(text.as_str()),
+			Self::Glob(pattern) => glob_match(pattern, &name.chars().collect::<Vec<_>>()),
+		}
+	}
+}
+
+/// Matches greedily, going back to the last `*` when the rest doesn't fit.
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+	let (mut p, mut n) = (0, 0);
+	// Where the last `*` was, and how much of the name it had taken when the rest was last tried
+	let mut star: Option<(usize, usize)> = None;
+	while n < name.len() {
+		match pattern.get(p) {
+			Some('*') => {
+				star = Some((p, n));
+				p += 1;
+			},
+			Some(&c) if c == '?' || c == name[n] => {
+				p += 1;
+				n += 1;
+			},
+			_ => match star {
+				Some((star_p, star_n)) => {
+					star = Some((star_p, star_n + 1));
+					p = star_p + 1;
+					n = star_n + 1;
+				},
+				None => return false,
+			},
+		}
+	}
+	pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// An item whose name matched, with the directories and archives that lead to it.
+#[derive(Clone, Debug)]
+pub struct Found {
+	pub item: VfsItem,
+	/// From just inside the root that was searched, down to the item's parent.
+	pub ancestors: Vec<VfsItem>,
+}
+
+#[derive(Debug, Default)]
+pub struct FindResults {
+	pub matches: Vec<Found>,
+	/// Directories and archives that couldn't be listed. Everything else was still searched.
+	pub errors: Vec<(VfsPath, Error)>,
+	/// Whether `keep_going` stopped the search early.
+	pub stopped: bool,
+}
+
+/// Looks through everything under `root` for names matching `pattern`, depth first, in order of path.
+/// Archives are searched too, including archives inside other archives.
+///
+/// `keep_going` is asked before each directory or archive is listed, and while an archive's index is read.
+/// Once it returns `false`, the search stops and returns what it's found so far.
+pub fn find<V: Vfs + ?Sized>(vfs: &V, root: &VfsPath, pattern: &NamePattern, mut keep_going: impl FnMut() -> bool) -> FindResults {
+	let mut results = FindResults::default();
+	walk(vfs, root, pattern, &mut keep_going, &mut Vec::new(), &mut results);
+	results
+}
+
+/// Returns `false` once the search has been stopped.
+fn walk<V: Vfs + ?Sized>(
+	vfs: &V,
+	path: &VfsPath,
+	pattern: &NamePattern,
+	keep_going: &mut impl FnMut() -> bool,
+	ancestors: &mut Vec<VfsItem>,
+	results: &mut FindResults,
+) -> bool {
+	if !keep_going() {
+		results.stopped = true;
+		return false;
+	}
+	let mut items = match vfs.read_dir_while(path, keep_going) {
+		Ok(items) => items,
+		// The listing was given up on because the search is stopping
+		Err(_) if !keep_going() => {
+			results.stopped = true;
+			return false;
+		},
+		Err(e) => {
+			results.errors.push((path.clone(), e));
+			return true;
+		},
+	};
+	items.sort_by(|a, b| a.path.cmp(&b.path));
+	
+	for item in items {
+		if pattern.is_match(&item.path.name()) {
+			results.matches.push(Found { item: item.clone(), ancestors: ancestors.clone() });
+		}
+		if item.item_type.can_be_listed() {
+			let item_path = item.path.clone();
+			ancestors.push(item);
+			let keep_going = walk(vfs, &item_path, pattern, keep_going, ancestors, results);
+			ancestors.pop();
+			if !keep_going { return false; }
+		}
+	}
+	true
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::vfs::MountedVfs;
+	use crate::vfs::tests::sample_tree;
+	use std::fs;
+	
+	#[test]
+	fn patterns() {
+		assert!(NamePattern::new("TXT").is_match("deep.txt"));
+		assert!(!NamePattern::new("txt").is_match("deep.stl"));
+		assert!(NamePattern::new("*.STL").is_match("text/menu.stl"));
+		assert!(!NamePattern::new("*.stl").is_match("menu.stl.bak"));
+		assert!(NamePattern::new("m?nu*").is_match("menu.stl"));
+		assert!(NamePattern::new("*a*b*c").is_match("xxaxxbxxbxxc"));
+		assert!(!NamePattern::new("*a*b*c").is_match("xxaxxbxxcxxb"));
+		assert!(NamePattern::new("*").is_match(""));
+		assert!(!NamePattern::new("?").is_match(""));
+	}
+	
+	#[test]
+	fn find_inside_archives() {
+		let root = sample_tree("find");
+		let vfs = MountedVfs::new();
+		let results = find(&vfs, &VfsPath::disk(&root), &NamePattern::new("*.txt"), || true);
+		assert!(results.errors.is_empty());
+		assert!(!results.stopped);
+		
+		let found: Vec<_> = results.matches.iter().map(|found| {
+			let ancestors: Vec<_> = found.ancestors.iter().map(|item| item.path.name().into_owned()).collect();
+			(found.item.path.name().into_owned(), ancestors)
+		}).collect();
+		assert_eq!(found, [
+			("loose.txt".to_string(), vec![]),
+			("deep.txt".to_string(), vec!["sub".to_string(), "outer.pak".to_string(), "nested/inner.pak".to_string()]),
+			("other.txt".to_string(), vec!["sub".to_string(), "outer.pak".to_string(), "nested/inner.pak".to_string()]),
+			("top.txt".to_string(), vec!["sub".to_string(), "outer.pak".to_string()]),
+		]);
+		
+		let archives = find(&vfs, &VfsPath::disk(&root), &NamePattern::new(".pak"), || true);
+		assert_eq!(archives.matches.len(), 2);
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+	
+	#[test]
+	fn find_can_stop_early() {
+		let root = sample_tree("find_stop");
+		let vfs = MountedVfs::new();
+		let mut listings = 0;
+		let results = find(&vfs, &VfsPath::disk(&root), &NamePattern::new("txt"), || {
+			listings += 1;
+			listings <= 1
+		});
+		assert!(results.stopped);
+		assert_eq!(results.matches.len(), 1);
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+	
+	#[test]
+	fn damaged_archives_are_skipped() {
+		let root = sample_tree("find_damaged");
+		fs::write(root.join("broken.pak"), b"not an archive").unwrap();
+		let vfs = MountedVfs::new();
+		let results = find(&vfs, &VfsPath::disk(&root), &NamePattern::new("*.txt"), || true);
+		assert_eq!(results.errors.len(), 1);
+		assert_eq!(results.errors[0].0, VfsPath::disk(root.join("broken.pak")));
+		assert_eq!(results.matches.len(), 4);
+		
+		fs::remove_dir_all(root).unwrap();
+	}
+}
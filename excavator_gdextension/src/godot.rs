@@ -1,8 +1,12 @@
 mod autoload;
+pub mod background_job;
 pub mod browser_tree;
 pub mod file_view;
+pub mod file_view_hex;
+pub mod file_view_level;
 pub mod file_view_st;
 mod format_resources;
+pub mod st_search;
 
 use godot::prelude::*;
 
@@ -1,12 +1,17 @@
 use godot::prelude::*;
 use godot::classes::Node;
 
+use crate::filesystem::vfs;
+use crate::formats::st::StEncoding;
+use crate::formats::vfs::VfsPath;
 use crate::godot::format_resources::SkePak;
 
 #[derive(GodotClass)]
 #[class(init, base=Node)]
 pub struct GlobalRust {
 	base: Base<Node>,
+	/// What string tables are decoded with. Picking another one in a table's view makes it the default for the next table.
+	st_encoding: StEncoding,
 }
 
 #[godot_api]
@@ -16,6 +21,11 @@ impl GlobalRust {
 	
 	#[func]
 	pub fn open_directory(&mut self, path: GString) {
+		vfs().unmount_all();
+		if let Err(e) = vfs().mount(VfsPath::disk(path.to_string())) {
+			godot_error!("Couldn't open {path}: {e}");
+			return;
+		}
 		self.signals().directory_opened().emit(&path);
 	}
 	
@@ -28,3 +38,13 @@ impl GlobalRust {
 		}
 	}
 }
+
+impl GlobalRust {
+	pub fn st_encoding(&self) -> StEncoding {
+		self.st_encoding
+	}
+	
+	pub fn set_st_encoding(&mut self, encoding: StEncoding) {
+		self.st_encoding = encoding;
+	}
+}
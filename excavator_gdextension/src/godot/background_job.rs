@@ -0,0 +1,167 @@
+use godot::prelude::*;
+use godot::classes::{INode, Node};
+
+use std::any::Any;
+use std::fmt::{self, Display};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::filesystem::vfs;
+use crate::formats;
+use crate::formats::vfs::{Interruptible, Vfs, VfsPath, VfsReader};
+
+/// Lets a background job's work notice that nobody wants its result anymore.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+	
+	/// For the `keep_going` arguments of the parsers and the [`Vfs`](crate::formats::vfs::Vfs), which stop once it returns `false`.
+	pub fn keep_going(&self) -> impl FnMut() -> bool + Send + '_ {
+		|| !self.is_cancelled()
+	}
+	
+	/// Opens a file whose reads fail once the job is cancelled, which stops whatever parser is reading it.
+	pub fn open(&self, path: &VfsPath) -> formats::Result<Interruptible<Box<dyn VfsReader>, impl FnMut() -> bool + Send + use<'_>>> {
+		let mut keep_going = self.keep_going();
+		let reader = vfs().open_while(path, &mut keep_going)?;
+		Ok(Interruptible::new(reader, keep_going))
+	}
+	
+	fn cancel(&self) {
+		self.0.store(true, Ordering::Relaxed);
+	}
+}
+
+/// The value a [`BackgroundJob`] produced, passed through Godot by its `finished` signal.
+#[derive(GodotClass)]
+#[class(no_init)]
+pub struct JobResult {
+	value: Option<Box<dyn Any + Send>>,
+}
+
+impl JobResult {
+	/// Takes the value out. Returns `None` if the work panicked, or `T` isn't what the work returned.
+	pub fn take<T: 'static>(&mut self) -> Option<T> {
+		self.value.take()?.downcast().ok().map(|value| *value)
+	}
+}
+
+/// The work of a [`BackgroundJob`] panicked instead of returning.
+#[derive(Copy, Clone, Debug)]
+pub struct JobPanicked;
+
+impl Display for JobPanicked {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "something went wrong")
+	}
+}
+
+/// Runs some work on its own thread, and emits `finished` on the main thread once it's done.
+///
+/// The node has to be in the scene tree, since it checks on the thread every frame. It frees itself after finishing.
+/// Cancelling it, or freeing it early, tells the work to stop and throws away its result.
+#[derive(GodotClass)]
+#[class(no_init, base=Node)]
+pub struct BackgroundJob {
+	base: Base<Node>,
+	receiver: Receiver<Box<dyn Any + Send>>,
+	cancel: CancelToken,
+}
+
+#[godot_api]
+impl INode for BackgroundJob {
+	fn process(&mut self, _delta: f64) {
+		let value = match self.receiver.try_recv() {
+			Ok(value) => Some(value),
+			Err(TryRecvError::Empty) => { return; },
+			// The sender was dropped without sending, so the work panicked
+			Err(TryRecvError::Disconnected) => None,
+		};
+		self.base_mut().set_process(false);
+		self.base_mut().queue_free();
+		if self.cancel.is_cancelled() { return; }
+		
+		let result = Gd::from_object(JobResult { value });
+		self.signals().finished().emit(&result);
+	}
+}
+
+#[godot_api]
+impl BackgroundJob {
+	#[signal]
+	pub fn finished(result: Gd<JobResult>);
+}
+
+impl BackgroundJob {
+	/// Starts `work` on a new thread. Its return value is what [`JobResult::take`] gives back.
+	pub fn start<T, F>(work: F) -> Gd<Self>
+	where
+		T: Send + 'static,
+		F: FnOnce(&CancelToken) -> T + Send + 'static,
+	{
+		let (sender, receiver) = mpsc::channel::<Box<dyn Any + Send>>();
+		let cancel = CancelToken::default();
+		let thread_cancel = cancel.clone();
+		// If the thread can't start, the sender is dropped, which looks the same as the work panicking
+		let _ = thread::Builder::new().name("background job".to_string()).spawn(move || {
+			let value = work(&thread_cancel);
+			if !thread_cancel.is_cancelled() {
+				let _ = sender.send(Box::new(value));
+			}
+		});
+		
+		Gd::from_init_fn(|base| Self { base, receiver, cancel })
+	}
+	
+	/// Starts `work` like [`Self::start`], and calls `done` on the main thread with what it returned.
+	///
+	/// Like with `finished`, the job has to be added to the scene tree, and `done` isn't called if it's cancelled.
+	pub fn run<T, F, D>(work: F, done: D) -> Gd<Self>
+	where
+		T: Send + 'static,
+		F: FnOnce(&CancelToken) -> T + Send + 'static,
+		D: FnOnce(Result<T, JobPanicked>) + 'static,
+	{
+		let job = Self::start(work);
+		let mut done = Some(done);
+		job.signals().finished().connect(move |mut result| {
+			let value = result.bind_mut().take::<T>().ok_or(JobPanicked);
+			if let Some(done) = done.take() {
+				done(value);
+			}
+		});
+		job
+	}
+	
+	/// Like [`Self::run`], for work that can fail. A panic is passed to `done` as one more error.
+	pub fn run_fallible<T, E, F, D>(work: F, done: D) -> Gd<Self>
+	where
+		T: Send + 'static,
+		E: Display + Send + 'static,
+		F: FnOnce(&CancelToken) -> Result<T, E> + Send + 'static,
+		D: FnOnce(Result<T, String>) + 'static,
+	{
+		Self::run(work, |result| done(match result {
+			Ok(result) => result.map_err(|e| e.to_string()),
+			Err(e) => Err(e.to_string()),
+		}))
+	}
+	
+	/// Tells the work to stop, and makes sure `finished` is never emitted.
+	pub fn cancel(&mut self) {
+		self.cancel.cancel();
+		self.base_mut().queue_free();
+	}
+}
+
+impl Drop for BackgroundJob {
+	fn drop(&mut self) {
+		self.cancel.cancel();
+	}
+}
@@ -1,66 +1,74 @@
 use godot::prelude::*;
-use godot::classes::{Tree, ITree, TreeItem};
+use godot::classes::{AcceptDialog, DisplayServer, FileDialog, PopupMenu, Tree, ITree, TreeItem};
+use godot::classes::file_dialog::{Access, FileMode};
+use godot::global::MouseButton;
 use godot::tools::get_autoload_by_name;
 
 use std::borrow::Cow;
-use std::ffi::CString;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
 
-use crate::filesystem::{FsItem, FsItemType, load_directory, open_file};
-use crate::formats::{FileType, pak::PakIndex};
+use crate::filesystem::vfs;
+use crate::formats::pak::{extract_all, ExtractReport};
+use crate::formats::vfs::{Vfs, VfsItem, VfsItemType, VfsPath};
+use crate::formats::vfs::find::{find, FindResults, NamePattern};
 use crate::godot::autoload::GlobalRust;
-
-#[derive(Clone, Debug)]
-pub enum ItemSource {
-	Fs { path: PathBuf, fs_type: FsItemType },
-	Pak { outer_path: PathBuf, inner_path: CString },
-}
-
-impl From<FsItem> for ItemSource {
-	fn from(value: FsItem) -> Self {
-		Self::Fs { path: value.path, fs_type: value.item_type }
-	}
-}
+use crate::godot::background_job::{BackgroundJob, JobPanicked};
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 enum ItemState {
 	Unloaded,
+	Loading,
 	Loaded,
 }
 
 #[derive(GodotClass, Clone)]
 #[class(no_init)]
-// Right now this struct is doing double duty in an inelegant way. I'm using it as a wrapper that can pass through Godot to places that only need the `source` field.
+// Right now this struct is doing double duty in an inelegant way. I'm using it as a wrapper that can pass through Godot to places that only need the `item` field.
 // The `state` field *only* refers to whether the item's children have been added to the browser tree.
 pub struct ItemInfo {
-	pub source: ItemSource,
+	pub item: VfsItem,
 	state: ItemState,
 }
 
-impl ItemSource {
-	fn text(&self) -> Cow<'_, str> {
-		match self {
-			ItemSource::Fs { path, .. } => path.file_name().unwrap_or_default().to_string_lossy(),
-			ItemSource::Pak { inner_path, .. } => inner_path.to_string_lossy(),
-		}
-	}
-	
-	fn can_be_expanded(&self) -> bool {
-		match self {
-			ItemSource::Fs { path, fs_type } => match fs_type {
-				FsItemType::Dir => true,
-				FsItemType::File => FileType::from_path(path) == FileType::Pak,
-				_ => false,
-			},
-			ItemSource::Pak { .. } => false,
-		}
+impl ItemInfo {
+	/// Wraps an item found some other way than browsing, to pass to the things that open files.
+	pub fn from_item(item: VfsItem) -> Gd<Self> {
+		Gd::from_object(Self { item, state: ItemState::Unloaded })
 	}
 }
 
+fn item_text(item: &VfsItem) -> Cow<'_, str> {
+	item.path.name()
+}
+
+/// What a mounted path is shown as, at the top of the tree.
+fn mount_item(path: VfsPath) -> VfsItem {
+	let item_type = if path.archived.is_empty() && path.disk.is_dir() { VfsItemType::Directory } else { VfsItemType::Archive };
+	VfsItem { path, item_type, size: None, archive_entry: None }
+}
+
+/// More matches than this make the tree slow, and aren't useful to scroll through anyway.
+const MAX_SHOWN_MATCHES: usize = 2000;
+
+fn item_tooltip(item: &VfsItem) -> Option<String> {
+	item.archive_entry.as_ref().map(|entry| format!(
+		"Header offset: {:#X}\nName pointer: {:#X}\nData: {:#X} ({} bytes)\nidk1: {:#018X}\nidk2: {:#018X}\nidk3: {:#018X}",
+		entry.header_offset, entry.name_pointer, entry.data_start, entry.data_length, entry.idk1, entry.idk2, entry.idk3,
+	))
+}
+
 #[derive(GodotClass)]
 #[class(init, base=Tree)]
 pub struct BrowserTree {
 	base: Base<Tree>,
+	/// Jobs listing the children of expanded items, or searching. They're cancelled when the whole tree is replaced.
+	loading: Vec<Gd<BackgroundJob>>,
+	/// The job extracting an archive, until it finishes. Only one runs at a time.
+	extracting: Option<Gd<BackgroundJob>>,
+	/// Whether only the results of [`Self::filter`] are shown.
+	filtered: bool,
 }
 
 #[godot_api]
@@ -71,6 +79,8 @@ impl ITree for BrowserTree {
 		
 		self.signals().item_collapsed().connect_self(Self::on_item_collapsed);
 		self.signals().item_selected().connect_self(Self::on_item_activated);
+		self.signals().item_mouse_selected().connect_self(Self::on_item_mouse_selected);
+		self.base_mut().set_allow_rmb_select(true);
 	}
 }
 
@@ -79,37 +89,180 @@ impl BrowserTree {
 	#[signal]
 	fn file_open_requested(item_info: Gd<ItemInfo>);
 	
-	fn setup_item(&mut self, item: &mut Gd<TreeItem>, source: ItemSource) {
-		let info = ItemInfo { source, state: ItemState::Unloaded };
+	#[signal]
+	fn hex_view_requested(item_info: Gd<ItemInfo>);
+	
+	fn setup_item(&mut self, item: &mut Gd<TreeItem>, vfs_item: VfsItem) {
+		let info = ItemInfo { item: vfs_item, state: ItemState::Unloaded };
 		let mut set_collapsed: Option<bool> = None;
 		
-		item.set_text(0, info.source.text().as_ref());
-		if info.source.can_be_expanded() {
+		item.set_text(0, item_text(&info.item).as_ref());
+		if let Some(tooltip) = item_tooltip(&info.item) {
+			item.set_tooltip_text(0, &tooltip);
+		}
+		if info.item.item_type.can_be_listed() {
 			item.create_child();
 			set_collapsed = Some(true);
 		}
 		item.set_metadata(0, &Gd::from_object(info).to_variant());
 		
-		// The item_collapsed signal going off in this function causes invalid aliasing
-		// We avoid this by disabling signals briefly when it otherwise would be sent
 		if let Some(value) = set_collapsed {
-			let previous = self.base().is_blocking_signals();
-			self.base_mut().set_block_signals(true);
-			
-			item.set_collapsed(value);
-			
-			self.base_mut().set_block_signals(previous);
+			self.set_collapsed_quietly(item, value);
+		}
+	}
+	
+	/// The item_collapsed signal going off while this object is borrowed causes invalid aliasing.
+	/// We avoid this by disabling signals briefly when it otherwise would be sent.
+	fn set_collapsed_quietly(&mut self, item: &mut Gd<TreeItem>, value: bool) {
+		let previous = self.base().is_blocking_signals();
+		self.base_mut().set_block_signals(true);
+		
+		item.set_collapsed(value);
+		
+		self.base_mut().set_block_signals(previous);
+	}
+	
+	fn cancel_loading(&mut self) {
+		for mut job in self.loading.drain(..) {
+			if job.is_instance_valid() {
+				job.bind_mut().cancel();
+			}
+		}
+	}
+	
+	/// Shows everything that's mounted in the VFS, which `path` has just been added to.
+	#[func]
+	fn show_directory(&mut self, _path: GString) {
+		self.cancel_loading();
+		self.filtered = false;
+		self.base_mut().clear();
+		self.base_mut().set_hide_root(true);
+		let mut root = self.base_mut().create_item().unwrap();
+		
+		for mount in vfs().mounts() {
+			let mut child = root.create_child().unwrap();
+			self.setup_item(&mut child, mount_item(mount));
+			child.call_deferred("set_collapsed", vslice![false]);
 		}
 	}
 	
+	/// Shows only the items whose names match `query`, from anywhere in the mounted directories and every archive in them.
+	/// Queries with `*` or `?` are globs, and anything else matches part of a name. An empty query shows everything again.
 	#[func]
-	fn show_directory(&mut self, path: GString) {
-		let path = PathBuf::from(path.to_string());
+	fn filter(&mut self, query: GString) {
+		let query = query.to_string().trim().to_string();
+		if query.is_empty() {
+			self.show_directory(GString::new());
+			return;
+		}
+		
+		self.cancel_loading();
+		self.filtered = true;
+		self.base_mut().clear();
+		self.base_mut().set_hide_root(true);
+		let mut root = self.base_mut().create_item().unwrap();
+		let mut status = root.create_child().unwrap();
+		status.set_text(0, &format!("(Searching for {query}...)"));
 		
+		let pattern = NamePattern::new(&query);
+		let mut this = self.to_gd();
+		let job = BackgroundJob::run(
+			move |cancel| {
+				vfs().mounts().into_iter().map(|mount| {
+					let results = find(vfs(), &mount, &pattern, cancel.keep_going());
+					(mount, results)
+				}).collect::<Vec<_>>()
+			},
+			move |searches| this.bind_mut().show_matches(searches),
+		);
+		self.loading.push(job.clone());
+		self.base_mut().add_child(&job);
+	}
+	
+	/// Goes back to showing everything when the filter is cleared.
+	#[func]
+	fn filter_changed(&mut self, text: GString) {
+		if text.is_empty() && self.filtered {
+			self.show_directory(GString::new());
+		}
+	}
+	
+	/// Shows each match inside its directories and archives, which are expanded but only contain matches.
+	fn show_matches(&mut self, searches: Result<Vec<(VfsPath, FindResults)>, JobPanicked>) {
 		self.base_mut().clear();
 		let mut root = self.base_mut().create_item().unwrap();
-		self.setup_item(&mut root, ItemSource::Fs { path, fs_type: FsItemType::Dir });
-		root.call_deferred("set_collapsed", vslice![false]);
+		let searches = match searches {
+			Ok(searches) => searches,
+			Err(e) => {
+				root.create_child().unwrap().set_text(0, &format!("(Error: {e} while searching)"));
+				return;
+			},
+		};
+		
+		let total: usize = searches.iter().map(|(_, results)| results.matches.len()).sum();
+		let mut remaining = MAX_SHOWN_MATCHES;
+		let mut errors = Vec::new();
+		for (mount, results) in searches {
+			if results.matches.is_empty() || remaining == 0 {
+				errors.extend(results.errors);
+				continue;
+			}
+			let mut mount_tree_item = root.create_child().unwrap();
+			self.setup_item(&mut mount_tree_item, mount_item(mount));
+			self.make_branch(&mut mount_tree_item);
+			
+			// Matches are in order of path, so a matching archive comes before any matches inside it
+			let mut branches = HashMap::<VfsPath, Gd<TreeItem>>::new();
+			for found in results.matches.into_iter().take(remaining) {
+				let mut parent = mount_tree_item.clone();
+				for ancestor in found.ancestors {
+					parent = match branches.get(&ancestor.path) {
+						Some(existing) => existing.clone(),
+						None => {
+							let path = ancestor.path.clone();
+							let mut branch = parent.create_child().unwrap();
+							self.setup_item(&mut branch, ancestor);
+							branches.insert(path, branch.clone());
+							branch
+						},
+					};
+					self.make_branch(&mut parent);
+				}
+				
+				let path = found.item.path.clone();
+				let listable = found.item.item_type.can_be_listed();
+				let mut child = parent.create_child().unwrap();
+				self.setup_item(&mut child, found.item);
+				if listable {
+					branches.insert(path, child);
+				}
+				remaining -= 1;
+			}
+			errors.extend(results.errors);
+		}
+		
+		if total == 0 {
+			root.create_child().unwrap().set_text(0, "(No matches)");
+		} else if total > MAX_SHOWN_MATCHES {
+			root.create_child().unwrap().set_text(0, &format!("(Showing the first {MAX_SHOWN_MATCHES} of {total} matches)"));
+		}
+		if !errors.is_empty() {
+			let mut item = root.create_child().unwrap();
+			item.set_text(0, &format!("({} places couldn't be searched)", errors.len()));
+			let tooltip: Vec<String> = errors.iter().map(|(path, e)| format!("{path}: {e}")).collect();
+			item.set_tooltip_text(0, &tooltip.join("\n"));
+		}
+	}
+	
+	/// Turns an item that would list its children when expanded into one that only holds what's added to it, expanded.
+	fn make_branch(&mut self, item: &mut Gd<TreeItem>) {
+		let Ok(mut info_gd) = item.get_metadata(0).try_to::<Gd<ItemInfo>>() else { return; };
+		if info_gd.bind().state != ItemState::Unloaded { return; }
+		info_gd.bind_mut().state = ItemState::Loaded;
+		for placeholder in item.get_children().iter_shared() {
+			placeholder.free();
+		}
+		self.set_collapsed_quietly(item, false);
 	}
 	
 	#[func]
@@ -121,51 +274,169 @@ impl BrowserTree {
 		let mut info = info_gd.bind_mut();
 		
 		if info.state != ItemState::Unloaded { return; }
+		info.state = ItemState::Loading;
 		
-		let mut children_sources: Vec<ItemSource> = match &info.source {
-			ItemSource::Fs { path, fs_type } => {
-				match fs_type {
-					FsItemType::Dir => {
-						let fs_items = load_directory(path).unwrap();
-						fs_items.into_iter().map(|fs_item| ItemSource::from(fs_item)).collect()
-					},
-					FsItemType::File if FileType::from_path(path) == FileType::Pak => {
-						let mut reader = open_file(path).unwrap();
-						let index = PakIndex::create_index(&mut reader).unwrap();
-						index.files.into_iter().map(|file| {
-							ItemSource::Pak { outer_path: path.clone(), inner_path: file.0 }
-						}).collect()
-					},
-					_ => unreachable!(),
-				}
-			},
-			_ => unreachable!(),
-		};
+		// Earlier we put a placeholder child so we could expand this item. Now it says what's happening.
+		if let Some(mut placeholder) = item.get_first_child() {
+			placeholder.set_text(0, "(Loading...)");
+		}
 		
-		children_sources.sort_by_key(|source| source.text().into_owned());
+		// Listing an archive means reading its whole index, which can take a while for big ones
+		let path = info.item.path.clone();
+		let mut this = self.to_gd();
+		let parent = item.instance_id();
+		let job = BackgroundJob::run_fallible(
+			move |cancel| vfs().read_dir_while(&path, &mut cancel.keep_going()),
+			move |children| this.bind_mut().show_children(parent, children),
+		);
+		self.loading.retain(|job| job.is_instance_valid());
+		self.loading.push(job.clone());
+		self.base_mut().add_child(&job);
+	}
+	
+	fn show_children(&mut self, item: InstanceId, children: Result<Vec<VfsItem>, String>) {
+		// The tree might have been cleared while the children were loading
+		let Ok(mut item) = Gd::<TreeItem>::try_from_instance_id(item) else { return; };
+		let Ok(mut info_gd) = item.get_metadata(0).try_to::<Gd<ItemInfo>>() else { return; };
+		let mut info = info_gd.bind_mut();
 		
-		// Earlier we put a placeholder child so we could expand this item. We don't need it anymore.
 		for old_child in item.get_children().iter_shared() {
 			old_child.free();
 		}
 		
-		if children_sources.len() == 0 {
+		let mut children = match children {
+			Ok(children) => children,
+			Err(e) => {
+				let mut child = item.create_child().unwrap();
+				child.set_text(0, &format!("(Error: {e})"));
+				info.state = ItemState::Loaded;
+				return;
+			},
+		};
+		children.sort_by_key(|child| item_text(child).into_owned());
+		
+		if children.is_empty() {
 			let mut child = item.create_child().unwrap();
 			child.set_text(0, "(Empty)");
 		}
 		
-		for source in children_sources {
+		for vfs_item in children {
 			let mut child = item.create_child().unwrap();
-			self.setup_item(&mut child, source);
+			self.setup_item(&mut child, vfs_item);
 		}
 		
 		info.state = ItemState::Loaded;
 	}
 	
 	fn on_item_activated(&mut self) {
-		let item = self.base().get_selected().unwrap();
+		let Some(item) = self.base().get_selected() else { return; };
 		let Ok(info_gd) = item.get_metadata(0).try_to::<Gd<ItemInfo>>() else { return; };
 		
 		self.signals().file_open_requested().emit(&info_gd);
 	}
+	
+	/// Right-clicking an archive offers to extract it. Archives and files can also be viewed as hex.
+	fn on_item_mouse_selected(&mut self, _position: Vector2, button: i64) {
+		const EXTRACT: i32 = 0;
+		const VIEW_HEX: i32 = 1;
+		
+		if button != MouseButton::RIGHT.ord() as i64 { return; }
+		let Some(item) = self.base().get_selected() else { return; };
+		let Ok(info_gd) = item.get_metadata(0).try_to::<Gd<ItemInfo>>() else { return; };
+		let item_type = info_gd.bind().item.item_type;
+		
+		let mut menu = PopupMenu::new_alloc();
+		match item_type {
+			VfsItemType::Archive => {
+				menu.add_item_ex("Extract...").id(EXTRACT).done();
+				if self.extracting.is_some() {
+					let index = menu.get_item_index(EXTRACT);
+					menu.set_item_disabled(index, true);
+				}
+				menu.add_item_ex("View as hex").id(VIEW_HEX).done();
+			},
+			VfsItemType::File => menu.add_item_ex("View as hex").id(VIEW_HEX).done(),
+			VfsItemType::Directory | VfsItemType::Other => {
+				menu.free();
+				return;
+			},
+		}
+		let mut this = self.to_gd();
+		menu.signals().id_pressed().connect(move |id| match id as i32 {
+			EXTRACT => this.bind_mut().choose_extract_destination(info_gd.bind().item.path.clone()),
+			VIEW_HEX => this.signals().hex_view_requested().emit(&info_gd),
+			_ => {},
+		});
+		let mut menu_to_free = menu.clone();
+		menu.signals().popup_hide().connect(move || menu_to_free.queue_free());
+		
+		self.base_mut().add_child(&menu);
+		menu.set_position(DisplayServer::singleton().mouse_get_position());
+		menu.popup();
+	}
+	
+	fn choose_extract_destination(&mut self, archive: VfsPath) {
+		let mut dialog = FileDialog::new_alloc();
+		dialog.set_file_mode(FileMode::OPEN_DIR);
+		dialog.set_access(Access::FILESYSTEM);
+		dialog.set_use_native_dialog(true);
+		dialog.set_title(&format!("Extract {} to...", archive.name()));
+		
+		let mut this = self.to_gd();
+		let mut dialog_to_free = dialog.clone();
+		dialog.signals().dir_selected().connect(move |dir| {
+			dialog_to_free.queue_free();
+			this.bind_mut().extract_archive(archive.clone(), PathBuf::from(dir.to_string()));
+		});
+		let mut dialog_to_free = dialog.clone();
+		dialog.signals().canceled().connect(move || dialog_to_free.queue_free());
+		
+		self.base_mut().add_child(&dialog);
+		dialog.popup_centered();
+	}
+	
+	/// Extracts on another thread, since big archives take a while, and says how it went once it's done.
+	fn extract_archive(&mut self, archive: VfsPath, destination: PathBuf) {
+		if self.extracting.is_some() { return; }
+		
+		let work_archive = archive.clone();
+		let work_destination = destination.clone();
+		let mut this = self.to_gd();
+		let job = BackgroundJob::run_fallible(
+			move |_cancel| {
+				let index = vfs().archive_index(&work_archive)?;
+				extract_all(&index, &mut vfs().open(&work_archive)?, &work_destination)
+			},
+			move |result| this.bind_mut().on_extracted(&archive, &destination, result),
+		);
+		self.base_mut().add_child(&job);
+		self.extracting = Some(job);
+	}
+	
+	fn on_extracted(&mut self, archive: &VfsPath, destination: &Path, result: Result<ExtractReport, String>) {
+		self.extracting = None;
+		let message = match result {
+			Ok(report) => {
+				let mut message = format!("Extracted {} files to {}.", report.extracted.len(), destination.display());
+				if !report.collisions.is_empty() {
+					let _ = write!(message, "\n\n{} files were skipped, because their paths were already taken or the files already existed:", report.collisions.len());
+					for collision in &report.collisions {
+						let _ = write!(message, "\n{}", collision.name.to_string_lossy());
+					}
+				}
+				message
+			},
+			Err(e) => format!("Couldn't extract {archive}:\n{e}"),
+		};
+		
+		let mut dialog = AcceptDialog::new_alloc();
+		dialog.set_title("Extract");
+		dialog.set_text(&message);
+		let mut dialog_to_free = dialog.clone();
+		dialog.signals().confirmed().connect(move || dialog_to_free.queue_free());
+		let mut dialog_to_free = dialog.clone();
+		dialog.signals().canceled().connect(move || dialog_to_free.queue_free());
+		self.base_mut().add_child(&dialog);
+		dialog.popup_centered();
+	}
 }
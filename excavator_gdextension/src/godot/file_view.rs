@@ -1,18 +1,72 @@
 use godot::prelude::*;
-use godot::classes::{Image, image::Format, ImageTexture, Node, INode, TextureRect};
+use godot::classes::{ConfirmationDialog, Image, ImageTexture, Label, Node, INode, TextureRect};
+use godot::classes::notify::NodeNotification;
+use godot::global::Error as GodotError;
+use godot::tools::get_autoload_by_name;
 
-use std::ffi::OsStr;
-use std::path::PathBuf;
+use std::cell::Cell;
+use std::io::Read;
+use std::rc::Rc;
 
-use crate::filesystem::{cruddy_complex_load, FsItemType};
-use crate::godot::browser_tree::{ItemInfo, ItemSource};
+use crate::formats::{self, FileType};
+use crate::formats::st::{StEncoding, StTable};
+use crate::formats::vfs::{VfsItemType, VfsPath};
+use crate::godot::autoload::GlobalRust;
+use crate::godot::background_job::{BackgroundJob, CancelToken};
+use crate::godot::browser_tree::ItemInfo;
+use crate::godot::file_view_hex::{FileViewHex, HexStuff};
+use crate::godot::file_view_level::{FileViewLevel, LevelStuff};
 use crate::godot::file_view_st::FileViewSt;
 
+/// Archived files are often renamed or missing their extension, so the contents get a say too.
+fn detect_type(path: &VfsPath, cancel: &CancelToken) -> formats::Result<FileType> {
+	let mut reader = cancel.open(path)?;
+	Ok(FileType::detect_reader(path.name().as_ref(), &mut reader)?.file_type)
+}
+
+fn st_encoding() -> StEncoding {
+	get_autoload_by_name::<GlobalRust>("R").bind().st_encoding()
+}
+
+/// A file that a background job has read and parsed, ready for its view to be built on the main thread.
+enum ViewStuff {
+	/// Archives are browsed in the tree instead.
+	Archive,
+	Png(Vec<u8>),
+	Level(LevelStuff),
+	St(VfsPath, StTable),
+	Hex(HexStuff),
+}
+
+impl ViewStuff {
+	/// Does all of the slow work for a file, stopping partway if `cancel` is cancelled.
+	/// String tables are decoded with `encoding`.
+	fn read(path: &VfsPath, encoding: StEncoding, cancel: &CancelToken) -> formats::Result<Self> {
+		let file_type = detect_type(path, cancel)?;
+		Ok(match file_type {
+			FileType::Pak => Self::Archive,
+			FileType::Png => {
+				let mut data = Vec::new();
+				cancel.open(path)?.read_to_end(&mut data)?;
+				Self::Png(data)
+			},
+			FileType::Ltb | FileType::Lvb => Self::Level(FileViewLevel::read_level_stuff(path, file_type, cancel)?),
+			FileType::Stl => Self::St(path.clone(), FileViewSt::read_st_stuff(path, true, encoding, cancel)?),
+			FileType::StmOrStb => Self::St(path.clone(), FileViewSt::read_st_stuff(path, false, encoding, cancel)?),
+			FileType::Unknown => Self::Hex(FileViewHex::read_hex_stuff(path, file_type, cancel)?),
+		})
+	}
+}
+
 #[derive(GodotClass)]
 #[class(base=Node)]
 struct FileViewController {
 	#[export]
 	current_view: Option<Gd<Node>>,
+	/// The job reading the file that was opened last, until it finishes.
+	loading: Option<Gd<BackgroundJob>>,
+	/// The cell to select once the string table that's loading is shown.
+	pending_cell: Option<(usize, usize)>,
 	scene_none: OnReady<Gd<PackedScene>>,
 	scene_unknown: OnReady<Gd<PackedScene>>,
 	scene_image: OnReady<Gd<PackedScene>>,
@@ -24,43 +78,198 @@ impl INode for FileViewController {
 	fn init(base: Base<Node>) -> Self {
 		Self {
 			current_view: Default::default(),
+			loading: None,
+			pending_cell: None,
 			scene_none: OnReady::from_loaded("uid://b0vgfjxmh04oy"),
 			scene_unknown: OnReady::from_loaded("uid://bc7du68pcbhuw"),
 			scene_image: OnReady::from_loaded("uid://b2ib32jigv6a0"),
 			base,
 		}
 	}
+	
+	fn ready(&mut self) {
+		// Closing the window asks about unsaved changes first, see `on_notification`
+		if let Some(mut tree) = self.base().get_tree() {
+			tree.set_auto_accept_quit(false);
+		}
+	}
+	
+	fn on_notification(&mut self, what: NodeNotification) {
+		if what == NodeNotification::WM_CLOSE_REQUEST {
+			self.leave_current_view(|this| {
+				if let Some(mut tree) = this.base().get_tree() {
+					tree.quit();
+				}
+			});
+		}
+	}
 }
 
 #[godot_api]
 impl FileViewController {
 	#[func]
 	fn open_file(&mut self, item_info: Gd<ItemInfo>) {
-		let innermost_path = match &item_info.bind().source {
-			ItemSource::Fs { path, fs_type } if *fs_type == FsItemType::File => path.clone(),
-			ItemSource::Pak { inner_path, .. } => PathBuf::from(inner_path.to_string_lossy().as_ref()),
-			_ => { return; },
+		let path = item_info.bind().item.path.clone();
+		if item_info.bind().item.item_type != VfsItemType::File { return; }
+		let encoding = st_encoding();
+		self.load_in_background(path, None, move |path, cancel| ViewStuff::read(path, encoding, cancel));
+	}
+	
+	/// Shows any file or archive as hex, with the structures its parser finds marked on top.
+	#[func]
+	fn open_hex(&mut self, item_info: Gd<ItemInfo>) {
+		let item = item_info.bind().item.clone();
+		match item.item_type {
+			VfsItemType::Archive => self.load_in_background(item.path, None, |path, cancel| {
+				Ok(ViewStuff::Hex(FileViewHex::read_hex_stuff(path, FileType::Pak, cancel)?))
+			}),
+			VfsItemType::File => self.load_in_background(item.path, None, |path, cancel| {
+				let file_type = detect_type(path, cancel)?;
+				Ok(ViewStuff::Hex(FileViewHex::read_hex_stuff(path, file_type, cancel)?))
+			}),
+			VfsItemType::Directory | VfsItemType::Other => {},
+		}
+	}
+	
+	/// Opens a string table with one of its cells selected, like a search result. `row` counts the row of field names as 0.
+	#[func]
+	fn open_cell(&mut self, item_info: Gd<ItemInfo>, row: i64, field: i64) {
+		let path = item_info.bind().item.path.clone();
+		let stl = match path.file_type() {
+			FileType::Stl => true,
+			FileType::StmOrStb => false,
+			_ => return,
+		};
+		let encoding = st_encoding();
+		let cell = (row.max(0) as usize, field.max(0) as usize);
+		self.load_in_background(path, Some(cell), move |path, cancel| {
+			Ok(ViewStuff::St(path.clone(), FileViewSt::read_st_stuff(path, stl, encoding, cancel)?))
+		});
+	}
+}
+
+impl FileViewController {
+	/// Runs `then` once whatever's shown can be replaced. A string table with unsaved changes asks whether to save them first,
+	/// and `then` only runs once they're saved or discarded.
+	fn leave_current_view(&mut self, then: impl FnOnce(&mut Self) + 'static) {
+		let table = self.current_view.clone().and_then(|view| view.try_cast::<FileViewSt>().ok());
+		let Some(mut table) = table.filter(|table| table.bind().is_dirty()) else {
+			then(self);
+			return;
 		};
 		
-		let new_view = match innermost_path.extension().and_then(OsStr::to_str) {
-			Some("pak") => { return; },
-			Some("png") => {
-				let view = self.scene_image.instantiate().unwrap();
-				let data = PackedArray::from(cruddy_complex_load(&item_info.bind().source).unwrap());
-				let mut image = Image::create_empty(1, 1, false, Format::L8).unwrap();
-				image.load_png_from_buffer(&data);
-				let texture = ImageTexture::create_from_image(&image).unwrap();
+		let then = Rc::new(Cell::new(Some(Box::new(then) as Box<dyn FnOnce(&mut Self)>)));
+		let this = self.to_gd();
+		let leave = move || {
+			if let Some(then) = then.take() {
+				then(&mut this.clone().bind_mut());
+			}
+		};
+		
+		let mut dialog = ConfirmationDialog::new_alloc();
+		dialog.set_title("Unsaved changes");
+		dialog.set_text("This string table has unsaved changes. Save them first?");
+		dialog.set_ok_button_text("Save");
+		dialog.add_button_ex("Discard").action("discard").done();
+		let leave_after_saving = leave.clone();
+		dialog.signals().confirmed().connect(move || table.bind_mut().save_then(leave_after_saving.clone()));
+		let mut dialog_to_free = dialog.clone();
+		dialog.signals().custom_action().connect(move |action| {
+			if action == StringName::from("discard") {
+				dialog_to_free.queue_free();
+				leave();
+			}
+		});
+		let mut dialog_to_free = dialog.clone();
+		dialog.signals().confirmed().connect(move || dialog_to_free.queue_free());
+		let mut dialog_to_free = dialog.clone();
+		dialog.signals().canceled().connect(move || dialog_to_free.queue_free());
+		
+		self.base_mut().add_child(&dialog);
+		dialog.popup_centered();
+	}
+	
+	/// Shows a placeholder while `read` runs on another thread, then selects `cell` if it's a string table.
+	/// Whatever was loading before is cancelled, once any unsaved changes in the view being replaced have been dealt with.
+	fn load_in_background<F>(&mut self, path: VfsPath, cell: Option<(usize, usize)>, read: F)
+	where
+		F: FnOnce(&VfsPath, &CancelToken) -> formats::Result<ViewStuff> + Send + 'static,
+	{
+		self.leave_current_view(move |this| this.start_loading(path, cell, read));
+	}
+	
+	fn start_loading<F>(&mut self, path: VfsPath, cell: Option<(usize, usize)>, read: F)
+	where
+		F: FnOnce(&VfsPath, &CancelToken) -> formats::Result<ViewStuff> + Send + 'static,
+	{
+		if let Some(mut previous) = self.loading.take() && previous.is_instance_valid() {
+			previous.bind_mut().cancel();
+		}
+		self.pending_cell = cell;
+		
+		let mut placeholder = self.scene_unknown.instantiate_as::<Label>();
+		placeholder.set_text(&format!("Loading {}...", path.name()));
+		self.show_view(placeholder.upcast());
+		
+		let mut this = self.to_gd();
+		let job = BackgroundJob::run_fallible(
+			move |cancel| read(&path, cancel),
+			move |stuff| this.bind_mut().on_loaded(stuff),
+		);
+		self.base_mut().add_child(&job);
+		self.loading = Some(job);
+	}
+	
+	fn on_loaded(&mut self, stuff: Result<ViewStuff, String>) {
+		self.loading = None;
+		let new_view = match stuff {
+			Ok(stuff) => self.build_view(stuff),
+			Err(e) => Err(format!("This file couldn't be opened:\n{e}")),
+		};
+		let new_view = new_view.unwrap_or_else(|message| {
+			let mut view = self.scene_unknown.instantiate_as::<Label>();
+			view.set_text(&message);
+			view.upcast()
+		});
+		self.show_view(new_view);
+	}
+	
+	fn build_view(&mut self, stuff: ViewStuff) -> Result<Gd<Node>, String> {
+		Ok(match stuff {
+			ViewStuff::Archive => self.scene_none.instantiate_as::<Node>(),
+			ViewStuff::Png(data) => {
+				let mut image = Image::new_gd();
+				let result = image.load_png_from_buffer(&PackedArray::from(data));
+				if result != GodotError::OK {
+					return Err(format!("This image couldn't be decoded: {result:?}"));
+				}
+				let texture = ImageTexture::create_from_image(&image).ok_or("This image couldn't be turned into a texture.")?;
+				let view = self.scene_image.instantiate_as::<Node>();
 				view.get_node_as::<TextureRect>("TextureRect").set_texture(Some(&texture));
 				view
 			},
-			Some("stl") => {
+			ViewStuff::Level(stuff) => {
+				let mut view = FileViewLevel::new_alloc();
+				view.bind_mut().load_level_stuff(stuff);
+				view.upcast()
+			},
+			ViewStuff::St(path, table) => {
 				let mut view = FileViewSt::new_alloc();
-				view.bind_mut().load_stl_stuff(&item_info.bind().source).unwrap();
+				view.bind_mut().load_st_stuff(Some(path), table);
+				if let Some((row, field)) = self.pending_cell.take() {
+					view.call_deferred("select_cell", vslice![row as i64, field as i64]);
+				}
 				view.upcast()
 			},
-			_ => self.scene_unknown.instantiate().unwrap(),
-		};
-		
+			ViewStuff::Hex(stuff) => {
+				let mut view = FileViewHex::new_alloc();
+				view.bind_mut().load_hex_stuff(stuff);
+				view.upcast()
+			},
+		})
+	}
+	
+	fn show_view(&mut self, new_view: Gd<Node>) {
 		if let Some(current_view) = &mut self.current_view {
 			current_view.queue_free();
 		}
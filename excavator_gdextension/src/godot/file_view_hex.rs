@@ -0,0 +1,602 @@
+use godot::prelude::*;
+use godot::classes::{
+	HBoxContainer, HSplitContainer, InputEvent, InputEventMouseButton, Label, LineEdit, SystemFont, Tree, TreeItem,
+	VBoxContainer, VScrollBar,
+};
+use godot::classes::control::SizeFlags;
+use godot::classes::text_server::AutowrapMode;
+use godot::global::{HorizontalAlignment, MouseButton};
+
+use std::fmt::Write;
+use std::io::{Read, SeekFrom};
+use std::ops::Range;
+
+use crate::filesystem::vfs;
+use crate::formats::{self, FileType};
+use crate::formats::annotate::{annotate_file, Annotations, Segment};
+use crate::formats::vfs::{Interruptible, Vfs, VfsPath, VfsReader};
+use crate::godot::background_job::CancelToken;
+
+const BYTES_PER_ROW: u64 = 16;
+const ASCII_COLUMN: i32 = BYTES_PER_ROW as i32 + 1;
+/// The most items the structure list shows directly inside one parent. Longer lists are split into groups of this many.
+const STRUCTURE_GROUP_SIZE: usize = 500;
+
+/// An opened and annotated file, ready to be shown by [`FileViewHex::load_hex_stuff`].
+pub struct HexStuff {
+	reader: Box<dyn VfsReader>,
+	length: u64,
+	annotations: Annotations,
+	parse_result: formats::Result<()>,
+}
+
+/// Gives `item` an empty child, so it can be expanded to show the annotations in `range` of `parent`'s children.
+fn add_structure_placeholder(item: &mut Gd<TreeItem>, parent: Option<usize>, range: Range<usize>) {
+	let parent = parent.map_or(-1, |parent| parent as i64);
+	item.set_metadata(1, &PackedInt64Array::from(&[parent, range.start as i64, range.end as i64]).to_variant());
+	item.create_child();
+	item.set_collapsed(true);
+}
+
+/// Which of its parent's children an item holds, as given to [`add_structure_placeholder`].
+fn structure_range(item: &Gd<TreeItem>) -> Option<(Option<usize>, Range<usize>)> {
+	let range = item.get_metadata(1).try_to::<PackedInt64Array>().ok()?;
+	let [parent, start, end] = range.as_slice().try_into().ok()?;
+	Some((usize::try_from(parent).ok(), start as usize..end as usize))
+}
+
+fn is_structure_placeholder(item: &Gd<TreeItem>) -> bool {
+	item.get_metadata(0).is_nil() && item.get_metadata(1).is_nil()
+}
+
+/// Colours an annotation's bytes, so neighbouring fields can be told apart.
+fn annotation_color(index: usize) -> Color {
+	Color::from_hsv((index as f64 * 0.618_034).fract(), 0.6, 0.8).with_alpha(0.35)
+}
+
+/// Shows any file as hex and ASCII, for investigating formats that don't have a view of their own yet.
+///
+/// The file isn't loaded into memory. Like [`super::file_view_st::FileViewSt`], only the rows that fit are put in the tree,
+/// and the scroll bar beside it picks which ones, so they're read as they're shown.
+/// For types this crate can parse, the structures the parser came across are coloured and listed at the side.
+#[derive(GodotClass)]
+#[class(init, base=VBoxContainer)]
+pub struct FileViewHex {
+	base: Base<VBoxContainer>,
+	reader: Option<Box<dyn VfsReader>>,
+	length: u64,
+	annotations: Option<Annotations>,
+	segments: Vec<Segment>,
+	/// The selected row, by its offset, and column. It's kept while the row is scrolled out of the tree.
+	selected: Option<(u64, i32)>,
+	/// Where the first row is drawn in the tree and how tall rows are, once a row has been drawn to measure.
+	row_metrics: Option<(f32, f32)>,
+	tree: Option<Gd<Tree>>,
+	scroll: Option<Gd<VScrollBar>>,
+	/// Lists the annotations. Items are only created once their parent is expanded, since there can be millions.
+	structure: Option<Gd<Tree>>,
+	/// The item of each annotation, if it has been created yet.
+	structure_items: Vec<Option<Gd<TreeItem>>>,
+	/// The annotations without a parent, then the ones inside each annotation, in the order they were recorded.
+	structure_roots: Vec<usize>,
+	structure_children: Vec<Vec<usize>>,
+	info: Option<Gd<Label>>,
+}
+
+impl FileViewHex {
+	/// Opens the file and annotates it.
+	///
+	/// `file_type` picks the parser used to annotate the file. Parsing problems don't stop the file from being shown.
+	/// Annotating stops partway if `cancel` is cancelled.
+	pub fn read_hex_stuff(path: &VfsPath, file_type: FileType, cancel: &CancelToken) -> formats::Result<HexStuff> {
+		let mut reader = vfs().open_while(path, &mut cancel.keep_going())?;
+		let (annotations, parse_result) = annotate_file(file_type, &mut Interruptible::new(&mut reader, cancel.keep_going()));
+		let length = reader.seek(SeekFrom::End(0))?;
+		Ok(HexStuff { reader, length, annotations, parse_result })
+	}
+	
+	pub fn load_hex_stuff(&mut self, stuff: HexStuff) {
+		let HexStuff { reader, length, annotations, parse_result } = stuff;
+		self.length = length;
+		self.reader = Some(reader);
+		
+		let mut font = SystemFont::new_gd();
+		font.set_font_names(&PackedArray::from(&[GString::from("monospace")]));
+		
+		let mut tree = Tree::new_alloc();
+		tree.set_v_size_flags(SizeFlags::EXPAND_FILL);
+		tree.add_theme_font_override("font", &font);
+		tree.set_hide_root(true);
+		tree.set_column_titles_visible(true);
+		tree.set_v_scroll_enabled(false);
+		tree.set_columns(ASCII_COLUMN + 1);
+		tree.set_column_title(0, "Offset");
+		for column in 1..ASCII_COLUMN {
+			tree.set_column_title(column, &format!("{:02X}", column - 1));
+			tree.set_column_expand(column, false);
+			tree.set_column_custom_minimum_width(column, 30);
+		}
+		tree.set_column_title(ASCII_COLUMN, "ASCII");
+		tree.create_item();
+		let mut this = self.to_gd();
+		tree.signals().cell_selected().connect(move || this.bind_mut().on_cell_selected());
+		let mut this = self.to_gd();
+		tree.signals().gui_input().connect(move |event| this.bind_mut().on_tree_input(event));
+		let mut this = self.to_gd();
+		tree.signals().resized().connect(move || this.bind_mut().render());
+		
+		let mut scroll = VScrollBar::new_alloc();
+		scroll.set_step(1.0);
+		let mut this = self.to_gd();
+		scroll.signals().value_changed().connect(move |_| this.bind_mut().render());
+		let mut hex_box = HBoxContainer::new_alloc();
+		hex_box.set_v_size_flags(SizeFlags::EXPAND_FILL);
+		hex_box.add_child(&tree);
+		hex_box.add_child(&scroll);
+		
+		let mut go_to_label = Label::new_alloc();
+		go_to_label.set_text("Go to offset:");
+		let mut go_to = LineEdit::new_alloc();
+		go_to.set_placeholder("0x1F or 31");
+		go_to.set_custom_minimum_size(Vector2::new(160.0, 0.0));
+		let mut this = self.to_gd();
+		go_to.signals().text_submitted().connect(move |text| this.bind_mut().go_to_offset_text(&text.to_string()));
+		let mut size_label = Label::new_alloc();
+		let mut size_text = format!("{} bytes", self.length);
+		if let Err(e) = &parse_result {
+			let _ = write!(size_text, ". The structure is only shown up to a problem: {e}");
+		}
+		size_label.set_text(&size_text);
+		size_label.set_h_size_flags(SizeFlags::EXPAND_FILL);
+		size_label.set_autowrap_mode(AutowrapMode::WORD_SMART);
+		let mut bar = HBoxContainer::new_alloc();
+		bar.add_child(&go_to_label);
+		bar.add_child(&go_to);
+		bar.add_child(&size_label);
+		
+		let mut info = Label::new_alloc();
+		info.set_autowrap_mode(AutowrapMode::WORD_SMART);
+		info.set_text("Select a byte to see what it could mean.");
+		
+		self.base_mut().set_h_size_flags(SizeFlags::EXPAND_FILL);
+		self.base_mut().add_child(&bar);
+		if annotations.is_empty() {
+			self.base_mut().add_child(&hex_box);
+		} else {
+			hex_box.set_h_size_flags(SizeFlags::EXPAND_FILL);
+			let structure = self.build_structure(&annotations);
+			let mut split = HSplitContainer::new_alloc();
+			split.set_v_size_flags(SizeFlags::EXPAND_FILL);
+			split.add_child(&hex_box);
+			split.add_child(&structure);
+			self.base_mut().add_child(&split);
+			self.structure = Some(structure);
+		}
+		self.base_mut().add_child(&info);
+		self.tree = Some(tree);
+		self.scroll = Some(scroll);
+		self.info = Some(info);
+		self.segments = annotations.segments();
+		self.annotations = Some(annotations);
+		self.render();
+	}
+	
+	/// Lists the annotations as a tree, with each one inside its parent. Only the outermost items are created here.
+	fn build_structure(&mut self, annotations: &Annotations) -> Gd<Tree> {
+		let mut structure = Tree::new_alloc();
+		structure.set_custom_minimum_size(Vector2::new(320.0, 0.0));
+		structure.set_hide_root(true);
+		structure.set_column_titles_visible(true);
+		structure.set_columns(3);
+		structure.set_column_title(0, "Structure");
+		structure.set_column_title(1, "Offset");
+		structure.set_column_title(2, "Length");
+		for column in 1..3 {
+			structure.set_column_expand(column, false);
+			structure.set_column_custom_minimum_width(column, 90);
+		}
+		let mut root = structure.create_item().unwrap();
+		
+		self.structure_items = vec![None; annotations.len()];
+		self.structure_roots = Vec::new();
+		self.structure_children = vec![Vec::new(); annotations.len()];
+		for (i, annotation) in annotations.iter().enumerate() {
+			match annotation.parent {
+				Some(parent) => self.structure_children[parent].push(i),
+				None => self.structure_roots.push(i),
+			}
+		}
+		self.fill_structure_item(annotations, &mut root, None, 0..self.structure_roots.len());
+		
+		let mut this = self.to_gd();
+		structure.signals().item_selected().connect(move || this.bind_mut().on_structure_selected());
+		let mut this = self.to_gd();
+		structure.signals().item_collapsed().connect(move |item| this.bind_mut().on_structure_collapsed(item));
+		structure
+	}
+	
+	fn structure_children_of(&self, parent: Option<usize>) -> &[usize] {
+		match parent {
+			Some(parent) => &self.structure_children[parent],
+			None => &self.structure_roots,
+		}
+	}
+	
+	/// Creates items for the annotations in `range` of `parent`'s children, or groups for them if there are too many.
+	/// Items that have children of their own get a placeholder, so they can be expanded.
+	fn fill_structure_item(&mut self, annotations: &Annotations, item: &mut Gd<TreeItem>, parent: Option<usize>, range: Range<usize>) {
+		if range.len() <= STRUCTURE_GROUP_SIZE {
+			for position in range {
+				let index = self.structure_children_of(parent)[position];
+				let annotation = annotations.get(index).unwrap();
+				let mut child = item.create_child().unwrap();
+				child.set_text(0, &annotation.name);
+				child.set_custom_bg_color(0, annotation_color(index));
+				child.set_text(1, &format!("{:#X}", annotation.offset));
+				child.set_text(2, &annotation.length.to_string());
+				child.set_metadata(0, &(index as u64).to_variant());
+				let child_count = self.structure_children[index].len();
+				if child_count > 0 {
+					add_structure_placeholder(&mut child, Some(index), 0..child_count);
+				}
+				self.structure_items[index] = Some(child);
+			}
+			return;
+		}
+		
+		let mut group_size = STRUCTURE_GROUP_SIZE;
+		while range.len().div_ceil(group_size) > STRUCTURE_GROUP_SIZE {
+			group_size = group_size.saturating_mul(STRUCTURE_GROUP_SIZE);
+		}
+		for start in range.clone().step_by(group_size) {
+			let end = start.saturating_add(group_size).min(range.end);
+			let children = self.structure_children_of(parent);
+			let first = &annotations.get(children[start]).unwrap().name;
+			let last = &annotations.get(children[end - 1]).unwrap().name;
+			let mut group = item.create_child().unwrap();
+			group.set_text(0, &format!("{first} … {last}"));
+			group.set_custom_color(0, Color::from_rgb(0.6, 0.6, 0.6));
+			add_structure_placeholder(&mut group, parent, start..end);
+		}
+	}
+	
+	/// Replaces the placeholder inside `item` with the items it stands for. Does nothing if that already happened.
+	fn expand_structure_item(&mut self, item: &mut Gd<TreeItem>) {
+		let Some(placeholder) = item.get_first_child() else { return; };
+		if !is_structure_placeholder(&placeholder) { return; }
+		let Some((parent, range)) = structure_range(item) else { return; };
+		let Some(annotations) = self.annotations.take() else { return; };
+		placeholder.free();
+		self.fill_structure_item(&annotations, item, parent, range);
+		self.annotations = Some(annotations);
+	}
+	
+	/// The item of an annotation, creating it and the items around it if needed.
+	fn structure_item(&mut self, index: usize) -> Option<Gd<TreeItem>> {
+		if let Some(item) = &self.structure_items[index] {
+			return Some(item.clone());
+		}
+		let parent = self.annotations.as_ref()?.get(index)?.parent;
+		let mut item = match parent {
+			Some(parent) => self.structure_item(parent)?,
+			None => self.structure.as_ref()?.get_root()?,
+		};
+		// Children are recorded in order, so the position tells which group the annotation is in
+		let position = self.structure_children_of(parent).binary_search(&index).ok()?;
+		loop {
+			self.expand_structure_item(&mut item);
+			if let Some(found) = &self.structure_items[index] {
+				return Some(found.clone());
+			}
+			item = item.get_children().iter_shared()
+				.find(|group| structure_range(group).is_some_and(|(_, range)| range.contains(&position)))?;
+		}
+	}
+	
+	fn on_structure_collapsed(&mut self, mut item: Gd<TreeItem>) {
+		if item.is_collapsed() { return; }
+		let Some(mut structure) = self.structure.clone() else { return; };
+		// Collapsing the new items would call back into this object while it's already borrowed
+		structure.set_block_signals(true);
+		self.expand_structure_item(&mut item);
+		structure.set_block_signals(false);
+	}
+	
+	/// The names of the annotations around `offset`, from the outermost to the innermost.
+	fn annotation_path(&self, offset: u64) -> Option<(usize, String)> {
+		let annotations = self.annotations.as_ref()?;
+		let innermost = Annotations::innermost_in(&self.segments, offset)?;
+		let mut names: Vec<&str> = annotations.ancestors(innermost)
+			.map(|i| annotations.get(i).unwrap().name.as_str())
+			.collect();
+		names.reverse();
+		Some((innermost, names.join(" › ")))
+	}
+	
+	fn total_rows(&self) -> u64 {
+		self.length.div_ceil(BYTES_PER_ROW)
+	}
+	
+	fn set_info(&mut self, text: &str) {
+		if let Some(info) = &mut self.info {
+			info.set_text(text);
+		}
+	}
+	
+	/// Reads up to `count` bytes starting at `offset`. Fewer are returned at the end of the file.
+	fn read_at(&mut self, offset: u64, count: u64) -> std::io::Result<Vec<u8>> {
+		let Some(reader) = &mut self.reader else { return Ok(Vec::new()); };
+		reader.seek(SeekFrom::Start(offset))?;
+		let mut buf = Vec::new();
+		reader.take(count).read_to_end(&mut buf)?;
+		Ok(buf)
+	}
+	
+	/// How many rows fit in the tree. Before a row has been measured, it's a guess.
+	fn visible_rows(&self) -> u64 {
+		let Some(tree) = &self.tree else { return 1; };
+		let (top, row_height) = self.row_metrics.unwrap_or((28.0, 24.0));
+		((tree.get_size().y - top) / row_height).floor().max(1.0) as u64
+	}
+	
+	/// Makes the scroll bar cover every row, with a page being the rows that fit.
+	fn update_scroll_range(&mut self) {
+		let visible = self.visible_rows();
+		let total = self.total_rows();
+		let Some(scroll) = &mut self.scroll else { return; };
+		// Changing the range can move the value, which would render in the middle of this
+		scroll.set_block_signals(true);
+		scroll.set_max(total as f64);
+		scroll.set_page(visible as f64);
+		scroll.set_block_signals(false);
+	}
+	
+	/// Fills the tree with the rows that fit, starting from the scroll bar's position.
+	fn render(&mut self) {
+		self.update_scroll_range();
+		let visible = self.visible_rows();
+		let total = self.total_rows();
+		let Some(scroll) = &self.scroll else { return; };
+		let first = (scroll.get_value() as u64).min(total.saturating_sub(visible));
+		let count = visible.min(total - first);
+		let start = first * BYTES_PER_ROW;
+		let data = match self.read_at(start, count * BYTES_PER_ROW) {
+			Ok(data) => data,
+			Err(e) => {
+				self.set_info(&format!("Couldn't read offset {start:#X}: {e}"));
+				Vec::new()
+			},
+		};
+		
+		let Some(mut tree) = self.tree.clone() else { return; };
+		let Some(mut root) = tree.get_root() else { return; };
+		let rows: Vec<&[u8]> = data.chunks(BYTES_PER_ROW as usize).collect();
+		// Selecting items would send signals while this is borrowed
+		tree.set_block_signals(true);
+		tree.deselect_all();
+		let mut children = root.get_child_count() as usize;
+		while children > rows.len() {
+			children -= 1;
+			if let Some(item) = root.get_child(children as i32) {
+				item.free();
+			}
+		}
+		for (i, row) in rows.iter().enumerate() {
+			let mut item = match root.get_child(i as i32) {
+				Some(item) => item,
+				None => root.create_child().unwrap(),
+			};
+			let row_offset = start + i as u64 * BYTES_PER_ROW;
+			self.show_row(&mut item, row_offset, row);
+			if let Some((selected_offset, column)) = self.selected && selected_offset == row_offset {
+				item.select(column);
+			}
+		}
+		tree.set_block_signals(false);
+		
+		if self.row_metrics.is_none() && let Some(item) = root.get_first_child() {
+			let rect = tree.get_item_area_rect(&item);
+			if rect.size.y > 0.0 {
+				self.row_metrics = Some((rect.position.y, rect.size.y));
+				self.render();
+			}
+		}
+	}
+	
+	/// Fills a reused item with the row at `row_offset`, which is shorter than a full row at the end of the file.
+	fn show_row(&self, item: &mut Gd<TreeItem>, row_offset: u64, row: &[u8]) {
+		let offset_digits = format!("{:X}", self.length).len().max(8);
+		item.set_text(0, &format!("{row_offset:0offset_digits$X}"));
+		item.set_metadata(0, &row_offset.to_variant());
+		item.set_custom_color(0, Color::from_rgb(0.6, 0.6, 0.6));
+		for column in 0..BYTES_PER_ROW as usize {
+			let cell = column as i32 + 1;
+			item.set_text_alignment(cell, HorizontalAlignment::CENTER);
+			item.set_selectable(cell, column < row.len());
+			item.set_text(cell, &row.get(column).map_or_else(String::new, |byte| format!("{byte:02X}")));
+			match row.get(column).and_then(|_| self.annotation_path(row_offset + column as u64)) {
+				Some((annotation, path)) => {
+					item.set_custom_bg_color(cell, annotation_color(annotation));
+					item.set_tooltip_text(cell, &path);
+				},
+				None => {
+					item.clear_custom_bg_color(cell);
+					item.set_tooltip_text(cell, "");
+				},
+			}
+		}
+		let ascii: String = row.iter().map(|&byte| if (0x20..0x7F).contains(&byte) { byte as char } else { '.' }).collect();
+		item.set_text(ASCII_COLUMN, &ascii);
+	}
+	
+	/// Scrolls so `row` is shown, either in the middle or just inside the edge it was past.
+	fn scroll_into_view(&mut self, row: u64, center: bool) {
+		self.update_scroll_range();
+		let visible = self.visible_rows();
+		let Some(scroll) = &mut self.scroll else { return; };
+		let first = scroll.get_value() as u64;
+		let first = if center {
+			row.saturating_sub(visible / 2)
+		} else if row < first {
+			row
+		} else if row >= first + visible {
+			row + 1 - visible
+		} else {
+			first
+		};
+		scroll.set_value_no_signal(first as f64);
+	}
+	
+	/// The offset a cell stands for. The offset and ASCII columns stand for the start of the row.
+	fn cell_offset(row_offset: u64, column: i32) -> u64 {
+		if (1..ASCII_COLUMN).contains(&column) { row_offset + column as u64 - 1 } else { row_offset }
+	}
+	
+	/// Selects a cell, scrolling to it, and shows what's there.
+	fn select_cell(&mut self, row: u64, column: i32, center: bool) {
+		let row_offset = row * BYTES_PER_ROW;
+		// The last row can be short, so stay on its last byte
+		let row_length = (self.length - row_offset).min(BYTES_PER_ROW) as i32;
+		let column = if (1..ASCII_COLUMN).contains(&column) { column.min(row_length) } else { column };
+		self.selected = Some((row_offset, column));
+		self.scroll_into_view(row, center);
+		self.render();
+		let offset = Self::cell_offset(row_offset, column);
+		self.show_interpretations(offset);
+		self.select_structure_at(offset);
+	}
+	
+	/// Moves the selection up or down, scrolling to keep it in view.
+	fn move_selection(&mut self, delta: i64) {
+		let total = self.total_rows();
+		if total == 0 { return; }
+		let (row, column) = match self.selected {
+			Some((row_offset, column)) => ((row_offset / BYTES_PER_ROW).saturating_add_signed(delta).min(total - 1), column),
+			None => (self.scroll.as_ref().map_or(0, |scroll| scroll.get_value() as u64), 1),
+		};
+		self.select_cell(row, column, false);
+	}
+	
+	/// The tree can't scroll by itself, since it only has the rows that fit, so scrolling and moving the selection are done here.
+	fn on_tree_input(&mut self, event: Gd<InputEvent>) {
+		let handled = if let Ok(button) = event.clone().try_cast::<InputEventMouseButton>() {
+			let step = match button.get_button_index() {
+				_ if !button.is_pressed() || button.is_shift_pressed() => None,
+				MouseButton::WHEEL_UP => Some(-3),
+				MouseButton::WHEEL_DOWN => Some(3),
+				_ => None,
+			};
+			if let Some(step) = step && let Some(scroll) = &mut self.scroll {
+				let value = scroll.get_value() + f64::from(step);
+				scroll.set_value_no_signal(value);
+				self.render();
+			}
+			step.is_some()
+		} else {
+			let page = self.visible_rows() as i64;
+			let moves = [("ui_up", -1), ("ui_down", 1), ("ui_page_up", -page), ("ui_page_down", page)];
+			let delta = moves.into_iter().find(|(action, _)| event.is_action_pressed_ex(*action).allow_echo(true).done());
+			if let Some((_, delta)) = delta {
+				self.move_selection(delta);
+			}
+			delta.is_some()
+		};
+		if handled && let Some(tree) = &mut self.tree {
+			tree.accept_event();
+		}
+	}
+	
+	fn on_cell_selected(&mut self) {
+		let Some(tree) = &self.tree else { return; };
+		let Some(item) = tree.get_selected() else { return; };
+		let Ok(row_offset) = item.get_metadata(0).try_to::<u64>() else { return; };
+		let column = tree.get_selected_column();
+		self.selected = Some((row_offset, column));
+		let offset = Self::cell_offset(row_offset, column);
+		self.show_interpretations(offset);
+		self.select_structure_at(offset);
+	}
+	
+	/// Selects the innermost annotation around `offset` in the structure list, expanding its parents so it's visible.
+	fn select_structure_at(&mut self, offset: u64) {
+		let Some(mut structure) = self.structure.clone() else { return; };
+		let Some(annotation) = Annotations::innermost_in(&self.segments, offset) else {
+			structure.deselect_all();
+			return;
+		};
+		// Expanding the parents or selecting the item would call back into this object while it's already borrowed
+		structure.set_block_signals(true);
+		let Some(item) = self.structure_item(annotation) else {
+			structure.set_block_signals(false);
+			return;
+		};
+		let mut parent = item.get_parent();
+		while let Some(mut ancestor) = parent {
+			ancestor.set_collapsed(false);
+			parent = ancestor.get_parent();
+		}
+		structure.set_selected(&item, 0);
+		structure.set_block_signals(false);
+		structure.scroll_to_item(&item);
+	}
+	
+	fn on_structure_selected(&mut self) {
+		let Some(structure) = &self.structure else { return; };
+		let Some(item) = structure.get_selected() else { return; };
+		let Ok(index) = item.get_metadata(0).try_to::<u64>() else { return; };
+		let Some(annotation) = self.annotations.as_ref().and_then(|annotations| annotations.get(index as usize)) else { return; };
+		let offset = annotation.offset;
+		if offset < self.length {
+			self.go_to_offset(offset);
+		}
+	}
+	
+	/// Shows what the bytes starting at `offset` would be as little-endian numbers.
+	fn show_interpretations(&mut self, offset: u64) {
+		let bytes = match self.read_at(offset, 8) {
+			Ok(bytes) => bytes,
+			Err(e) => {
+				self.set_info(&format!("Couldn't read offset {offset:#X}: {e}"));
+				return;
+			},
+		};
+		
+		let mut text = format!("Offset {offset:#X} ({offset})");
+		let field = |len: usize| bytes.get(..len);
+		let mut show = |name: &str, value: Option<String>| {
+			let _ = write!(text, "   {name}: {}", value.as_deref().unwrap_or("-"));
+		};
+		show("u16", field(2).map(|b| u16::from_le_bytes(b.try_into().unwrap())).map(|v| format!("{v} ({v:#06X})")));
+		show("u32", field(4).map(|b| u32::from_le_bytes(b.try_into().unwrap())).map(|v| format!("{v} ({v:#010X})")));
+		show("u64", field(8).map(|b| u64::from_le_bytes(b.try_into().unwrap())).map(|v| format!("{v} ({v:#018X})")));
+		show("f32", field(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).map(|v| format!("{v}")));
+		if let Some((_, path)) = self.annotation_path(offset) {
+			let _ = write!(text, "\nPart of: {path}");
+		}
+		self.set_info(&text);
+	}
+	
+	/// Accepts hex with a `0x` prefix, or decimal.
+	fn go_to_offset_text(&mut self, text: &str) {
+		let text = text.trim();
+		let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+			Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16),
+			None => text.replace('_', "").parse(),
+		};
+		match parsed {
+			Ok(offset) if offset < self.length => {
+				self.go_to_offset(offset);
+				self.select_structure_at(offset);
+			},
+			Ok(offset) => self.set_info(&format!("Offset {offset:#X} is past the end of the file ({:#X} bytes)", self.length)),
+			Err(_) => self.set_info(&format!("{text:?} isn't an offset. Use hex starting with 0x, or decimal")),
+		}
+	}
+	
+	fn go_to_offset(&mut self, offset: u64) {
+		self.selected = Some((offset - offset % BYTES_PER_ROW, (offset % BYTES_PER_ROW) as i32 + 1));
+		self.scroll_into_view(offset / BYTES_PER_ROW, true);
+		self.render();
+		self.show_interpretations(offset);
+	}
+}
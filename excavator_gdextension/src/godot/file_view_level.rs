@@ -0,0 +1,255 @@
+use godot::prelude::*;
+use godot::classes::{
+	CheckBox, Control, IControl, HSplitContainer, IHSplitContainer, InputEvent, InputEventMouseButton,
+	InputEventMouseMotion, Label, ScrollContainer, Tree, TreeItem, VBoxContainer,
+};
+use godot::classes::control::SizeFlags;
+use godot::global::{MouseButton, MouseButtonMask};
+
+use crate::formats::{self, FileType};
+use crate::formats::level::{read_ltb, read_lvb, LtbLayer};
+use crate::formats::vfs::VfsPath;
+use crate::godot::background_job::CancelToken;
+
+/// A set of points that can be shown or hidden together.
+pub struct CanvasLayer {
+	name: String,
+	points: Vec<Vector2>,
+	color: Color,
+	visible: bool,
+}
+
+/// What a level view shows, read away from the main thread.
+pub enum LevelStuff {
+	/// Positions to plot.
+	Plot(Vec<CanvasLayer>),
+	/// Nobody knows what an LTB layer's numbers mean yet, so they're listed as they are instead of plotted.
+	Layers(Vec<LtbLayer>),
+}
+
+/// A scrollable list of checkboxes, all checked to start with. `toggled` is called with the index of the one that changed.
+fn toggle_list(labels: &[(String, Option<Color>)], toggled: impl FnMut(usize, bool) + Clone + 'static) -> Gd<ScrollContainer> {
+	let mut list = VBoxContainer::new_alloc();
+	for (i, (label, color)) in labels.iter().enumerate() {
+		let mut check_box = CheckBox::new_alloc();
+		check_box.set_text(label);
+		check_box.set_pressed_no_signal(true);
+		if let Some(color) = color {
+			check_box.add_theme_color_override("font_color", *color);
+		}
+		let mut toggled = toggled.clone();
+		check_box.signals().toggled().connect(move |pressed| toggled(i, pressed));
+		list.add_child(&check_box);
+	}
+	if labels.is_empty() {
+		let mut label = Label::new_alloc();
+		label.set_text("(No layers)");
+		list.add_child(&label);
+	}
+	
+	let mut scroll = ScrollContainer::new_alloc();
+	scroll.set_custom_minimum_size(Vector2::new(180.0, 0.0));
+	scroll.add_child(&list);
+	scroll
+}
+
+/// Plots the positions found in a level file. Scroll to zoom, and drag to pan.
+#[derive(GodotClass)]
+#[class(base=Control)]
+pub struct LevelCanvas {
+	base: Base<Control>,
+	layers: Vec<CanvasLayer>,
+	zoom: f32,
+	pan: Vector2,
+}
+
+#[godot_api]
+impl IControl for LevelCanvas {
+	fn init(base: Base<Control>) -> Self {
+		Self {
+			base,
+			layers: Vec::new(),
+			zoom: 1.0,
+			pan: Vector2::ZERO,
+		}
+	}
+	
+	fn ready(&mut self) {
+		self.base_mut().set_clip_contents(true);
+	}
+	
+	fn draw(&mut self) {
+		let size = self.base().get_size();
+		let origin = self.to_screen(Vector2::ZERO);
+		let axis_color = Color::from_rgba(1.0, 1.0, 1.0, 0.2);
+		self.base_mut().draw_line(Vector2::new(0.0, origin.y), Vector2::new(size.x, origin.y), axis_color);
+		self.base_mut().draw_line(Vector2::new(origin.x, 0.0), Vector2::new(origin.x, size.y), axis_color);
+		
+		let radius = (2.0 * self.zoom).clamp(1.5, 6.0);
+		let mut dots = Vec::<(Vector2, Color)>::new();
+		for layer in self.layers.iter().filter(|layer| layer.visible) {
+			dots.extend(layer.points.iter().map(|point| (self.to_screen(*point), layer.color)));
+		}
+		for (position, color) in dots {
+			self.base_mut().draw_circle(position, radius, color);
+		}
+	}
+	
+	fn gui_input(&mut self, event: Gd<InputEvent>) {
+		if let Ok(button) = event.clone().try_cast::<InputEventMouseButton>() {
+			let factor = match button.get_button_index() {
+				MouseButton::WHEEL_UP if button.is_pressed() => 1.25,
+				MouseButton::WHEEL_DOWN if button.is_pressed() => 0.8,
+				_ => return,
+			};
+			// Keep the point under the cursor in place while zooming
+			let cursor = button.get_position();
+			let under_cursor = self.to_level(cursor);
+			self.zoom = (self.zoom * factor).clamp(0.01, 100.0);
+			self.pan += cursor - self.to_screen(under_cursor);
+			self.base_mut().accept_event();
+			self.base_mut().queue_redraw();
+		} else if let Ok(motion) = event.try_cast::<InputEventMouseMotion>() {
+			let mask = motion.get_button_mask();
+			if mask.is_set(MouseButtonMask::LEFT) || mask.is_set(MouseButtonMask::MIDDLE) {
+				self.pan += motion.get_relative();
+				self.base_mut().accept_event();
+				self.base_mut().queue_redraw();
+			}
+		}
+	}
+}
+
+#[godot_api]
+impl LevelCanvas {
+	fn to_screen(&self, point: Vector2) -> Vector2 {
+		point * self.zoom + self.pan
+	}
+	
+	fn to_level(&self, position: Vector2) -> Vector2 {
+		(position - self.pan) / self.zoom
+	}
+	
+	fn set_layer_visible(&mut self, index: usize, visible: bool) {
+		self.layers[index].visible = visible;
+		self.base_mut().queue_redraw();
+	}
+	
+	/// Zooms and pans so every point is on screen.
+	#[func]
+	fn fit_to_view(&mut self) {
+		let mut points = self.layers.iter().flat_map(|layer| layer.points.iter().copied());
+		let Some(first) = points.next() else { return; };
+		let bounds = points.fold(Rect2::new(first, Vector2::ZERO), |bounds, point| bounds.expand(point));
+		
+		let size = self.base().get_size();
+		let margin = 16.0;
+		let available = (size - Vector2::splat(margin * 2.0)).coord_max(Vector2::ONE);
+		let extent = bounds.size.coord_max(Vector2::ONE);
+		self.zoom = (available.x / extent.x).min(available.y / extent.y).clamp(0.01, 100.0);
+		self.pan = Vector2::splat(margin) - bounds.position * self.zoom;
+		self.base_mut().queue_redraw();
+	}
+}
+
+/// Shows a level file, with a list of layers that can be toggled.
+///
+/// LVB objects are plotted on a [`LevelCanvas`]. LTB layers are shown as a table of their numbers.
+#[derive(GodotClass)]
+#[class(init, base=HSplitContainer)]
+pub struct FileViewLevel {
+	base: Base<HSplitContainer>,
+	canvas: Option<Gd<LevelCanvas>>,
+}
+
+#[godot_api]
+impl IHSplitContainer for FileViewLevel {
+	fn ready(&mut self) {
+		// The canvas only knows its size once it's been laid out
+		if let Some(canvas) = &mut self.canvas {
+			canvas.call_deferred("fit_to_view", &[]);
+		}
+	}
+}
+
+impl FileViewLevel {
+	/// Reads the level and works out what to show.
+	pub fn read_level_stuff(path: &VfsPath, file_type: FileType, cancel: &CancelToken) -> formats::Result<LevelStuff> {
+		Ok(match file_type {
+			FileType::Lvb => {
+				let lvb = read_lvb(&mut cancel.open(path)?)?;
+				let points = lvb.objects.iter()
+					.map(|object| Vector2::new(f32::from(object.maybe_x), f32::from(object.maybe_y)))
+					.collect();
+				LevelStuff::Plot(vec![CanvasLayer {
+					name: "Objects".to_string(),
+					points,
+					color: Color::from_hsv(0.0, 0.7, 0.95),
+					visible: true,
+				}])
+			},
+			FileType::Ltb => LevelStuff::Layers(read_ltb(&mut cancel.open(path)?)?.layers),
+			_ => unreachable!(),
+		})
+	}
+	
+	pub fn load_level_stuff(&mut self, stuff: LevelStuff) {
+		self.base_mut().set_h_size_flags(SizeFlags::EXPAND_FILL);
+		match stuff {
+			LevelStuff::Plot(layers) => self.load_plot(layers),
+			LevelStuff::Layers(layers) => self.load_layer_table(&layers),
+		}
+	}
+	
+	fn load_plot(&mut self, layers: Vec<CanvasLayer>) {
+		let mut canvas = LevelCanvas::new_alloc();
+		canvas.set_h_size_flags(SizeFlags::EXPAND_FILL);
+		canvas.set_v_size_flags(SizeFlags::EXPAND_FILL);
+		
+		let labels: Vec<_> = layers.iter()
+			.map(|layer| (format!("{} ({} points)", layer.name, layer.points.len()), Some(layer.color)))
+			.collect();
+		let canvas_for_list = canvas.clone();
+		let layer_list = toggle_list(&labels, move |i, pressed| canvas_for_list.clone().bind_mut().set_layer_visible(i, pressed));
+		canvas.bind_mut().layers = layers;
+		
+		self.base_mut().add_child(&canvas);
+		self.base_mut().add_child(&layer_list);
+		self.canvas = Some(canvas);
+	}
+	
+	/// One row per layer, with its name and each of its numbers. Unticking a layer hides its row, to compare the others.
+	fn load_layer_table(&mut self, layers: &[LtbLayer]) {
+		let number_count = layers.first().map_or(0, |layer| layer.numbers.len());
+		let mut tree = Tree::new_alloc();
+		tree.set_h_size_flags(SizeFlags::EXPAND_FILL);
+		tree.set_v_size_flags(SizeFlags::EXPAND_FILL);
+		tree.set_hide_root(true);
+		tree.set_column_titles_visible(true);
+		tree.set_columns(1 + number_count as i32);
+		tree.set_column_title(0, "Layer");
+		for i in 0..number_count {
+			let column = i as i32 + 1;
+			tree.set_column_title(column, &i.to_string());
+			tree.set_column_expand(column, false);
+			tree.set_column_custom_minimum_width(column, 90);
+		}
+		
+		let mut root = tree.create_item().unwrap();
+		let items: Vec<Gd<TreeItem>> = layers.iter().map(|layer| {
+			let mut item = root.create_child().unwrap();
+			item.set_text(0, &layer.name);
+			for (i, number) in layer.numbers.iter().enumerate() {
+				let column = i as i32 + 1;
+				item.set_text(column, &number.to_string());
+				item.set_tooltip_text(column, &format!("{number:#010X}"));
+			}
+			item
+		}).collect();
+		
+		let labels: Vec<_> = layers.iter().map(|layer| (layer.name.clone(), None)).collect();
+		let layer_list = toggle_list(&labels, move |i, pressed| items[i].clone().set_visible(pressed));
+		self.base_mut().add_child(&tree);
+		self.base_mut().add_child(&layer_list);
+	}
+}
@@ -1,72 +1,763 @@
 use godot::prelude::*;
-use godot::classes::{Tree, ITree};
+use godot::classes::{
+	AcceptDialog, Button, FileDialog, HBoxContainer, HSplitContainer, InputEvent, InputEventKey, InputEventMouseButton, Label, LineEdit,
+	OptionButton, Shortcut, Tree, TreeItem, VBoxContainer, VScrollBar,
+};
+use godot::classes::control::SizeFlags;
+use godot::classes::file_dialog::{Access, FileMode};
+use godot::classes::text_server::OverrunBehavior;
+use godot::global::{Key, MouseButton};
+use godot::tools::get_autoload_by_name;
 
-use std::error::Error;
 use std::io::Cursor;
 
-use crate::filesystem::cruddy_complex_load;
-use crate::formats::st::{read_st, StReadOutcome};
-use crate::godot::browser_tree::ItemSource;
+use crate::filesystem::vfs;
+use crate::formats;
+use crate::formats::st::edit::{StChange, StEditState, StEditor, StRowOrder};
+use crate::formats::st::{
+	read_st_with_encoding, string_checksum, verify_checksums, write_st, StEncoding, StExtendedData, StTable,
+};
+use crate::formats::vfs::VfsPath;
+use crate::godot::autoload::GlobalRust;
+use crate::godot::background_job::{BackgroundJob, CancelToken};
 
+/// A keyboard shortcut using Ctrl, or Cmd on macOS.
+fn shortcut(keys: &[(Key, bool)]) -> Gd<Shortcut> {
+	let events: VarArray = keys.iter().map(|&(key, shift)| {
+		let mut event = InputEventKey::new_gd();
+		event.set_keycode(key);
+		event.set_command_or_control_autoremap(true);
+		event.set_shift_pressed(shift);
+		event.to_variant()
+	}).collect();
+	let mut shortcut = Shortcut::new_gd();
+	shortcut.set_events(&events);
+	shortcut
+}
+
+/// Marks strings whose stored checksum doesn't match their text.
+const MISMATCH_COLOR: Color = Color::from_rgba(0.8, 0.2, 0.2, 0.35);
+
+/// The first column shows row numbers, and each field's column comes after it.
+fn field_column(field: usize) -> i32 {
+	field as i32 + 1
+}
+
+/// Shows a row's number and strings. For tables with checksums, they go in an extra last column.
+/// Strings that don't match their checksums are marked, with a tooltip saying so.
+fn show_row(item: &mut Gd<TreeItem>, row: usize, table: &StTable) {
+	let field_count = table.field_count;
+	let start = row * field_count;
+	item.set_text(0, &row.to_string());
+	item.set_metadata(0, &(row as u64).to_variant());
+	let mut checksums = Vec::new();
+	let mut mismatches = Vec::new();
+	for (j, string) in table.strings[start..start + field_count].iter().enumerate() {
+		let column = field_column(j);
+		item.set_text(column, string.text());
+		item.set_editable(column, true);
+		let mut tooltip = String::new();
+		if string.is_lossy() {
+			tooltip = format!("Some bytes aren't valid {}: {:02X?}", table.encoding.name(), string.raw());
+		}
+		item.clear_custom_bg_color(column);
+		if let Some(extended) = &table.extended {
+			let stored = extended.checksums[start + j];
+			let computed = string_checksum(string.raw());
+			checksums.push(format!("{stored:08X}"));
+			if stored != computed {
+				let mismatch = format!("The stored checksum is {stored:08X}, but the text's is {computed:08X}");
+				tooltip = if tooltip.is_empty() { mismatch.clone() } else { format!("{tooltip}\n{mismatch}") };
+				item.set_custom_bg_color(column, MISMATCH_COLOR);
+				mismatches.push(format!("{}: {mismatch}", table.strings[j].text()));
+			}
+		}
+		item.set_tooltip_text(column, &tooltip);
+	}
+	
+	if table.extended.is_some() {
+		let column = field_column(field_count);
+		item.set_text(column, &checksums.join(" "));
+		item.set_tooltip_text(column, &mismatches.join("\n"));
+		if mismatches.is_empty() {
+			item.clear_custom_bg_color(column);
+		} else {
+			item.set_custom_bg_color(column, MISMATCH_COLOR);
+		}
+	}
+}
+
+/// Lists the entries of the extra sections of `.stm`/`.stb` files. What their pieces mean is unknown, so they're shown as numbers.
+fn extra_panel(extended: &StExtendedData) -> Gd<Tree> {
+	let mut panel = Tree::new_alloc();
+	panel.set_custom_minimum_size(Vector2::new(260.0, 0.0));
+	panel.set_hide_root(true);
+	panel.set_column_titles_visible(true);
+	panel.set_columns(3);
+	panel.set_column_title(0, "Extra data");
+	panel.set_column_title(1, "First");
+	panel.set_column_title(2, "Second");
+	for column in 1..3 {
+		panel.set_column_expand(column, false);
+		panel.set_column_custom_minimum_width(column, 90);
+	}
+	let mut root = panel.create_item().unwrap();
+	for (name, section) in [("extra1", &extended.extra1), ("extra2", &extended.extra2)] {
+		let mut section_item = root.create_child().unwrap();
+		section_item.set_text(0, &format!("{name} ({} entries)", section.len()));
+		for (i, entry) in section.iter().enumerate() {
+			let mut entry_item = section_item.create_child().unwrap();
+			entry_item.set_text(0, &format!("Entry {i} ({} pieces)", entry.pieces.len()));
+			entry_item.set_collapsed(true);
+			for (k, (first, second)) in entry.pieces.iter().enumerate() {
+				let mut piece_item = entry_item.create_child().unwrap();
+				piece_item.set_text(0, &format!("Piece {k}"));
+				piece_item.set_text(1, &first.to_string());
+				piece_item.set_tooltip_text(1, &format!("{first:#X}"));
+				piece_item.set_text(2, &second.to_string());
+				piece_item.set_tooltip_text(2, &format!("{second:#X}"));
+			}
+		}
+	}
+	panel
+}
+
+/// Shows a string table, and lets its strings and rows be edited and saved.
+///
+/// `.stm`/`.stb` tables also show the checksums of each row, and their extra data at the side.
+///
+/// Row 0 of the table names the fields, so it's shown as the column titles. Only the rows that fit are put in the tree,
+/// and the scroll bar beside it picks which ones, so even huge tables can be used straight away.
+/// The tree's items are reused as it scrolls, and each one holds the number of the row it's showing.
 #[derive(GodotClass)]
-#[class(init, base=Tree)]
+#[class(init, base=VBoxContainer)]
 pub struct FileViewSt {
-	base: Base<Tree>,
-	stuff: Option<StReadOutcome>,
-	loaded_items: usize,
+	base: Base<VBoxContainer>,
+	editor: Option<StEditor>,
+	/// Where saving writes to. `None` until a file is chosen, for tables that didn't come from one.
+	path: Option<VfsPath>,
+	/// The order rows are shown in.
+	order: StRowOrder,
+	/// The column being sorted by, and whether it's descending.
+	sort: Option<(i32, bool)>,
+	/// The selected row, by its number in the table, and column. It's kept while the row is scrolled out of the tree.
+	selected: Option<(usize, i32)>,
+	/// Where the first row is drawn in the tree and how tall rows are, once a row has been drawn to measure.
+	row_metrics: Option<(f32, f32)>,
+	/// The save that's running, until it finishes.
+	saving: Option<Gd<BackgroundJob>>,
+	/// What to do once the table has been saved, from [`Self::save_then`].
+	after_save: Option<Box<dyn FnOnce()>>,
+	tree: Option<Gd<Tree>>,
+	scroll: Option<Gd<VScrollBar>>,
+	title: Option<Gd<Label>>,
+	message: Option<Gd<Label>>,
+	/// For tables with checksums, the field names' checksums and how many strings don't match theirs.
+	checksum_summary: Option<Gd<Label>>,
+	undo_button: Option<Gd<Button>>,
+	redo_button: Option<Gd<Button>>,
+	save_button: Option<Gd<Button>>,
 }
 
 #[godot_api]
-impl ITree for FileViewSt {
-	fn process(&mut self, _delta: f64) {
-		self.load_strings(10);
+impl FileViewSt {
+	/// Selects a cell and scrolls to it. Row 0 holds the field names, so it can't be selected.
+	#[func]
+	fn select_cell(&mut self, row: i64, field: i64) {
+		let (Ok(row), Ok(field)) = (usize::try_from(row), usize::try_from(field)) else { return; };
+		let Some(position) = self.order.position_of(row) else { return; };
+		self.select_position(position, field_column(field), true);
 	}
 }
 
 impl FileViewSt {
-	pub fn load_stl_stuff(&mut self, source: &ItemSource) -> Result<(), Box<dyn Error>> {
-		let data = cruddy_complex_load(source)?;
-		let stuff = read_st(&mut Cursor::new(data), true)?;
+	/// Reads the table, giving up partway if `cancel` is cancelled.
+	pub fn read_st_stuff(path: &VfsPath, stl: bool, encoding: StEncoding, cancel: &CancelToken) -> formats::Result<StTable> {
+		read_st_with_encoding(&mut cancel.open(path)?, stl, encoding)
+	}
+	
+	/// `path` is where the table was read from, and where saving writes it back to.
+	pub fn load_st_stuff(&mut self, path: Option<VfsPath>, stuff: StTable) {
 		let field_count = stuff.field_count;
 		
-		self.base_mut().set_hide_root(true);
-		self.base_mut().set_column_titles_visible(true);
-		self.base_mut().set_columns(field_count as i32);
-		let _root = self.base_mut().create_item().unwrap();
+		let mut tree = Tree::new_alloc();
+		tree.set_h_size_flags(SizeFlags::EXPAND_FILL);
+		tree.set_hide_root(true);
+		tree.set_column_titles_visible(true);
+		tree.set_v_scroll_enabled(false);
+		let extended = stuff.extended.is_some();
+		tree.set_columns(field_column(field_count) + i32::from(extended));
+		tree.set_column_expand(0, false);
+		tree.set_column_custom_minimum_width(0, 60);
+		if extended {
+			tree.set_column_expand(field_column(field_count), false);
+			tree.set_column_custom_minimum_width(field_column(field_count), 90 * field_count as i32);
+		}
+		tree.create_item();
+		let mut this = self.to_gd();
+		tree.signals().item_edited().connect(move || this.bind_mut().on_cell_edited());
+		let mut this = self.to_gd();
+		tree.signals().cell_selected().connect(move || this.bind_mut().on_cell_selected());
+		let mut this = self.to_gd();
+		tree.signals().column_title_clicked().connect(move |column, button| this.bind_mut().on_column_title_clicked(column, button));
+		let mut this = self.to_gd();
+		tree.signals().gui_input().connect(move |event| this.bind_mut().on_tree_input(event));
+		let mut this = self.to_gd();
+		tree.signals().resized().connect(move || this.bind_mut().render());
 		
-		self.stuff = Some(stuff);
-		Ok(())
+		let mut scroll = VScrollBar::new_alloc();
+		scroll.set_step(1.0);
+		let mut this = self.to_gd();
+		scroll.signals().value_changed().connect(move |_| this.bind_mut().render());
+		let mut table_box = HBoxContainer::new_alloc();
+		table_box.set_v_size_flags(SizeFlags::EXPAND_FILL);
+		table_box.set_h_size_flags(SizeFlags::EXPAND_FILL);
+		table_box.add_child(&tree);
+		table_box.add_child(&scroll);
+		
+		let title = Label::new_alloc();
+		let mut message = Label::new_alloc();
+		message.set_h_size_flags(SizeFlags::EXPAND_FILL);
+		message.set_text_overrun_behavior(OverrunBehavior::TRIM_ELLIPSIS);
+		let mut go_to = LineEdit::new_alloc();
+		go_to.set_placeholder("Go to row");
+		go_to.set_custom_minimum_size(Vector2::new(100.0, 0.0));
+		let mut this = self.to_gd();
+		go_to.signals().text_submitted().connect(move |text| this.bind_mut().go_to_row(&text.to_string()));
+		let mut encoding_picker = OptionButton::new_alloc();
+		encoding_picker.set_tooltip_text("What the strings are decoded with, and what edited strings are encoded with");
+		for encoding in StEncoding::ALL {
+			encoding_picker.add_item(encoding.name());
+		}
+		encoding_picker.select(StEncoding::ALL.iter().position(|&encoding| encoding == stuff.encoding).unwrap_or(0) as i32);
+		let mut this = self.to_gd();
+		encoding_picker.signals().item_selected().connect(move |index| this.bind_mut().set_encoding(index));
+		let mut bar = HBoxContainer::new_alloc();
+		bar.add_child(&title);
+		bar.add_child(&encoding_picker);
+		bar.add_child(&go_to);
+		self.undo_button = Some(self.add_button(&mut bar, "Undo", Some(shortcut(&[(Key::Z, false)])), Self::undo));
+		self.redo_button = Some(self.add_button(&mut bar, "Redo", Some(shortcut(&[(Key::Z, true), (Key::Y, false)])), Self::redo));
+		self.add_button(&mut bar, "Add row", None, Self::add_row);
+		self.add_button(&mut bar, "Remove row", None, Self::remove_row);
+		self.save_button = Some(self.add_button(&mut bar, "Save", Some(shortcut(&[(Key::S, false)])), Self::save));
+		self.add_button(&mut bar, "Save as...", Some(shortcut(&[(Key::S, true)])), Self::choose_save_path);
+		bar.add_child(&message);
+		
+		self.base_mut().set_h_size_flags(SizeFlags::EXPAND_FILL);
+		self.base_mut().add_child(&bar);
+		match &stuff.extended {
+			None => self.base_mut().add_child(&table_box),
+			Some(extended) => {
+				let mut split = HSplitContainer::new_alloc();
+				split.set_v_size_flags(SizeFlags::EXPAND_FILL);
+				split.add_child(&table_box);
+				split.add_child(&extra_panel(extended));
+				self.base_mut().add_child(&split);
+			},
+		}
+		if stuff.extended.is_some() {
+			let mut checksum_summary = Label::new_alloc();
+			checksum_summary.set_text_overrun_behavior(OverrunBehavior::TRIM_ELLIPSIS);
+			// Just under the bar
+			self.base_mut().add_child(&checksum_summary);
+			self.base_mut().move_child(&checksum_summary, 1);
+			self.checksum_summary = Some(checksum_summary);
+		}
+		self.tree = Some(tree);
+		self.scroll = Some(scroll);
+		self.title = Some(title);
+		self.message = Some(message);
+		self.path = path;
+		self.order = StRowOrder::new(&stuff);
+		self.editor = Some(StEditor::new(stuff));
+		self.show_titles();
+		self.update_checksum_summary();
+		self.update_controls();
+		self.render();
+	}
+	
+	fn add_button(&mut self, bar: &mut Gd<HBoxContainer>, text: &str, shortcut: Option<Gd<Shortcut>>, action: fn(&mut Self)) -> Gd<Button> {
+		let mut button = Button::new_alloc();
+		button.set_text(text);
+		button.set_shortcut(shortcut.as_ref());
+		let mut this = self.to_gd();
+		button.signals().pressed().connect(move || action(&mut this.bind_mut()));
+		bar.add_child(&button);
+		button
 	}
 	
-	fn load_strings(&mut self, amount: usize) {
-		// Simple way to keep the borrow checker happy
-		let Some(stuff) = self.stuff.take() else { return; };
+	/// How many rows fit in the tree. Before a row has been measured, it's a guess.
+	fn visible_rows(&self) -> usize {
+		let Some(tree) = &self.tree else { return 1; };
+		let (top, row_height) = self.row_metrics.unwrap_or((28.0, 24.0));
+		((tree.get_size().y - top) / row_height).floor().max(1.0) as usize
+	}
+	
+	/// Makes the scroll bar cover every row, with a page being the rows that fit.
+	fn update_scroll_range(&mut self) {
+		let visible = self.visible_rows();
+		let total = self.order.len();
+		let Some(scroll) = &mut self.scroll else { return; };
+		// Changing the range can move the value, which would render in the middle of this
+		scroll.set_block_signals(true);
+		scroll.set_max(total as f64);
+		scroll.set_page(visible as f64);
+		scroll.set_block_signals(false);
+	}
+	
+	/// Fills the tree with the rows that fit, starting from the scroll bar's position.
+	fn render(&mut self) {
+		self.update_scroll_range();
+		let visible = self.visible_rows();
+		let (Some(editor), Some(tree), Some(scroll)) = (&self.editor, &mut self.tree, &self.scroll) else { return; };
+		let Some(mut root) = tree.get_root() else { return; };
+		let table = editor.table();
+		let total = self.order.len();
+		let first = (scroll.get_value() as usize).min(total.saturating_sub(visible));
+		let count = visible.min(total - first);
 		
-		let Some(mut root) = self.base().get_root() else { return; };
-		let field_count = stuff.field_count;
+		// Selecting items would send signals while this is borrowed
+		tree.set_block_signals(true);
+		tree.deselect_all();
+		let mut children = root.get_child_count() as usize;
+		while children > count {
+			children -= 1;
+			if let Some(item) = root.get_child(children as i32) {
+				item.free();
+			}
+		}
+		for i in 0..count {
+			let mut item = match root.get_child(i as i32) {
+				Some(item) => item,
+				None => root.create_child().unwrap(),
+			};
+			let row = self.order.row_at(first + i).expect("only positions in the order are shown");
+			show_row(&mut item, row, table);
+			if let Some((selected_row, column)) = self.selected && selected_row == row {
+				item.select(column);
+			}
+		}
+		tree.set_block_signals(false);
+		
+		if self.row_metrics.is_none() && let Some(item) = root.get_first_child() {
+			let rect = tree.get_item_area_rect(&item);
+			if rect.size.y > 0.0 {
+				self.row_metrics = Some((rect.position.y, rect.size.y));
+				self.render();
+			}
+		}
+	}
+	
+	/// Scrolls so the row at `position` is shown, either in the middle or just inside the edge it was past.
+	fn scroll_into_view(&mut self, position: usize, center: bool) {
+		self.update_scroll_range();
+		let visible = self.visible_rows();
+		let Some(scroll) = &mut self.scroll else { return; };
+		let first = scroll.get_value() as usize;
+		let first = if center {
+			position.saturating_sub(visible / 2)
+		} else if position < first {
+			position
+		} else if position >= first + visible {
+			position + 1 - visible
+		} else {
+			first
+		};
+		scroll.set_value_no_signal(first as f64);
+	}
+	
+	fn select_position(&mut self, position: usize, column: i32, center: bool) {
+		let Some(row) = self.order.row_at(position) else { return; };
+		self.selected = Some((row, column));
+		self.scroll_into_view(position, center);
+		self.render();
+	}
+	
+	/// Moves the selection up or down, scrolling to keep it in view.
+	fn move_selection(&mut self, delta: isize) {
+		let total = self.order.len();
+		if total == 0 { return; }
+		let current = self.selected.and_then(|(row, column)| Some((self.order.position_of(row)?, column)));
+		let (position, column) = match current {
+			Some((position, column)) => (position.saturating_add_signed(delta).min(total - 1), column),
+			None => (self.scroll.as_ref().map_or(0, |scroll| scroll.get_value() as usize), field_column(0)),
+		};
+		self.select_position(position, column, false);
+	}
+	
+	fn go_to_row(&mut self, text: &str) {
+		let row = text.trim().parse::<usize>().ok().filter(|&row| self.order.position_of(row).is_some());
+		match row {
+			Some(row) => {
+				self.set_message("");
+				self.select_cell(row as i64, 0);
+			},
+			None => self.set_message(&format!("There's no row {}.", text.trim())),
+		}
+	}
+	
+	/// Shows the strings decoded with another encoding, which is also used for the tables opened after this one.
+	/// The strings' bytes stay as they are until they're edited.
+	fn set_encoding(&mut self, index: i64) {
+		let Some(&encoding) = usize::try_from(index).ok().and_then(|index| StEncoding::ALL.get(index)) else { return; };
+		let Some(editor) = &mut self.editor else { return; };
+		editor.redecode(encoding);
+		let lossy = editor.table().strings.iter().filter(|string| string.is_lossy()).count();
+		get_autoload_by_name::<GlobalRust>("R").bind_mut().set_st_encoding(encoding);
+		
+		if lossy > 0 {
+			self.set_message(&format!("{lossy} strings aren't valid {}.", encoding.name()));
+		} else {
+			self.set_message("");
+		}
+		self.show_titles();
+		self.render();
+	}
+	
+	/// Puts the field names in the column titles, with an arrow on the one being sorted by.
+	fn show_titles(&mut self) {
+		let (Some(editor), Some(tree)) = (&self.editor, &mut self.tree) else { return; };
+		let table = editor.table();
+		let mut titles = vec!["#".to_string()];
+		titles.extend(table.rows().next().unwrap_or_default().iter().map(|string| string.text().to_string()));
+		if table.extended.is_some() {
+			titles.push("Checksums".to_string());
+		}
+		for (column, title) in titles.iter().enumerate() {
+			let arrow = match self.sort {
+				Some((sorted, false)) if sorted == column as i32 => " ▲",
+				Some((sorted, true)) if sorted == column as i32 => " ▼",
+				_ => "",
+			};
+			tree.set_column_title(column as i32, &format!("{title}{arrow}"));
+		}
+	}
+	
+	/// Clicking a column title sorts by it, then clicking again sorts the other way, then a third time goes back to the table's order.
+	fn on_column_title_clicked(&mut self, column: i64, mouse_button: i64) {
+		if mouse_button != MouseButton::LEFT.ord() as i64 { return; }
+		let column = column as i32;
+		self.sort = match self.sort {
+			Some((sorted, false)) if sorted == column => Some((column, true)),
+			Some((sorted, true)) if sorted == column => None,
+			_ => Some((column, false)),
+		};
 		
-		let mut chunks = stuff.strings
-			.chunks(field_count)
-			.skip(self.loaded_items)
-			.take(amount);
+		let Some(editor) = &self.editor else { return; };
+		let table = editor.table();
+		let field_count = table.field_count;
+		match self.sort {
+			None => self.order.reset(),
+			Some((0, descending)) => self.order.sort_by_key(|row| row, descending),
+			Some((column, descending)) if (column as usize) <= field_count => {
+				let field = column as usize - 1;
+				self.order.sort_by_key(|row| table.strings[row * field_count + field].text().to_lowercase(), descending);
+			},
+			Some((_, descending)) => self.order.sort_by_key(|row| {
+				table.extended.as_ref().map(|extended| extended.checksums[row * field_count..(row + 1) * field_count].to_vec())
+			}, descending),
+		}
 		
-		if self.loaded_items == 0 {
-			let chunk = chunks.next().unwrap_or_default();
-			for (j, text) in chunk.into_iter().enumerate() {
-				self.base_mut().set_column_title(j as i32, text);
+		self.show_titles();
+		// Keep the selection in view, or start from the top
+		match self.selected.and_then(|(row, _)| self.order.position_of(row)) {
+			Some(position) => self.scroll_into_view(position, true),
+			None => self.scroll_into_view(0, false),
+		}
+		self.render();
+	}
+	
+	/// The tree can't scroll by itself, since it only has the rows that fit, so scrolling and moving the selection are done here.
+	fn on_tree_input(&mut self, event: Gd<InputEvent>) {
+		let handled = if let Ok(button) = event.clone().try_cast::<InputEventMouseButton>() {
+			let step = match button.get_button_index() {
+				_ if !button.is_pressed() || button.is_shift_pressed() => None,
+				MouseButton::WHEEL_UP => Some(-3),
+				MouseButton::WHEEL_DOWN => Some(3),
+				_ => None,
+			};
+			if let Some(step) = step && let Some(scroll) = &mut self.scroll {
+				let value = scroll.get_value() + f64::from(step);
+				scroll.set_value_no_signal(value);
+				self.render();
+			}
+			step.is_some()
+		} else {
+			let page = self.visible_rows() as isize;
+			let moves = [("ui_up", -1), ("ui_down", 1), ("ui_page_up", -page), ("ui_page_down", page)];
+			let delta = moves.into_iter().find(|(action, _)| event.is_action_pressed_ex(*action).allow_echo(true).done());
+			if let Some((_, delta)) = delta {
+				self.move_selection(delta);
 			}
-			self.loaded_items += 1;
+			delta.is_some()
+		};
+		if handled && let Some(tree) = &mut self.tree {
+			tree.accept_event();
+		}
+	}
+	
+	fn on_cell_selected(&mut self) {
+		let Some(tree) = &self.tree else { return; };
+		let Some(item) = tree.get_selected() else { return; };
+		let Ok(row) = item.get_metadata(0).try_to::<u64>() else { return; };
+		self.selected = Some((row as usize, tree.get_selected_column()));
+	}
+	
+	fn set_message(&mut self, text: &str) {
+		if let Some(message) = &mut self.message {
+			message.set_text(text);
+		}
+	}
+	
+	/// Marks the file name if there are unsaved changes, and only enables what can be done.
+	fn update_controls(&mut self) {
+		let Some(editor) = &self.editor else { return; };
+		let name = self.path.as_ref().map_or_else(|| "Untitled".to_string(), |path| path.name().into_owned());
+		let dirty = if editor.is_dirty() { "*" } else { "" };
+		let (can_undo, can_redo) = (editor.can_undo(), editor.can_redo());
+		if let Some(title) = &mut self.title {
+			title.set_text(&format!("{name}{dirty} ({} rows)", self.order.len()));
+			title.set_tooltip_text(&self.path.as_ref().map_or_else(String::new, VfsPath::to_string));
+		}
+		if let Some(button) = &mut self.undo_button {
+			button.set_disabled(!can_undo);
+		}
+		if let Some(button) = &mut self.redo_button {
+			button.set_disabled(!can_redo);
 		}
+		let saving = self.saving.is_some();
+		if let Some(button) = &mut self.save_button {
+			button.set_disabled(saving);
+		}
+	}
+	
+	fn on_cell_edited(&mut self) {
+		let Some(tree) = &self.tree else { return; };
+		let (Some(item), column) = (tree.get_edited(), tree.get_edited_column()) else { return; };
+		let Ok(row) = item.get_metadata(0).try_to::<u64>() else { return; };
+		let Some(field) = (column as usize).checked_sub(1) else { return; };
+		let Some(editor) = &mut self.editor else { return; };
+		let index = row as usize * editor.table().field_count + field;
 		
-		for chunk in chunks {
-			let mut item = root.create_child().unwrap();
-			for (j, text) in chunk.into_iter().enumerate() {
-				item.set_text(j as i32, text);
+		match editor.set_text(index, item.get_text(column).to_string()) {
+			Ok(change) => {
+				self.set_message("");
+				if let Some(change) = change {
+					self.show_change(change);
+				}
+			},
+			Err(e) => {
+				self.set_message(&format!("This text can't be stored: {e}"));
+				// Put back what's really in the table
+				self.render();
+			},
+		}
+		self.update_controls();
+	}
+	
+	/// Updates the order, selection and tree after the table changed.
+	fn show_change(&mut self, change: StChange) {
+		self.order.apply(change);
+		if let Some((row, column)) = self.selected {
+			self.selected = match change {
+				StChange::RowInserted(inserted) if row >= inserted => Some((row + 1, column)),
+				StChange::RowRemoved(removed) if row == removed => None,
+				StChange::RowRemoved(removed) if row > removed => Some((row - 1, column)),
+				_ => Some((row, column)),
+			};
+		}
+		let field_count = self.editor.as_ref().map_or(0, |editor| editor.table().field_count);
+		if let StChange::Cell(index) = change && index < field_count {
+			self.show_titles();
+		}
+		self.update_checksum_summary();
+		self.render();
+	}
+	
+	/// Shows the checksums of the field names, since they're only column titles here, and counts the strings that don't
+	/// match their checksums. That count stays up while editing, since the stored checksums are kept as they are.
+	fn update_checksum_summary(&mut self) {
+		let Some(editor) = &self.editor else { return; };
+		let table = editor.table();
+		let Some(extended) = &table.extended else { return; };
+		let header: Vec<String> = extended.checksums.iter().take(table.field_count).map(|checksum| format!("{checksum:08X}")).collect();
+		let mismatches = verify_checksums(table).len();
+		let total = table.strings.len();
+		
+		let summary = if mismatches == 0 {
+			"Every string matches its checksum.".to_string()
+		} else if mismatches * 2 <= total {
+			format!("{mismatches} of {total} strings don't match their checksums, and are marked.")
+		} else {
+			// The checksum is only a guess, so this is worth knowing when judging it
+			format!("{mismatches} of {total} strings don't match their checksums, and are marked. \
+				That's most of them, so the checksum algorithm is probably wrong rather than the table.")
+		};
+		let text = format!("Field name checksums: {}. {summary}", header.join(" "));
+		if let Some(label) = &mut self.checksum_summary {
+			label.set_text(&text);
+			label.set_tooltip_text(&text);
+		}
+	}
+	
+	/// Shows what was undone or redone, and selects it.
+	fn show_undo_redo(&mut self, change: Option<StChange>) {
+		let Some(change) = change else { return; };
+		self.show_change(change);
+		self.set_message("");
+		self.update_controls();
+		let field_count = self.editor.as_ref().map_or(1, |editor| editor.table().field_count.max(1));
+		match change {
+			StChange::Cell(index) => self.select_cell((index / field_count) as i64, (index % field_count) as i64),
+			StChange::RowInserted(row) => self.select_cell(row as i64, 0),
+			StChange::RowRemoved(_) => {},
+		}
+	}
+	
+	fn undo(&mut self) {
+		let change = self.editor.as_mut().and_then(StEditor::undo);
+		self.show_undo_redo(change);
+	}
+	
+	fn redo(&mut self) {
+		let change = self.editor.as_mut().and_then(StEditor::redo);
+		self.show_undo_redo(change);
+	}
+	
+	/// Adds an empty row after the selected one, or at the end if nothing is selected.
+	fn add_row(&mut self) {
+		let selected = self.selected.map(|(row, _)| row);
+		let Some(editor) = &mut self.editor else { return; };
+		let row = selected.map_or(editor.table().entry_count(), |row| row + 1);
+		let change = editor.insert_row(row);
+		self.show_change(change);
+		self.update_controls();
+		self.select_cell(row as i64, 0);
+	}
+	
+	/// Removes the selected row, and selects the one shown after it, so several can be removed in a row.
+	fn remove_row(&mut self) {
+		let Some((row, column)) = self.selected else {
+			self.set_message("Select a row to remove first.");
+			return;
+		};
+		let position = self.order.position_of(row);
+		let Some(editor) = &mut self.editor else { return; };
+		let change = editor.remove_row(row);
+		self.show_change(change);
+		if let Some(position) = position {
+			let position = position.min(self.order.len().saturating_sub(1));
+			if self.order.row_at(position).is_some() {
+				self.select_position(position, column, false);
 			}
-			self.loaded_items += 1;
 		}
+		self.update_controls();
+	}
+	
+	pub fn is_dirty(&self) -> bool {
+		self.editor.as_ref().is_some_and(StEditor::is_dirty)
+	}
+	
+	/// Saves like the Save button, then runs `then` if everything was saved. It's dropped if saving fails or is cancelled.
+	pub fn save_then(&mut self, then: impl FnOnce() + 'static) {
+		self.after_save = Some(Box::new(then));
+		self.save();
+	}
+	
+	/// Saves back to where the table came from. Tables inside archives are saved by rebuilding the archives around them.
+	fn save(&mut self) {
+		match self.path.clone() {
+			Some(path) => self.save_to(path),
+			None => self.choose_save_path(),
+		}
+	}
+	
+	fn choose_save_path(&mut self) {
+		let Some(editor) = &self.editor else { return; };
+		let filter = if editor.table().extended.is_some() { "*.stm, *.stb" } else { "*.stl" };
+		let mut dialog = FileDialog::new_alloc();
+		dialog.set_file_mode(FileMode::SAVE_FILE);
+		dialog.set_access(Access::FILESYSTEM);
+		dialog.set_use_native_dialog(true);
+		dialog.set_title("Save string table as...");
+		dialog.set_filters(&PackedArray::from(&[GString::from(filter)]));
+		if let Some(path) = &self.path {
+			dialog.set_current_file(path.name().as_ref());
+		}
+		
+		let mut this = self.to_gd();
+		let mut dialog_to_free = dialog.clone();
+		dialog.signals().file_selected().connect(move |path| {
+			dialog_to_free.queue_free();
+			this.bind_mut().save_to(VfsPath::disk(path.to_string()));
+		});
+		let mut dialog_to_free = dialog.clone();
+		let mut this = self.to_gd();
+		dialog.signals().canceled().connect(move || {
+			dialog_to_free.queue_free();
+			this.bind_mut().after_save = None;
+		});
+		
+		self.base_mut().add_child(&dialog);
+		dialog.popup_centered();
+	}
+	
+	/// Writes the table on another thread, since rebuilding a big archive takes a while. Editing can carry on meanwhile.
+	fn save_to(&mut self, path: VfsPath) {
+		if self.saving.is_some() { return; }
+		let Some(editor) = &self.editor else { return; };
+		let mut writer = Cursor::new(Vec::new());
+		if let Err(e) = write_st(&mut writer, editor.table()) {
+			self.show_save_error(&path, &e.to_string());
+			return;
+		}
+		let state = editor.state();
 		
-		// Simple way to keep the borrow checker happy, cont
-		self.stuff = Some(stuff)
+		let data = writer.into_inner();
+		let target = path.clone();
+		let mut this = self.to_gd();
+		let job = BackgroundJob::run_fallible(
+			move |_cancel| vfs().write(&target, data),
+			move |result| this.bind_mut().on_saved(path, state, result),
+		);
+		self.base_mut().add_child(&job);
+		self.saving = Some(job);
+		self.set_message("Saving...");
+		self.update_controls();
+	}
+	
+	fn on_saved(&mut self, path: VfsPath, state: StEditState, result: Result<(), String>) {
+		self.saving = None;
+		match result {
+			Ok(()) => {
+				if let Some(editor) = &mut self.editor {
+					editor.mark_saved(state);
+				}
+				self.set_message(&format!("Saved to {path}"));
+				self.path = Some(path);
+			},
+			Err(e) => self.show_save_error(&path, &e),
+		}
+		self.update_controls();
+		
+		// Edits made while saving weren't saved, so going ahead would lose them
+		if let Some(then) = self.after_save.take() && !self.is_dirty() {
+			then();
+		}
+	}
+	
+	fn show_save_error(&mut self, path: &VfsPath, error: &str) {
+		self.after_save = None;
+		self.set_message("");
+		let mut dialog = AcceptDialog::new_alloc();
+		dialog.set_title("Save");
+		dialog.set_text(&format!("Couldn't save {path}:\n{error}"));
+		let mut dialog_to_free = dialog.clone();
+		dialog.signals().confirmed().connect(move || dialog_to_free.queue_free());
+		let mut dialog_to_free = dialog.clone();
+		dialog.signals().canceled().connect(move || dialog_to_free.queue_free());
+		self.base_mut().add_child(&dialog);
+		dialog.popup_centered();
 	}
 }
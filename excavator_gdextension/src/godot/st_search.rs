@@ -0,0 +1,206 @@
+use godot::prelude::*;
+use godot::classes::{Button, CheckBox, HBoxContainer, IVBoxContainer, Label, LineEdit, Tree, VBoxContainer};
+use godot::classes::control::{MouseFilter, SizeFlags};
+use godot::classes::text_server::AutowrapMode;
+use godot::tools::get_autoload_by_name;
+
+use std::sync::Arc;
+
+use crate::filesystem::vfs;
+use crate::formats::st::search::{StIndex, StMatch, StQuery};
+use crate::formats::vfs::{Vfs, VfsItem, VfsItemType};
+use crate::godot::autoload::GlobalRust;
+use crate::godot::background_job::{BackgroundJob, JobPanicked};
+use crate::godot::browser_tree::ItemInfo;
+
+/// Like in the browser's filter, more results than this make the tree slow.
+const MAX_SHOWN_RESULTS: usize = 2000;
+
+/// Searches the text of every string table in the opened directory, and opens the table at a result when it's clicked.
+///
+/// Reading every table takes a while, so it's only done for the first search, then kept until another directory is opened
+/// or another encoding is picked in a table's view.
+#[derive(GodotClass)]
+#[class(init, base=VBoxContainer)]
+pub struct StSearchPanel {
+	base: Base<VBoxContainer>,
+	index: Option<Arc<StIndex>>,
+	/// The search that's running, until it finishes.
+	searching: Option<Gd<BackgroundJob>>,
+	/// What the results tree shows. Its items hold indexes into this.
+	results: Vec<StMatch>,
+	query: Option<Gd<LineEdit>>,
+	regex: Option<Gd<CheckBox>>,
+	status: Option<Gd<Label>>,
+	tree: Option<Gd<Tree>>,
+}
+
+#[godot_api]
+impl IVBoxContainer for StSearchPanel {
+	fn ready(&mut self) {
+		let r = get_autoload_by_name::<GlobalRust>("R");
+		r.signals().directory_opened().connect_other(&*self, Self::forget_index);
+		
+		let mut query = LineEdit::new_alloc();
+		query.set_placeholder("Text in any string table");
+		query.set_clear_button_enabled(true);
+		query.set_h_size_flags(SizeFlags::EXPAND_FILL);
+		let mut this = self.to_gd();
+		query.signals().text_submitted().connect(move |_| this.bind_mut().search());
+		let mut regex = CheckBox::new_alloc();
+		regex.set_text("Regex");
+		regex.set_tooltip_text("Treat the text as a regular expression. Case matters unless it starts with (?i)");
+		let mut button = Button::new_alloc();
+		button.set_text("Search");
+		let mut this = self.to_gd();
+		button.signals().pressed().connect(move || this.bind_mut().search());
+		let mut bar = HBoxContainer::new_alloc();
+		bar.add_child(&query);
+		bar.add_child(&regex);
+		bar.add_child(&button);
+		
+		let mut status = Label::new_alloc();
+		status.set_autowrap_mode(AutowrapMode::WORD_SMART);
+		status.set_mouse_filter(MouseFilter::PASS);
+		
+		let mut tree = Tree::new_alloc();
+		tree.set_v_size_flags(SizeFlags::EXPAND_FILL);
+		tree.set_hide_root(true);
+		tree.set_column_titles_visible(true);
+		tree.set_columns(4);
+		for (column, title) in ["Text", "Table", "Row", "Field"].into_iter().enumerate() {
+			tree.set_column_title(column as i32, title);
+		}
+		tree.set_column_expand(2, false);
+		tree.set_column_custom_minimum_width(2, 60);
+		let mut this = self.to_gd();
+		tree.signals().item_selected().connect(move || this.bind_mut().on_result_selected());
+		
+		self.base_mut().add_child(&bar);
+		self.base_mut().add_child(&status);
+		self.base_mut().add_child(&tree);
+		self.query = Some(query);
+		self.regex = Some(regex);
+		self.status = Some(status);
+		self.tree = Some(tree);
+	}
+}
+
+#[godot_api]
+impl StSearchPanel {
+	/// Asks for a table to be opened with a cell selected. `row` counts the row of field names as 0.
+	#[signal]
+	fn cell_requested(item_info: Gd<ItemInfo>, row: i64, field: i64);
+}
+
+impl StSearchPanel {
+	fn set_status(&mut self, text: &str, tooltip: &str) {
+		if let Some(status) = &mut self.status {
+			status.set_text(text);
+			status.set_tooltip_text(tooltip);
+		}
+	}
+	
+	/// The tables that were read belong to the directory that was open before.
+	fn forget_index(&mut self, _path: GString) {
+		self.cancel_search();
+		self.index = None;
+		self.results.clear();
+		if let Some(tree) = &mut self.tree {
+			tree.clear();
+		}
+		self.set_status("", "");
+	}
+	
+	fn cancel_search(&mut self) {
+		if let Some(mut job) = self.searching.take() && job.is_instance_valid() {
+			job.bind_mut().cancel();
+		}
+	}
+	
+	fn search(&mut self) {
+		let Some(text) = self.query.as_ref().map(|query| query.get_text().to_string()) else { return; };
+		if text.is_empty() { return; }
+		let is_regex = self.regex.as_ref().is_some_and(|regex| regex.is_pressed());
+		let query = if is_regex {
+			match StQuery::regex(&text) {
+				Ok(query) => query,
+				Err(e) => {
+					self.set_status(&format!("This isn't a valid regular expression:\n{e}"), "");
+					return;
+				},
+			}
+		} else {
+			StQuery::substring(&text)
+		};
+		
+		self.cancel_search();
+		let encoding = get_autoload_by_name::<GlobalRust>("R").bind().st_encoding();
+		let index = self.index.clone().filter(|index| index.encoding() == encoding);
+		self.set_status(if index.is_some() { "Searching..." } else { "Reading every string table..." }, "");
+		let mut this = self.to_gd();
+		let job = BackgroundJob::run(
+			move |cancel| {
+				let index = index.unwrap_or_else(|| {
+					Arc::new(StIndex::build(vfs(), &vfs().mounts(), encoding, cancel.keep_going()))
+				});
+				let results = index.search(&query);
+				(index, results)
+			},
+			move |found| this.bind_mut().show_results(found),
+		);
+		self.base_mut().add_child(&job);
+		self.searching = Some(job);
+	}
+	
+	fn show_results(&mut self, found: Result<(Arc<StIndex>, Vec<StMatch>), JobPanicked>) {
+		self.searching = None;
+		let Some(mut tree) = self.tree.clone() else { return; };
+		tree.clear();
+		let mut root = tree.create_item().unwrap();
+		let (index, results) = match found {
+			Ok(found) => found,
+			Err(e) => {
+				self.set_status(&format!("Searching stopped: {e}."), "");
+				return;
+			},
+		};
+		
+		for (i, found) in results.iter().take(MAX_SHOWN_RESULTS).enumerate() {
+			let table = &index.tables()[found.table];
+			let mut item = root.create_child().unwrap();
+			item.set_text(0, table.text(found.row, found.field).unwrap_or_default());
+			item.set_text(1, table.path.name().as_ref());
+			item.set_tooltip_text(1, &table.path.to_string());
+			item.set_text(2, &found.row.to_string());
+			item.set_text(3, &table.field_name(found.field).map_or_else(|| found.field.to_string(), str::to_string));
+			item.set_metadata(0, &(i as u64).to_variant());
+		}
+		
+		let mut status = match results.len() {
+			0 => format!("No matches in {} tables.", index.tables().len()),
+			count if count > MAX_SHOWN_RESULTS => {
+				format!("Showing the first {MAX_SHOWN_RESULTS} of {count} matches in {} tables.", index.tables().len())
+			},
+			count => format!("{count} matches in {} tables.", index.tables().len()),
+		};
+		let errors: Vec<String> = index.errors().iter().map(|(path, e)| format!("{path}: {e}")).collect();
+		if !errors.is_empty() {
+			status.push_str(&format!(" {} places couldn't be searched.", errors.len()));
+		}
+		self.set_status(&status, &errors.join("\n"));
+		self.results = results;
+		self.index = Some(index);
+	}
+	
+	fn on_result_selected(&mut self) {
+		let Some(item) = self.tree.as_ref().and_then(|tree| tree.get_selected()) else { return; };
+		let Ok(i) = item.get_metadata(0).try_to::<u64>() else { return; };
+		let (Some(found), Some(index)) = (self.results.get(i as usize), &self.index) else { return; };
+		
+		let path = index.tables()[found.table].path.clone();
+		let info = ItemInfo::from_item(VfsItem { path, item_type: VfsItemType::File, size: None, archive_entry: None });
+		let (row, field) = (found.row as i64, found.field as i64);
+		self.signals().cell_requested().emit(&info, row, field);
+	}
+}